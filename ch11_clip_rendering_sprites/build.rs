@@ -0,0 +1,89 @@
+// Generates the SpriteName enum from the sprite jsons of data/ (the files with a "spritesheet"
+// and a "sprites" array), so that the enum can't get out of sync with the metadata:
+// a name used in the code but missing from the jsons is a compile error.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[path = "src/sprite_names.rs"]
+mod sprite_names;
+use sprite_names::check_sprite_name;
+
+fn collect_jsons(dir: &Path, files: &mut Vec<PathBuf>)
+{
+  let Ok(entries) = fs::read_dir(dir) else { return; };
+  for path in entries.filter_map(|e| e.ok().map(|e| e.path())) {
+    if path.is_dir() {
+      collect_jsons(&path, files);
+    }
+    else if path.extension().is_some_and(|ext| ext == "json") {
+      files.push(path);
+    }
+  }
+}
+
+fn main()
+{
+  println!("cargo:rerun-if-changed=data");
+
+  let mut files = Vec::new();
+  collect_jsons(Path::new("data"), &mut files);
+  files.sort(); // reproducible enum order
+
+  let mut names: Vec<String> = Vec::new();
+  for file in &files
+  {
+    println!("cargo:rerun-if-changed={}", file.display());
+    let text = fs::read_to_string(file).unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+    let json: serde_json::Value = match serde_json::from_str(&text) {
+      Ok(json) => json,
+      Err(e) => { println!("cargo:warning={}: not valid json, ignored ({})", file.display(), e); continue; }
+    };
+    let (Some(_), Some(sprites)) = (json.get("spritesheet"), json.get("sprites").and_then(|s| s.as_array())) else {
+      continue; // not sprite metadata
+    };
+
+    for sprite in sprites
+    {
+      let name = sprite.get("name").and_then(|n| n.as_str())
+        .unwrap_or_else(|| panic!("{}: sprite without a \"name\" string", file.display()));
+      if let Err(e) = check_sprite_name(name) {
+        panic!("{}: bad sprite name, {}", file.display(), e);
+      }
+      if !names.iter().any(|n| n == name) {
+        names.push(name.to_string());
+      }
+    }
+  }
+
+  if names.is_empty() {
+    println!("cargo:warning=no sprite found in the jsons of data/: SpriteName has no variant");
+  }
+
+  let variants: String = names.iter().map(|n| format!("  {},\n", n)).collect();
+  let all: String = names.iter().map(|n| format!("SpriteName::{}, ", n)).collect();
+  let strs: String = names.iter().map(|n| format!("      SpriteName::{} => \"{}\",\n", n, n)).collect();
+  let code = format!(
+"#[allow(clippy::enum_variant_names)] // these are the names used in the json
+#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum SpriteName
+{{
+{variants}}}
+
+impl SpriteName
+{{
+  pub const ALL: [SpriteName; {count}] = [{all}];
+
+  pub fn as_str(&self) -> &'static str
+  {{
+    match *self // (compiles with no variant too, unlike `match self`)
+    {{
+{strs}    }}
+  }}
+}}
+", count = names.len());
+
+  let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("sprite_name.rs");
+  fs::write(out, code).unwrap();
+}
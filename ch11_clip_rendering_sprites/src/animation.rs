@@ -0,0 +1,299 @@
+// Frame-based animations, declared in the sprite json next to the sprites they use:
+//
+// "animations": [
+//   { "name": "Blink", "mode": "ping_pong", "frames": [ { "sprite": "RedCircle", "duration": 100 }, ... ] }
+// ]
+//
+// durations are in milliseconds, mode is "loop" (default), "ping_pong" or "once".
+
+use crate::sprite::{RenderEx, Sprite, SpriteKey, SpriteName};
+
+use sdl2::render::WindowCanvas;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimationMode
+{
+  #[default]
+  Loop,
+  PingPong, // 0 1 2 1 0 1 2 ...
+  Once      // stays on the last frame
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct FrameData<N = SpriteName>
+{
+  pub(crate) sprite: N,
+  pub(crate) duration: u32
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AnimationData<N = SpriteName>
+{
+  pub(crate) name: String,
+  #[serde(default)]
+  pub(crate) mode: AnimationMode,
+  pub(crate) frames: Vec<FrameData<N>>
+}
+
+impl<N> AnimationData<N>
+{
+  pub fn name(&self) -> &str { &self.name }
+}
+
+// The sequence of frames over one cycle (ping pong already unfolded), independent of SDL.
+#[derive(Debug, Clone)]
+pub struct Timeline
+{
+  steps: Vec<(usize, u32)>, // (frame index, duration in ms)
+  total: u32,               // duration of one cycle
+  mode: AnimationMode
+}
+
+impl Timeline
+{
+  pub fn new(durations: &[u32], mode: AnimationMode) -> Result<Timeline, String>
+  {
+    if durations.is_empty() {
+      return Err("an animation needs at least one frame".to_string());
+    }
+    if durations.contains(&0) {
+      return Err("frame durations must be positive".to_string());
+    }
+
+    let mut steps: Vec<(usize, u32)> = durations.iter().copied().enumerate().collect();
+    if mode == AnimationMode::PingPong && durations.len() > 2 {
+      // back to the first frame, without repeating both ends
+      steps.extend((1..durations.len() - 1).rev().map(|i| (i, durations[i])));
+    }
+    let total = steps.iter().map(|s| s.1).sum();
+    Ok(Timeline { steps, total, mode })
+  }
+
+  pub fn total(&self) -> u32 { self.total }
+  pub fn mode(&self) -> AnimationMode { self.mode }
+
+  // Frame shown at `position` ms, position being within [0, total]
+  pub fn frame_at(&self, position: u32) -> usize
+  {
+    let mut start = 0;
+    for &(frame, duration) in &self.steps {
+      if position < start + duration {
+        return frame;
+      }
+      start += duration;
+    }
+    self.steps.last().unwrap().0 // position == total, only happens with Once
+  }
+
+  // The position dt after `position`, wrapped or stopped at the end. Kept as a Duration, not in
+  // ms: the sub-millisecond parts of the frames add up (at 60 fps, or more).
+  pub fn advance(&self, position: Duration, dt: Duration) -> (Duration, Option<AnimationEvent>)
+  {
+    let total = Duration::from_millis(self.total as u64);
+    let position = position + dt;
+    if position < total {
+      (position, None)
+    }
+    else if self.mode == AnimationMode::Once {
+      (total, Some(AnimationEvent::Finished))
+    }
+    else {
+      (Duration::from_nanos((position.as_nanos() % total.as_nanos()) as u64), Some(AnimationEvent::Looped))
+    }
+  }
+}
+
+pub struct AnimationClip<'a, N = SpriteName>
+{
+  name: String,
+  frames: Vec<Sprite<'a, N>>,
+  timeline: Timeline
+}
+
+impl<'a, N> AnimationClip<'a, N>
+{
+  pub fn new(name: &str, frames: Vec<(Sprite<'a, N>, u32)>, mode: AnimationMode) -> Result<AnimationClip<'a, N>, String>
+  {
+    let durations: Vec<u32> = frames.iter().map(|f| f.1).collect();
+    let timeline = Timeline::new(&durations, mode).map_err(|e| format!("animation {}: {}", name, e))?;
+    Ok(AnimationClip { name: name.to_string(), frames: frames.into_iter().map(|f| f.0).collect(), timeline })
+  }
+
+  pub fn name(&self) -> &str { &self.name }
+  pub fn duration(&self) -> Duration { Duration::from_millis(self.timeline.total() as u64) }
+  pub fn frame_count(&self) -> usize { self.frames.len() }
+}
+
+// Builds the clips of a sheet from their json description and the sheet's sprites
+pub fn create_animations<'a, N: SpriteKey>(animations: &[AnimationData<N>], sprites: &HashMap<N, Sprite<'a, N>>)
+  -> Result<HashMap<String, Rc<AnimationClip<'a, N>>>, String>
+{
+  let mut clips = HashMap::new();
+  for data in animations
+  {
+    let mut frames = Vec::with_capacity(data.frames.len());
+    for frame in &data.frames {
+      let sprite = sprites.get(&frame.sprite)
+        .ok_or_else(|| format!("animation {}: unknown sprite {:?}", data.name, frame.sprite))?;
+      frames.push((sprite.clone(), frame.duration));
+    }
+    let clip = AnimationClip::new(&data.name, frames, data.mode)?;
+    if clips.insert(data.name.clone(), Rc::new(clip)).is_some() {
+      return Err(format!("animation {} is defined twice", data.name));
+    }
+  }
+  Ok(clips)
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AnimationEvent
+{
+  Looped,  // a Loop or PingPong clip started over
+  Finished // a Once clip reached its end
+}
+
+// Plays a clip: several AnimatedSprites can share the same clip, each at its own position.
+pub struct AnimatedSprite<'a, N = SpriteName>
+{
+  clip: Rc<AnimationClip<'a, N>>,
+  position: Duration, // into the current cycle
+  playing: bool,
+  finished: bool
+}
+
+impl<'a, N> AnimatedSprite<'a, N>
+{
+  pub fn new(clip: Rc<AnimationClip<'a, N>>) -> AnimatedSprite<'a, N>
+  {
+    AnimatedSprite { clip, position: Duration::ZERO, playing: true, finished: false }
+  }
+
+  pub fn clip(&self) -> &AnimationClip<'a, N> { &self.clip }
+
+  // Switches to another clip, from its start
+  pub fn set_clip(&mut self, clip: Rc<AnimationClip<'a, N>>)
+  {
+    self.clip = clip;
+    self.seek(Duration::ZERO);
+  }
+
+  // Resumes, or restarts if the clip had finished
+  pub fn play(&mut self)
+  {
+    if self.finished {
+      self.seek(Duration::ZERO);
+    }
+    self.playing = true;
+  }
+
+  pub fn pause(&mut self) { self.playing = false; }
+  pub fn is_playing(&self) -> bool { self.playing }
+  pub fn is_finished(&self) -> bool { self.finished }
+
+  pub fn position(&self) -> Duration { self.position }
+
+  // Jumps to a time within the cycle (clamped for Once clips, wrapped for the others)
+  pub fn seek(&mut self, to: Duration)
+  {
+    let total = self.clip.duration();
+    self.position = if self.clip.timeline.mode() == AnimationMode::Once { to.min(total) }
+      else { Duration::from_nanos((to.as_nanos() % total.as_nanos()) as u64) };
+    self.finished = self.clip.timeline.mode() == AnimationMode::Once && self.position == total;
+  }
+
+  // Advances the animation, to be called every frame with the elapsed time
+  pub fn update(&mut self, dt: Duration) -> Option<AnimationEvent>
+  {
+    if !self.playing || self.finished {
+      return None;
+    }
+
+    let (position, event) = self.clip.timeline.advance(self.position, dt);
+    self.position = position;
+    if event == Some(AnimationEvent::Finished) {
+      self.finished = true;
+      self.playing = false;
+    }
+    event
+  }
+
+  pub fn current_frame(&self) -> usize { self.clip.timeline.frame_at(self.position.as_millis() as u32) }
+  pub fn current_sprite(&self) -> &Sprite<'a, N> { &self.clip.frames[self.current_frame()] }
+
+  pub fn render(&self, canvas: &mut WindowCanvas, x: i32, y: i32)
+  {
+    self.current_sprite().render(canvas, x, y);
+  }
+
+  pub fn render_ex(&self, canvas: &mut WindowCanvas, x: i32, y: i32, params: &RenderEx)
+  {
+    self.current_sprite().render_ex(canvas, x, y, params);
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_loop_timeline() {
+    let timeline = Timeline::new(&[100, 50, 100], AnimationMode::Loop).unwrap();
+    assert_eq!(timeline.total(), 250);
+    assert_eq!(timeline.frame_at(0), 0);
+    assert_eq!(timeline.frame_at(99), 0);
+    assert_eq!(timeline.frame_at(100), 1);
+    assert_eq!(timeline.frame_at(160), 2);
+  }
+
+  #[test]
+  fn test_ping_pong_timeline() {
+    let timeline = Timeline::new(&[10, 10, 10, 10], AnimationMode::PingPong).unwrap();
+    // 0 1 2 3 2 1
+    assert_eq!(timeline.total(), 60);
+    let frames: Vec<usize> = (0..6).map(|i| timeline.frame_at(i * 10)).collect();
+    assert_eq!(frames, vec![0, 1, 2, 3, 2, 1]);
+  }
+
+  #[test]
+  fn test_once_timeline_ends_on_last_frame() {
+    let timeline = Timeline::new(&[10, 20], AnimationMode::Once).unwrap();
+    assert_eq!(timeline.frame_at(timeline.total()), 1);
+  }
+
+  #[test]
+  fn test_advance_keeps_sub_milliseconds() {
+    let timeline = Timeline::new(&[10, 10], AnimationMode::Loop).unwrap();
+    // 20 steps of 0.5 ms: a whole frame
+    let mut position = Duration::ZERO;
+    for _ in 0..20 {
+      position = timeline.advance(position, Duration::from_micros(500)).0;
+    }
+    assert_eq!((position, timeline.frame_at(position.as_millis() as u32)), (Duration::from_millis(10), 1));
+    let mut events = Vec::new();
+    for _ in 0..21 {
+      let (next, event) = timeline.advance(position, Duration::from_micros(500));
+      position = next;
+      events.extend(event);
+    }
+    assert_eq!((position, events), (Duration::from_micros(500), vec![AnimationEvent::Looped]));
+
+    let once = Timeline::new(&[10], AnimationMode::Once).unwrap();
+    assert_eq!(once.advance(Duration::from_micros(9500), Duration::from_micros(16667)),
+      (Duration::from_millis(10), Some(AnimationEvent::Finished)));
+  }
+
+  #[test]
+  fn test_invalid_timelines() {
+    assert!(Timeline::new(&[], AnimationMode::Loop).is_err());
+    assert!(Timeline::new(&[10, 0], AnimationMode::Loop).is_err());
+  }
+}
@@ -0,0 +1,73 @@
+// Asset lookup. By default assets are read from the disk, relative to the working directory.
+// With the "embed-assets" feature, the whole data/ directory is baked into the executable
+// and looked up with the very same paths (e.g. "data/meta.json"), so callers don't change.
+
+use sdl2::surface::Surface;
+use std::borrow::Cow;
+
+#[cfg(feature = "embed-assets")]
+static DATA_DIR: include_dir::Dir<'static> = include_dir::include_dir!("$CARGO_MANIFEST_DIR/data");
+
+#[cfg(feature = "embed-assets")]
+fn embedded_file(path: &str) -> Result<&'static [u8], String>
+{
+  let normalized = path.replace('\\', "/");
+  let relative = normalized.trim_start_matches("./").strip_prefix("data/")
+    .ok_or_else(|| format!("{}: embedded assets must live under data/", path))?;
+  DATA_DIR.get_file(relative)
+    .map(|f| f.contents())
+    .ok_or_else(|| format!("{}: no such embedded asset", path))
+}
+
+// Raw bytes of an asset (json, images, ...)
+#[cfg(feature = "embed-assets")]
+pub fn read_asset(path: &str) -> Result<Cow<'static, [u8]>, String>
+{
+  embedded_file(path).map(Cow::Borrowed)
+}
+
+#[cfg(not(feature = "embed-assets"))]
+pub fn read_asset(path: &str) -> Result<Cow<'static, [u8]>, String>
+{
+  std::fs::read(path)
+    .map(Cow::Owned)
+    .map_err(|e| format!("{}: {}", path, e))
+}
+
+// Image assets go through a Surface (and not straight to a Texture) so that the caller
+// can still set a color key before the conversion.
+#[cfg(feature = "embed-assets")]
+pub fn load_surface(path: &str) -> Result<Surface<'static>, String>
+{
+  use sdl2::image::ImageRWops;
+  use sdl2::rwops::RWops;
+  // The bytes are 'static, so is the RWops reading them: no copy is made.
+  RWops::from_bytes(embedded_file(path)?)?.load()
+}
+
+#[cfg(not(feature = "embed-assets"))]
+pub fn load_surface(path: &str) -> Result<Surface<'static>, String>
+{
+  use sdl2::image::LoadSurface;
+  let path = std::path::Path::new(path);
+  if !path.is_file() {
+    return Err("no such file".to_string());
+  }
+  Surface::from_file(path)
+}
+
+// Fonts (ttf feature), same lookup
+#[cfg(all(feature = "ttf", feature = "embed-assets"))]
+pub fn load_font<'ttf>(context: &'ttf sdl2::ttf::Sdl2TtfContext, path: &str, point_size: u16)
+  -> Result<sdl2::ttf::Font<'ttf, 'static>, String>
+{
+  use sdl2::rwops::RWops;
+  context.load_font_from_rwops(RWops::from_bytes(embedded_file(path)?)?, point_size)
+}
+
+#[cfg(all(feature = "ttf", not(feature = "embed-assets")))]
+pub fn load_font<'ttf>(context: &'ttf sdl2::ttf::Sdl2TtfContext, path: &str, point_size: u16)
+  -> Result<sdl2::ttf::Font<'ttf, 'static>, String>
+{
+  context.load_font(path, point_size)
+}
@@ -0,0 +1,337 @@
+// Texture atlas packing: a folder of PNGs becomes one spritesheet + its json (same format
+// as data/meta.json), so that nobody has to type pixel coordinates by hand anymore.
+//
+// The packing itself is a "MaxRects" algorithm (best short side fit), which is simple and
+// gives tight sheets. It is independent of SDL so it can be unit tested.
+
+use sdl2::image::{LoadSurface, SaveSurface};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+use sdl2::surface::Surface;
+
+use crate::sprite::{SpriteData, SpriteSheetData};
+use crate::sprite_names::check_sprite_name;
+
+use std::path::Path;
+
+pub struct AtlasOptions
+{
+  pub padding: u32,       // transparent pixels between two sprites
+  pub power_of_two: bool, // force the sheet dimensions to powers of two
+  pub max_size: u32       // the sheet won't grow beyond max_size x max_size
+}
+
+impl Default for AtlasOptions
+{
+  fn default() -> AtlasOptions
+  {
+    AtlasOptions { padding: 1, power_of_two: false, max_size: 4096 }
+  }
+}
+
+// Result of the packing: the sheet dimensions and the position of every input rectangle,
+// in the same order as the input.
+#[derive(Debug)]
+pub struct PackedLayout
+{
+  pub width: u32,
+  pub height: u32,
+  pub positions: Vec<(u32, u32)>
+}
+
+// Own rectangle type: sdl2's Rect can't be empty (it clamps w and h to 1),
+// and empty free rectangles do happen while splitting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PackRect
+{
+  x: u32,
+  y: u32,
+  w: u32,
+  h: u32
+}
+
+impl PackRect
+{
+  fn right(&self) -> u32 { self.x + self.w }
+  fn bottom(&self) -> u32 { self.y + self.h }
+
+  fn intersects(&self, other: &PackRect) -> bool
+  {
+    self.x < other.right() && other.x < self.right() && self.y < other.bottom() && other.y < self.bottom()
+  }
+
+  fn contains(&self, other: &PackRect) -> bool
+  {
+    other.x >= self.x && other.y >= self.y && other.right() <= self.right() && other.bottom() <= self.bottom()
+  }
+}
+
+struct MaxRectsBin
+{
+  free_rects: Vec<PackRect>
+}
+
+impl MaxRectsBin
+{
+  fn new(width: u32, height: u32) -> MaxRectsBin
+  {
+    MaxRectsBin { free_rects: vec![PackRect { x: 0, y: 0, w: width, h: height }] }
+  }
+
+  fn insert(&mut self, w: u32, h: u32) -> Option<(u32, u32)>
+  {
+    // Best short side fit: the free rectangle leaving the smallest leftover on its shortest side
+    let best = self.free_rects.iter()
+      .filter(|f| f.w >= w && f.h >= h)
+      .min_by_key(|f| {
+        let (dw, dh) = (f.w - w, f.h - h);
+        (dw.min(dh), dw.max(dh))
+      })
+      .copied()?;
+
+    let placed = PackRect { x: best.x, y: best.y, w, h };
+    self.split_free_rects(&placed);
+    self.prune_free_rects();
+    Some((placed.x, placed.y))
+  }
+
+  // Every free rectangle overlapping the placed one is replaced by (up to) 4 maximal rectangles around it
+  fn split_free_rects(&mut self, placed: &PackRect)
+  {
+    let mut new_rects = Vec::new();
+    self.free_rects.retain(|free| {
+      if !free.intersects(placed) {
+        return true;
+      }
+      if placed.x > free.x {
+        new_rects.push(PackRect { w: placed.x - free.x, ..*free });
+      }
+      if placed.right() < free.right() {
+        new_rects.push(PackRect { x: placed.right(), w: free.right() - placed.right(), ..*free });
+      }
+      if placed.y > free.y {
+        new_rects.push(PackRect { h: placed.y - free.y, ..*free });
+      }
+      if placed.bottom() < free.bottom() {
+        new_rects.push(PackRect { y: placed.bottom(), h: free.bottom() - placed.bottom(), ..*free });
+      }
+      false
+    });
+    self.free_rects.append(&mut new_rects);
+  }
+
+  // Free rectangles contained in another one are useless
+  fn prune_free_rects(&mut self)
+  {
+    let rects = std::mem::take(&mut self.free_rects);
+    for (i, r) in rects.iter().enumerate() {
+      let redundant = rects.iter().enumerate().any(|(j, other)| {
+        i != j && other.contains(r) && (other != r || j < i) // keep one of two identical rects
+      });
+      if !redundant {
+        self.free_rects.push(*r);
+      }
+    }
+  }
+}
+
+fn try_pack(sizes: &[(u32, u32)], order: &[usize], bin_w: u32, bin_h: u32) -> Option<Vec<(u32, u32)>>
+{
+  let mut bin = MaxRectsBin::new(bin_w, bin_h);
+  let mut positions = vec![(0, 0); sizes.len()];
+  for &i in order {
+    positions[i] = bin.insert(sizes[i].0, sizes[i].1)?;
+  }
+  Some(positions)
+}
+
+fn grow(side: u32, power_of_two: bool) -> u32
+{
+  if power_of_two { side * 2 } else { side + (side / 8).max(1) }
+}
+
+// Packs rectangles of the given sizes in the smallest sheet found, growing it until everything fits.
+pub fn pack(sizes: &[(u32, u32)], options: &AtlasOptions) -> Result<PackedLayout, String>
+{
+  if sizes.is_empty() {
+    return Err("nothing to pack".to_string());
+  }
+  if sizes.iter().any(|&(w, h)| w == 0 || h == 0) {
+    return Err("cannot pack an empty image".to_string());
+  }
+
+  // Padding is added on the right and bottom sides of every rectangle, and the bin is
+  // enlarged by the same amount: this way there's padding between sprites only, not on the sheet borders.
+  let pad = options.padding;
+  let padded: Vec<(u32, u32)> = sizes.iter().map(|&(w, h)| (w + pad, h + pad)).collect();
+
+  // Big rectangles first, it's the usual heuristic for this family of algorithms
+  let mut order: Vec<usize> = (0..sizes.len()).collect();
+  order.sort_by_key(|&i| std::cmp::Reverse((sizes[i].0.max(sizes[i].1), sizes[i].0 * sizes[i].1)));
+
+  let area: u64 = sizes.iter().map(|&(w, h)| u64::from(w) * u64::from(h)).sum();
+  let side = (area as f64).sqrt().ceil() as u32;
+  let mut width = side.max(sizes.iter().map(|s| s.0).max().unwrap());
+  let mut height = side.max(sizes.iter().map(|s| s.1).max().unwrap());
+  if options.power_of_two {
+    width = width.next_power_of_two();
+    height = height.next_power_of_two();
+  }
+  // With power_of_two, the sheet can't go past the largest power of two below max_size
+  let max_size = match options.power_of_two
+  {
+    true if options.max_size > 0 => 1 << (31 - options.max_size.leading_zeros()),
+    _ => options.max_size
+  };
+
+  loop
+  {
+    if width > max_size || height > max_size {
+      return Err(format!("sprites don't fit in a {}x{} sheet", options.max_size, options.max_size));
+    }
+
+    if let Some(positions) = try_pack(&padded, &order, width + pad, height + pad)
+    {
+      if !options.power_of_two {
+        // The sheet only needs to be as large as what was actually used
+        width = positions.iter().zip(sizes).map(|(p, s)| p.0 + s.0).max().unwrap();
+        height = positions.iter().zip(sizes).map(|(p, s)| p.1 + s.1).max().unwrap();
+      }
+      return Ok(PackedLayout { width, height, positions });
+    }
+
+    // Grow the smallest side, without jumping over max_size
+    if width <= height {
+      width = grow(width, options.power_of_two).min(max_size.max(width + 1));
+    }
+    else {
+      height = grow(height, options.power_of_two).min(max_size.max(height + 1));
+    }
+  }
+}
+
+pub struct Atlas
+{
+  pub sheet: Surface<'static>,
+  pub sprites: Vec<(String, Rect)>
+}
+
+// Loads every PNG of input_dir (sorted by file name, for reproducible sheets) and blits them into one sheet
+pub fn build_atlas(input_dir: &Path, options: &AtlasOptions) -> Result<Atlas, String>
+{
+  let mut paths: Vec<_> = std::fs::read_dir(input_dir)
+    .map_err(|e| format!("{}: {}", input_dir.display(), e))?
+    .filter_map(|entry| entry.ok().map(|e| e.path()))
+    .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")))
+    .collect();
+  paths.sort();
+
+  // the names become SpriteName variants: checked before loading anything
+  let mut names = Vec::with_capacity(paths.len());
+  for path in &paths
+  {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    check_sprite_name(&name).map_err(|e| format!("{}: {}", path.display(), e))?;
+    names.push(name);
+  }
+
+  let mut images = Vec::with_capacity(paths.len());
+  for (path, name) in paths.iter().zip(names)
+  {
+    let surface = Surface::from_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    images.push((name, surface));
+  }
+
+  let sizes: Vec<(u32, u32)> = images.iter().map(|(_, s)| (s.width(), s.height())).collect();
+  let layout = pack(&sizes, options)?;
+
+  // A fresh surface is zeroed, i.e. fully transparent
+  let mut sheet = Surface::new(layout.width, layout.height, PixelFormatEnum::RGBA32)?;
+  let mut sprites = Vec::with_capacity(images.len());
+  for ((name, mut surface), (x, y)) in images.into_iter().zip(layout.positions)
+  {
+    let dest = Rect::new(x as i32, y as i32, surface.width(), surface.height());
+    surface.set_blend_mode(BlendMode::None)?; // copy the alpha channel as is, don't blend it
+    surface.blit(None, &mut sheet, dest)?;
+    sprites.push((name, dest));
+  }
+
+  Ok(Atlas { sheet, sprites })
+}
+
+// Writes the sheet as PNG and its json. sheet_path_in_json is the path that load_texture
+// will be given at runtime (e.g. "data/sprites.png"), which is not necessarily png_path.
+pub fn save_atlas(atlas: &Atlas, png_path: &Path, json_path: &Path, sheet_path_in_json: &str) -> Result<(), String>
+{
+  atlas.sheet.save(png_path)?;
+
+  // free-form names (the image file stems): the packer doesn't know the SpriteName enum
+  let mut data = SpriteSheetData::<String>::new(sheet_path_in_json);
+  data.sprites_mut().extend(atlas.sprites.iter().map(|(name, rect)| SpriteData::new(name.clone(), *rect)));
+  data.save_json(json_path)
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn assert_valid(sizes: &[(u32, u32)], layout: &PackedLayout, padding: u32)
+  {
+    let rects: Vec<PackRect> = layout.positions.iter().zip(sizes)
+      .map(|(&(x, y), &(w, h))| PackRect { x, y, w: w + padding, h: h + padding })
+      .collect();
+    for (i, r) in rects.iter().enumerate() {
+      assert!(r.right() - padding <= layout.width && r.bottom() - padding <= layout.height, "Out of the sheet!");
+      for other in &rects[i + 1..] {
+        assert!(!r.intersects(other), "Overlapping sprites!");
+      }
+    }
+  }
+
+  #[test]
+  fn test_bad_file_name() {
+    let dir = std::env::temp_dir().join(format!("atlas_names_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("red circle.png"), b"").unwrap();
+    let result = build_atlas(&dir, &AtlasOptions::default());
+    std::fs::remove_dir_all(&dir).unwrap();
+    let err = result.err().unwrap();
+    assert!(err.contains("red circle.png: ") && err.contains("isn't made of letters"), "{}", err);
+  }
+
+  #[test]
+  fn test_pack_without_overlap() {
+    let sizes = [(100, 100), (100, 100), (50, 200), (30, 30), (64, 16), (16, 64), (1, 1)];
+    let layout = pack(&sizes, &AtlasOptions { padding: 0, ..Default::default() }).unwrap();
+    assert_valid(&sizes, &layout, 0);
+  }
+
+  #[test]
+  fn test_pack_with_padding() {
+    let sizes = [(10, 10); 9];
+    let layout = pack(&sizes, &AtlasOptions { padding: 2, ..Default::default() }).unwrap();
+    assert_valid(&sizes, &layout, 2);
+    // 3x3 sprites of 10 px and 2 px between them
+    assert_eq!((layout.width, layout.height), (34, 34));
+  }
+
+  #[test]
+  fn test_pack_power_of_two() {
+    let sizes = [(100, 30), (70, 70), (20, 90)];
+    let layout = pack(&sizes, &AtlasOptions { power_of_two: true, ..Default::default() }).unwrap();
+    assert_valid(&sizes, &layout, 1);
+    assert!(layout.width.is_power_of_two() && layout.height.is_power_of_two());
+    // they'd fit in 3000x3000, but 3000 isn't a power of two: 2048 at most
+    assert!(pack(&[(1500, 1100); 2], &AtlasOptions { power_of_two: true, max_size: 3000, ..Default::default() }).is_err());
+  }
+
+  #[test]
+  fn test_pack_too_big() {
+    let result = pack(&[(300, 300), (300, 300)], &AtlasOptions { max_size: 512, ..Default::default() });
+    assert!(result.is_err());
+  }
+}
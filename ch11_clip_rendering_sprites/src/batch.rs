@@ -0,0 +1,207 @@
+// Sprite batch: the draws of a frame are collected with a layer and a z value instead of being
+// submitted in call order. At flush time they are sorted (stably: equal keys keep the call order),
+// draws of the same texture being grouped when the order allows it, to cut texture switches.
+
+use crate::sprite::{rects_overlap, RenderEx, Sprite, SpriteName};
+
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatchStats
+{
+  pub draws: usize,
+  pub texture_switches: usize
+}
+
+struct DrawCommand<'a, N>
+{
+  sprite: Sprite<'a, N>, // cheap clone, the texture is shared
+  x: i32,
+  y: i32,
+  params: Option<RenderEx>,
+  key: SortKey
+}
+
+// Layer first, then z within the layer. The texture (only compared for equality) and the bounds
+// decide which draws of the same depth can be moved next to each other.
+#[derive(Debug, Clone, Copy)]
+struct SortKey
+{
+  layer: i32,
+  z: f32,
+  texture_id: usize,
+  bounds: Rect
+}
+
+fn compare_depth(a: &SortKey, b: &SortKey) -> std::cmp::Ordering
+{
+  a.layer.cmp(&b.layer).then(a.z.total_cmp(&b.z))
+}
+
+// Indices of the draws in drawing order: sorted by depth (stably), then within a depth, a draw
+// moves up behind the previous one of its texture if it overlaps none of the draws it jumps over.
+// Overlapping draws keep the call order, so what's on top never depends on the textures.
+fn draw_order(keys: &[SortKey]) -> Vec<usize>
+{
+  let mut sorted: Vec<usize> = (0..keys.len()).collect();
+  sorted.sort_by(|&a, &b| compare_depth(&keys[a], &keys[b]));
+
+  let mut order: Vec<usize> = Vec::with_capacity(keys.len());
+  let mut depth_start = 0;
+  for i in sorted {
+    let key = &keys[i];
+    if order.last().is_some_and(|&last| compare_depth(&keys[last], key).is_ne()) {
+      depth_start = order.len();
+    }
+    let mut position = order.len();
+    for p in (depth_start..order.len()).rev() {
+      let other = &keys[order[p]];
+      if other.texture_id == key.texture_id {
+        position = p + 1;
+        break;
+      }
+      if rects_overlap(other.bounds, key.bounds) {
+        break;
+      }
+    }
+    order.insert(position, i);
+  }
+  order
+}
+
+// Where a draw can land (more than that when rotated), cf. Sprite::render_ex
+fn draw_bounds<N>(sprite: &Sprite<N>, x: i32, y: i32, params: Option<&RenderEx>) -> Rect
+{
+  let (w, h) = sprite.size();
+  let Some(params) = params else { return Rect::new(x, y, w, h); };
+  let (sx, sy) = (params.scale.0.abs() as f64, params.scale.1.abs() as f64);
+  let (w, h) = (w as f64 * sx, h as f64 * sy);
+  if params.angle % 360.0 == 0.0 {
+    return Rect::new(x, y, w.ceil() as u32, h.ceil() as u32);
+  }
+  // any angle: the square around the rotation center reaching the farthest corner
+  let center = params.center.map(|c| (c.x() as f64, c.y() as f64))
+    .or_else(|| sprite.pivot().map(|p| (p.x() as f64 * sx, p.y() as f64 * sy)))
+    .unwrap_or((w / 2.0, h / 2.0));
+  let radius = center.0.max(w - center.0).hypot(center.1.max(h - center.1)).ceil();
+  let size = 2 * radius as u32 + 1;
+  Rect::new((x as f64 + center.0 - radius).floor() as i32, (y as f64 + center.1 - radius).floor() as i32, size, size)
+}
+
+// Number of texture changes when drawing in this order (the first bind counts)
+fn count_switches(texture_ids: impl Iterator<Item = usize>) -> usize
+{
+  let mut previous = None;
+  texture_ids.filter(|&id| previous.replace(id) != Some(id)).count()
+}
+
+pub struct SpriteBatch<'a, N = SpriteName>
+{
+  commands: Vec<DrawCommand<'a, N>>,
+  stats: BatchStats
+}
+
+impl<'a, N: Clone> Default for SpriteBatch<'a, N>
+{
+  fn default() -> SpriteBatch<'a, N>
+  {
+    SpriteBatch::new()
+  }
+}
+
+impl<'a, N: Clone> SpriteBatch<'a, N>
+{
+  pub fn new() -> SpriteBatch<'a, N>
+  {
+    SpriteBatch { commands: Vec::new(), stats: BatchStats::default() }
+  }
+
+  pub fn draw(&mut self, sprite: &Sprite<'a, N>, x: i32, y: i32, layer: i32, z: f32)
+  {
+    self.push(sprite, x, y, None, layer, z);
+  }
+
+  pub fn draw_ex(&mut self, sprite: &Sprite<'a, N>, x: i32, y: i32, params: &RenderEx, layer: i32, z: f32)
+  {
+    self.push(sprite, x, y, Some(*params), layer, z);
+  }
+
+  fn push(&mut self, sprite: &Sprite<'a, N>, x: i32, y: i32, params: Option<RenderEx>, layer: i32, z: f32)
+  {
+    let bounds = draw_bounds(sprite, x, y, params.as_ref());
+    let key = SortKey { layer, z, texture_id: Rc::as_ptr(sprite.texture()) as usize, bounds };
+    self.commands.push(DrawCommand { sprite: sprite.clone(), x, y, params, key });
+  }
+
+  // Number of draws waiting for the flush
+  pub fn len(&self) -> usize { self.commands.len() }
+  pub fn is_empty(&self) -> bool { self.commands.is_empty() }
+
+  // Sorts and submits everything to the canvas, and empties the batch for the next frame
+  pub fn flush(&mut self, canvas: &mut WindowCanvas) -> BatchStats
+  {
+    let keys: Vec<SortKey> = self.commands.iter().map(|c| c.key).collect();
+    let order = draw_order(&keys);
+
+    for command in order.iter().map(|&i| &self.commands[i]) {
+      match &command.params {
+        None => command.sprite.render(canvas, command.x, command.y),
+        Some(params) => command.sprite.render_ex(canvas, command.x, command.y, params)
+      }
+    }
+
+    self.stats = BatchStats {
+      draws: self.commands.len(),
+      texture_switches: count_switches(order.iter().map(|&i| keys[i].texture_id))
+    };
+    self.commands.clear(); // (keeps the allocation)
+    self.stats
+  }
+
+  // Stats of the last flush
+  pub fn stats(&self) -> BatchStats { self.stats }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  // (layer, z, texture, x): 10x10 draws, at x on the same line
+  fn sorted(draws: &[(i32, f32, usize, i32)]) -> Vec<usize> {
+    let keys: Vec<SortKey> = draws.iter()
+      .map(|&(layer, z, texture_id, x)| SortKey { layer, z, texture_id, bounds: Rect::new(x, 0, 10, 10) })
+      .collect();
+    draw_order(&keys)
+  }
+
+  #[test]
+  fn test_layers_then_z() {
+    let order = sorted(&[(1, 0.0, 1, 0), (0, 5.0, 1, 0), (0, -1.0, 1, 0), (1, -3.0, 1, 0)]);
+    assert_eq!(order, vec![2, 1, 3, 0]);
+  }
+
+  #[test]
+  fn test_textures_grouped_within_same_depth() {
+    // same layer and z, apart: A B A B gives A A B B, and the call order is kept per texture
+    let draws = [(0, 0.0, 7, 0), (0, 0.0, 3, 20), (0, 0.0, 7, 40), (0, 0.0, 3, 60)];
+    assert_eq!(sorted(&draws), vec![0, 2, 1, 3]);
+    assert_eq!(count_switches(draws.iter().map(|d| d.2)), 4);
+    assert_eq!(count_switches(sorted(&draws).iter().map(|&i| draws[i].2)), 2);
+    // whatever the texture addresses: the other way around gives the same order
+    let swapped = draws.map(|(layer, z, texture, x)| (layer, z, 10 - texture, x));
+    assert_eq!(sorted(&swapped), vec![0, 2, 1, 3]);
+  }
+
+  #[test]
+  fn test_overlapping_draws_keep_the_call_order() {
+    // the second A is over B: it can't go before it, the last B can still join the first one
+    let draws = [(0, 0.0, 7, 0), (0, 0.0, 3, 20), (0, 0.0, 7, 25), (0, 0.0, 3, 60)];
+    assert_eq!(sorted(&draws), vec![0, 1, 3, 2]);
+  }
+}
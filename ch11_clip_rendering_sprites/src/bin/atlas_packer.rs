@@ -0,0 +1,51 @@
+// Packs a folder of PNGs into one spritesheet + the json describing it (cf. data/meta.json).
+//
+// cargo run --bin atlas_packer -- <input_dir> <output.png> <output.json> [--padding N] [--pot] [--max-size N] [--sheet-path P]
+//
+// --sheet-path is the spritesheet path written in the json, i.e. the one given to the TextureManager
+// at runtime (defaults to output.png as typed on the command line).
+// Sprites are named after the file stems: RedCircle.png gives a "RedCircle" sprite.
+
+use ch11_clip_rendering_sprites::atlas::{build_atlas, save_atlas, AtlasOptions};
+
+use std::path::Path;
+
+const USAGE: &str = "usage: atlas_packer <input_dir> <output.png> <output.json> [--padding N] [--pot] [--max-size N] [--sheet-path P]";
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String>
+{
+  value.and_then(|v| v.parse().ok())
+    .ok_or_else(|| format!("{} expects a number\n{}", flag, USAGE))
+}
+
+fn main() -> Result<(), String>
+{
+  let mut options = AtlasOptions::default();
+  let mut positional = Vec::new();
+  let mut sheet_path = None;
+
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next()
+  {
+    match arg.as_str()
+    {
+      "--padding" => { options.padding = parse_value("--padding", args.next())?; },
+      "--max-size" => { options.max_size = parse_value("--max-size", args.next())?; },
+      "--pot" => { options.power_of_two = true; },
+      "--sheet-path" => { sheet_path = Some(args.next().ok_or(USAGE)?); },
+      _ if arg.starts_with("--") => { return Err(format!("unknown option {}\n{}", arg, USAGE)); },
+      _ => { positional.push(arg); }
+    }
+  }
+
+  let [input_dir, png_path, json_path] = <[String; 3]>::try_from(positional)
+    .map_err(|_| USAGE.to_string())?;
+  let sheet_path = sheet_path.unwrap_or_else(|| png_path.clone());
+
+  let atlas = build_atlas(Path::new(&input_dir), &options)?;
+  save_atlas(&atlas, Path::new(&png_path), Path::new(&json_path), &sheet_path)?;
+
+  println!("{} sprites packed in a {}x{} sheet: {}, {}",
+    atlas.sprites.len(), atlas.sheet.width(), atlas.sheet.height(), png_path, json_path);
+  Ok(())
+}
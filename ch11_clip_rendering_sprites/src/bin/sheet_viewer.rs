@@ -0,0 +1,180 @@
+// Shows a spritesheet with the rects of its json outlined and labelled, to check the metadata.
+//
+// cargo run --bin sheet_viewer -- data/meta.json
+//
+// Mouse wheel: zoom around the pointer, drag: pan, F: fit the window, L: labels on/off, Escape: quit.
+// Hovering a sprite shows its name, rect and issues; the rects out of the sheet, overlapping
+// another one or with the name of another one are red (also listed in the console at start).
+
+use ch11_clip_rendering_sprites::camera::Camera2D;
+use ch11_clip_rendering_sprites::debug_text::{debug_text_size, draw_debug_label};
+use ch11_clip_rendering_sprites::sprite::{try_load_sprites_from_json_as, SheetIssue, SpriteSheetData};
+use ch11_clip_rendering_sprites::texture::{ColorKey, TextureManager};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::image::InitFlag;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, WindowCanvas};
+
+const USAGE: &str = "usage: sheet_viewer <sprites.json>";
+
+const OK_COLOR: Color = Color::RGB(0x00, 0xE0, 0x40);
+const ISSUE_COLOR: Color = Color::RGB(0xFF, 0x20, 0x20);
+const HOVER_COLOR: Color = Color::RGB(0xFF, 0xE0, 0x00);
+const LABEL_BACKGROUND: Color = Color::RGBA(0x00, 0x00, 0x00, 0xB0);
+
+fn describe(issue: SheetIssue, sheet: &SpriteSheetData<String>) -> String
+{
+  let name = |i: usize| &sheet.sprites()[i].name()[..];
+  match issue
+  {
+    SheetIssue::OutOfBounds(i) => format!("{} is out of the sheet", name(i)),
+    SheetIssue::Overlap(i, j) => format!("{} overlaps {}", name(i), name(j)),
+    SheetIssue::DuplicateName(i, j) => format!("sprites {} and {} are both named {}", i, j, name(i))
+  }
+}
+
+fn involves(issue: SheetIssue, index: usize) -> bool
+{
+  match issue
+  {
+    SheetIssue::OutOfBounds(i) => i == index,
+    SheetIssue::Overlap(i, j) | SheetIssue::DuplicateName(i, j) => i == index || j == index
+  }
+}
+
+fn draw_cross(canvas: &mut WindowCanvas, center: Point, size: i32) -> Result<(), String>
+{
+  canvas.draw_line(center.offset(-size, 0), center.offset(size, 0))?;
+  canvas.draw_line(center.offset(0, -size), center.offset(0, size))
+}
+
+fn main() -> Result<(), String>
+{
+  let json_path = std::env::args().nth(1).ok_or(USAGE)?;
+  let sheet = try_load_sprites_from_json_as::<String>(&json_path)?;
+
+  let sdl_context = sdl2::init()?;
+  sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0"); // zoomed pixels stay square
+  let _image_context = sdl2::image::init(InitFlag::PNG)?;
+  let video_subsystem = sdl_context.video()?;
+  let window = video_subsystem.window("sheet_viewer", 1000, 700)
+    .position_centered().resizable().build()
+    .map_err(|e| e.to_string())?;
+  let mut canvas = window.into_canvas().present_vsync().build().map_err(|e| e.to_string())?;
+  canvas.set_blend_mode(BlendMode::Blend);
+  let mut event_pump = sdl_context.event_pump()?;
+
+  // Keyed like in the game
+  let texture_creator = canvas.texture_creator();
+  let mut texture_manager = TextureManager::new();
+  let region_keys: Vec<(Rect, ColorKey)> = sheet.sprites().iter()
+    .filter_map(|data| data.color_key().map(|key| (data.rect(), key)))
+    .collect();
+  let texture = texture_manager.try_load_texture_with_keys(&texture_creator, sheet.spritesheet(), sheet.color_key(), &region_keys)?;
+  let query = texture.borrow().query();
+  let sheet_size = (query.width, query.height);
+
+  let issues = sheet.validate(sheet_size);
+  println!("{}: {} sprites in a {}x{} sheet, {} issue(s)", json_path, sheet.sprites().len(), sheet_size.0, sheet_size.1, issues.len());
+  for &issue in &issues {
+    println!("  {}", describe(issue, &sheet));
+  }
+
+  let mut camera = Camera2D::from_canvas(&canvas);
+  camera.fit(Rect::new(0, 0, sheet_size.0, sheet_size.1), 0.1);
+  let mut mouse = Point::new(0, 0);
+  let mut dragging = false;
+  let mut show_labels = true;
+  let mut title = String::new();
+
+  'viewer: loop
+  {
+    for event in event_pump.poll_iter()
+    {
+      match event
+      {
+        Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => { break 'viewer; },
+        Event::KeyDown { keycode: Some(Keycode::F), .. } => { camera.fit(Rect::new(0, 0, sheet_size.0, sheet_size.1), 0.1); },
+        Event::KeyDown { keycode: Some(Keycode::L), .. } => { show_labels = !show_labels; },
+        Event::Window { win_event: WindowEvent::SizeChanged(w, h), .. } =>
+        {
+          camera.set_viewport(Rect::new(0, 0, w.max(1) as u32, h.max(1) as u32));
+        },
+        Event::MouseButtonDown { mouse_btn: MouseButton::Left | MouseButton::Middle | MouseButton::Right, .. } => { dragging = true; },
+        Event::MouseButtonUp {..} => { dragging = false; },
+        Event::MouseMotion { x, y, xrel, yrel, .. } =>
+        {
+          mouse = Point::new(x, y);
+          if dragging {
+            camera.move_by(-xrel as f32 / camera.zoom(), -yrel as f32 / camera.zoom());
+          }
+        },
+        Event::MouseWheel { y, .. } if y != 0 =>
+        {
+          camera.zoom_at(mouse, (camera.zoom() * 1.25f32.powi(y)).clamp(0.05, 64.0));
+        },
+        _ => {}
+      }
+    }
+
+    let pixel = camera.screen_to_world(mouse).map(|(x, y)| Point::new(x.floor() as i32, y.floor() as i32));
+    let hovered = pixel.and_then(|p| sheet.sprites().iter().rposition(|data| data.rect().contains_point(p)));
+
+    canvas.set_draw_color(Color::RGB(0x30, 0x30, 0x30));
+    canvas.clear();
+    let sheet_rect = camera.world_rect(Rect::new(0, 0, sheet_size.0, sheet_size.1));
+    canvas.set_draw_color(Color::RGB(0x50, 0x50, 0x50));
+    canvas.fill_rect(sheet_rect)?;
+    canvas.copy(&texture.borrow(), None, sheet_rect)?;
+
+    for (i, data) in sheet.sprites().iter().enumerate() {
+      let rect = camera.world_rect(data.rect());
+      let color = if hovered == Some(i) { HOVER_COLOR }
+        else if issues.iter().any(|&issue| involves(issue, i)) { ISSUE_COLOR }
+        else { OK_COLOR };
+      canvas.set_draw_color(color);
+      canvas.draw_rect(rect)?;
+      if let Some(pivot) = data.pivot() {
+        let (x, y) = camera.world_to_viewport((data.rect().x() + pivot.x()) as f32, (data.rect().y() + pivot.y()) as f32);
+        draw_cross(&mut canvas, Point::new(x as i32, y as i32), 4)?;
+      }
+      if show_labels {
+        draw_debug_label(&mut canvas, data.name(), rect.x(), rect.y(), 2, color, LABEL_BACKGROUND)?;
+      }
+    }
+
+    // Info about what's under the mouse, at the bottom left
+    let mut info = Vec::new();
+    if let Some(p) = pixel {
+      info.push(format!("pixel {}, {}", p.x(), p.y()));
+    }
+    if let Some(i) = hovered {
+      let data = &sheet.sprites()[i];
+      let rect = data.rect();
+      info.push(format!("{}: {}, {} {}x{}{}", data.name(), rect.x(), rect.y(), rect.width(), rect.height(),
+        if data.is_rotated() { " rotated" } else { "" }));
+      info.extend(issues.iter().filter(|&&issue| involves(issue, i)).map(|&issue| describe(issue, &sheet)));
+    }
+    if !info.is_empty() {
+      let text = info.join("\n");
+      let (_, h) = debug_text_size(&text, 2);
+      let bottom = camera.viewport().height() as i32;
+      draw_debug_label(&mut canvas, &text, 8, bottom - h as i32 - 12, 2, Color::WHITE, LABEL_BACKGROUND)?;
+    }
+
+    let new_title = format!("sheet_viewer - {} - {} sprites, {} issue(s) - {:.0}%",
+      json_path, sheet.sprites().len(), issues.len(), camera.zoom() * 100.0);
+    if new_title != title {
+      canvas.window_mut().set_title(&new_title).map_err(|e| e.to_string())?;
+      title = new_title;
+    }
+
+    canvas.present();
+  }
+
+  Ok(())
+}
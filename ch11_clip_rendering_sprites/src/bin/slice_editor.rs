@@ -0,0 +1,463 @@
+// Cuts a spritesheet into sprites with the mouse, and writes the json (cf. data/meta.json).
+//
+// cargo run --bin slice_editor -- art/sheet.png [output.json]   (a new json, sheet.json by default)
+// cargo run --bin slice_editor -- data/meta.json [output.json]  (edits it: pivots, animations... are kept)
+//
+// Left drag on the sheet: a new rect, named right away (Enter: ok, Escape: keep the given name).
+// Left drag on a rect: moves it, by its bottom right corner: resizes it. Right/middle drag: pan,
+// mouse wheel: zoom. Arrows move the selected rect by a pixel (with Shift: resize it), Tab selects
+// the next one, Enter renames it (the animations follow), Delete removes it (unless an animation uses it).
+// G: snap to the grid ([ and ] change its size), A: snap to the visible pixels (alpha bounding box).
+// Ctrl+Z / Ctrl+Y: undo / redo, Ctrl+S: save, F: fit the window.
+
+use ch11_clip_rendering_sprites::assets::load_surface;
+use ch11_clip_rendering_sprites::camera::Camera2D;
+use ch11_clip_rendering_sprites::debug_text::{debug_text_size, draw_debug_label};
+use ch11_clip_rendering_sprites::slicing::{alpha_bounds, rect_from_corners, snap_to_grid, unique_name, EditHistory};
+use ch11_clip_rendering_sprites::sprite::{try_load_sprites_from_json_as, SpriteData, SpriteSheetData};
+use ch11_clip_rendering_sprites::sprite_names::check_sprite_name;
+use ch11_clip_rendering_sprites::texture::{apply_color_keys, ColorKey};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::image::InitFlag;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::BlendMode;
+use sdl2::surface::Surface;
+
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "usage: slice_editor <sheet.png | sprites.json> [output.json]";
+
+const RECT_COLOR: Color = Color::RGB(0x00, 0xE0, 0x40);
+const SELECTED_COLOR: Color = Color::RGB(0xFF, 0xE0, 0x00);
+const NEW_RECT_COLOR: Color = Color::RGB(0x00, 0xC0, 0xFF);
+const GRID_COLOR: Color = Color::RGBA(0xFF, 0xFF, 0xFF, 0x30);
+const LABEL_BACKGROUND: Color = Color::RGBA(0x00, 0x00, 0x00, 0xB0);
+const HANDLE_SIZE: i32 = 8; // screen pixels
+
+type Snapshot = SpriteSheetData<String>; // the whole sheet, animations included
+
+enum Drag
+{
+  Create { start: Point, current: Point },
+  Move { index: usize, grab: Point, before: Snapshot },
+  Resize { index: usize, before: Snapshot },
+  Pan
+}
+
+struct Editor
+{
+  sheet: SpriteSheetData<String>,
+  pixels: Surface<'static>, // RGBA32, keyed, for the alpha snapping
+  output: PathBuf,
+  history: EditHistory<Snapshot>,
+  selected: Option<usize>,
+  naming: Option<String>, // the name being typed for the selected sprite
+  grid: u32,
+  grid_snap: bool,
+  alpha_snap: bool,
+  modified: bool,
+  message: String
+}
+
+impl Editor
+{
+  fn sprites(&self) -> &[SpriteData<String>] { self.sheet.sprites() }
+
+  // Saves the state for undo, before a change
+  fn record(&mut self)
+  {
+    let before = self.sheet.clone();
+    self.history.record(before);
+    self.modified = true;
+  }
+
+  fn snapped(&self, rect: Rect) -> Rect
+  {
+    let mut rect = rect;
+    if self.grid_snap {
+      rect = snap_to_grid(rect, self.grid);
+    }
+    if self.alpha_snap {
+      let (pitch, size) = (self.pixels.pitch() as usize, self.pixels.size());
+      if let Some(visible) = self.pixels.with_lock(|pixels| alpha_bounds(pixels, pitch, size, rect)) {
+        rect = visible;
+      }
+    }
+    rect
+  }
+
+  fn add(&mut self, rect: Rect)
+  {
+    self.record();
+    let name = unique_name("Sprite", self.sprites().iter().map(|data| &data.name()[..]));
+    self.sheet.sprites_mut().push(SpriteData::new(name, rect));
+    self.selected = Some(self.sprites().len() - 1);
+    self.naming = Some(String::new());
+  }
+
+  fn set_rect(&mut self, index: usize, rect: Rect)
+  {
+    self.sheet.sprites_mut()[index].set_rect(rect);
+  }
+
+  fn delete_selected(&mut self)
+  {
+    let Some(index) = self.selected else { return; };
+    // the animations would point to nothing
+    let used_by = self.sheet.animations_using(self.sprites()[index].name());
+    if !used_by.is_empty() {
+      self.message = format!("can't delete {}: used by {}", self.sprites()[index].name(), used_by.join(", "));
+      return;
+    }
+    self.selected = None;
+    self.record();
+    let data = self.sheet.sprites_mut().remove(index);
+    self.message = format!("{} deleted", data.name());
+  }
+
+  fn nudge_selected(&mut self, dx: i32, dy: i32, resize: bool)
+  {
+    let Some(index) = self.selected else { return; };
+    self.record();
+    let rect = self.sprites()[index].rect();
+    let rect = if resize {
+      Rect::new(rect.x(), rect.y(), (rect.width() as i32 + dx).max(1) as u32, (rect.height() as i32 + dy).max(1) as u32)
+    }
+    else {
+      rect.right_shifted(dx).bottom_shifted(dy)
+    };
+    self.set_rect(index, rect);
+  }
+
+  fn finish_naming(&mut self)
+  {
+    let (Some(name), Some(index)) = (self.naming.take(), self.selected) else { return; };
+    if name.is_empty() || name == *self.sprites()[index].name() {
+      return;
+    }
+    if let Err(e) = check_sprite_name(&name) {
+      self.message = format!("can't rename: {}", e);
+    }
+    else if self.sprites().iter().any(|data| *data.name() == name) {
+      self.message = format!("there is already a sprite named {}", name);
+    }
+    else {
+      self.record();
+      self.sheet.rename_sprite(index, name); // the animations follow
+    }
+  }
+
+  fn undo(&mut self, redo: bool)
+  {
+    let current = self.sheet.clone();
+    let state = if redo { self.history.redo(current) } else { self.history.undo(current) };
+    match state
+    {
+      Some(sheet) =>
+      {
+        self.sheet = sheet;
+        self.selected = self.selected.filter(|&i| i < self.sprites().len());
+        self.modified = true;
+      },
+      None => { self.message = format!("nothing to {}", if redo { "redo" } else { "undo" }); }
+    }
+  }
+
+  fn save(&mut self)
+  {
+    self.message = match self.sheet.save_json(&self.output)
+    {
+      Ok(()) => { self.modified = false; format!("saved {}", self.output.display()) },
+      Err(e) => format!("not saved! {}", e)
+    };
+  }
+}
+
+// A new sheet for a png, or the json to edit, and where to save
+fn open(path: &str, output: Option<String>) -> Result<(SpriteSheetData<String>, PathBuf), String>
+{
+  if path.ends_with(".json") {
+    Ok((try_load_sprites_from_json_as::<String>(path)?, PathBuf::from(output.unwrap_or_else(|| path.to_string()))))
+  }
+  else {
+    let output = output.map_or_else(|| Path::new(path).with_extension("json"), PathBuf::from);
+    Ok((SpriteSheetData::new(path), output))
+  }
+}
+
+fn main() -> Result<(), String>
+{
+  let mut args = std::env::args().skip(1);
+  let path = args.next().ok_or(USAGE)?;
+  let (sheet, output) = open(&path, args.next())?;
+
+  let sdl_context = sdl2::init()?;
+  sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0"); // zoomed pixels stay square
+  let _image_context = sdl2::image::init(InitFlag::PNG)?;
+  let video_subsystem = sdl_context.video()?;
+  let window = video_subsystem.window("slice_editor", 1200, 800)
+    .position_centered().resizable().build()
+    .map_err(|e| e.to_string())?;
+  let mut canvas = window.into_canvas().present_vsync().build().map_err(|e| e.to_string())?;
+  canvas.set_blend_mode(BlendMode::Blend);
+  let mut event_pump = sdl_context.event_pump()?;
+  let text_input = video_subsystem.text_input();
+
+  // Keyed like the TextureManager does, the pixels being kept for the alpha snapping
+  let mut pixels = load_surface(sheet.spritesheet())?.convert_format(PixelFormatEnum::RGBA32)?;
+  let region_keys: Vec<(Rect, ColorKey)> = sheet.sprites().iter()
+    .filter_map(|data| data.color_key().map(|key| (data.rect(), key)))
+    .collect();
+  apply_color_keys(&mut pixels, sheet.color_key(), &region_keys);
+  let texture_creator = canvas.texture_creator();
+  let texture = texture_creator.create_texture_from_surface(&pixels).map_err(|e| e.to_string())?;
+  let sheet_rect = Rect::new(0, 0, pixels.width(), pixels.height());
+
+  let mut editor = Editor {
+    sheet,
+    pixels,
+    message: format!("editing {} - Ctrl+S saves to {}", path, output.display()),
+    output,
+    history: EditHistory::new(200),
+    selected: None,
+    naming: None,
+    grid: 16,
+    grid_snap: false,
+    alpha_snap: false,
+    modified: false
+  };
+
+  let mut camera = Camera2D::from_canvas(&canvas);
+  camera.fit(sheet_rect, 0.1);
+  let mut mouse = Point::new(0, 0);
+  let mut drag: Option<Drag> = None;
+  let mut quit_asked = false;
+  let mut title = String::new();
+
+  'editor: loop
+  {
+    let pixel_at = |camera: &Camera2D, point: Point| camera.screen_to_world(point)
+      .map(|(x, y)| Point::new(x.floor() as i32, y.floor() as i32));
+
+    for event in event_pump.poll_iter()
+    {
+      if editor.naming.is_some() {
+        match event
+        {
+          Event::TextInput { text, .. } =>
+          {
+            let name = editor.naming.as_mut().unwrap();
+            name.extend(text.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '_'));
+            continue;
+          },
+          Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => { editor.naming.as_mut().unwrap().pop(); continue; },
+          Event::KeyDown { keycode: Some(Keycode::Return | Keycode::KpEnter), .. } => { editor.finish_naming(); continue; },
+          Event::KeyDown { keycode: Some(Keycode::Escape), .. } => { editor.naming = None; continue; },
+          Event::KeyDown {..} => { continue; }, // (no shortcuts while typing)
+          Event::MouseButtonDown {..} => { editor.finish_naming(); }, // before the selection changes
+          _ => {}
+        }
+      }
+
+      match event
+      {
+        Event::Quit {..} =>
+        {
+          if !editor.modified || quit_asked {
+            break 'editor;
+          }
+          quit_asked = true;
+          editor.message = "unsaved changes! Ctrl+S to save them, close again to drop them".to_string();
+        },
+        Event::Window { win_event: WindowEvent::SizeChanged(w, h), .. } =>
+        {
+          camera.set_viewport(Rect::new(0, 0, w.max(1) as u32, h.max(1) as u32));
+        },
+        Event::KeyDown { keycode: Some(keycode), keymod, .. } =>
+        {
+          let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD | Mod::LGUIMOD | Mod::RGUIMOD);
+          let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+          // no undo or delete under a rect being dragged (the drag holds its index)
+          let dragging_rect = matches!(drag, Some(Drag::Move {..} | Drag::Resize {..}));
+          match keycode
+          {
+            Keycode::Z | Keycode::Y | Keycode::Delete | Keycode::Backspace if dragging_rect => {},
+            Keycode::Z if ctrl => { editor.undo(shift); },
+            Keycode::Y if ctrl => { editor.undo(true); },
+            Keycode::S if ctrl => { editor.save(); },
+            Keycode::G => { editor.grid_snap = !editor.grid_snap; },
+            Keycode::A => { editor.alpha_snap = !editor.alpha_snap; },
+            Keycode::LeftBracket => { editor.grid = (editor.grid / 2).max(2); },
+            Keycode::RightBracket => { editor.grid = (editor.grid * 2).min(512); },
+            Keycode::F => { camera.fit(sheet_rect, 0.1); },
+            Keycode::Tab if !editor.sprites().is_empty() =>
+            {
+              editor.selected = Some(editor.selected.map_or(0, |i| (i + 1) % editor.sprites().len()));
+            },
+            Keycode::Return | Keycode::KpEnter | Keycode::F2 if editor.selected.is_some() => { editor.naming = Some(String::new()); },
+            Keycode::Delete | Keycode::Backspace => { editor.delete_selected(); },
+            Keycode::Left => { editor.nudge_selected(-1, 0, shift); },
+            Keycode::Right => { editor.nudge_selected(1, 0, shift); },
+            Keycode::Up => { editor.nudge_selected(0, -1, shift); },
+            Keycode::Down => { editor.nudge_selected(0, 1, shift); },
+            Keycode::Escape => { editor.selected = None; },
+            _ => {}
+          }
+        },
+        Event::MouseButtonDown { mouse_btn: MouseButton::Middle | MouseButton::Right, .. } => { drag = Some(Drag::Pan); },
+        Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } =>
+        {
+          let Some(pixel) = pixel_at(&camera, Point::new(x, y)) else { continue; };
+          // the corner handle of the selected rect first, then the rects, the topmost first
+          let corner = editor.selected.filter(|&i| {
+            let rect = camera.world_rect(editor.sprites()[i].rect());
+            (x - rect.right()).abs() <= HANDLE_SIZE && (y - rect.bottom()).abs() <= HANDLE_SIZE
+          });
+          let before = editor.sheet.clone();
+          drag = if let Some(index) = corner {
+            Some(Drag::Resize { index, before })
+          }
+          else if let Some(index) = editor.sprites().iter().rposition(|data| data.rect().contains_point(pixel)) {
+            editor.selected = Some(index);
+            let rect = editor.sprites()[index].rect();
+            Some(Drag::Move { index, grab: Point::new(pixel.x() - rect.x(), pixel.y() - rect.y()), before })
+          }
+          else {
+            Some(Drag::Create { start: pixel, current: pixel })
+          };
+        },
+        Event::MouseMotion { x, y, xrel, yrel, .. } =>
+        {
+          mouse = Point::new(x, y);
+          let pixel = pixel_at(&camera, mouse);
+          match (&mut drag, pixel)
+          {
+            (Some(Drag::Pan), _) => { camera.move_by(-xrel as f32 / camera.zoom(), -yrel as f32 / camera.zoom()); },
+            (Some(Drag::Create { current, .. }), Some(pixel)) => { *current = pixel; },
+            (Some(Drag::Move { index, grab, .. }), Some(pixel)) if *index < editor.sprites().len() =>
+            {
+              let rect = editor.sprites()[*index].rect();
+              let moved = Rect::new(pixel.x() - grab.x(), pixel.y() - grab.y(), rect.width(), rect.height());
+              editor.set_rect(*index, moved);
+            },
+            (Some(Drag::Resize { index, .. }), Some(pixel)) if *index < editor.sprites().len() =>
+            {
+              let rect = editor.sprites()[*index].rect();
+              let resized = rect_from_corners(rect.top_left(), Point::new(pixel.x().max(rect.x()), pixel.y().max(rect.y())));
+              editor.set_rect(*index, resized);
+            },
+            _ => {}
+          }
+        },
+        Event::MouseButtonUp {..} =>
+        {
+          match drag.take()
+          {
+            Some(Drag::Create { start, current }) if start == current => { editor.selected = None; }, // a click
+            Some(Drag::Create { start, current }) =>
+            {
+              let rect = editor.snapped(rect_from_corners(start, current));
+              editor.add(rect);
+            },
+            Some(Drag::Move { index, before, .. } | Drag::Resize { index, before }) if index < editor.sprites().len() =>
+            {
+              let rect = editor.snapped(editor.sprites()[index].rect());
+              editor.set_rect(index, rect);
+              if before.sprites().get(index).map(|data| data.rect()) != Some(rect) {
+                editor.history.record(before);
+                editor.modified = true;
+              }
+            },
+            _ => {}
+          }
+        },
+        Event::MouseWheel { y, .. } if y != 0 =>
+        {
+          camera.zoom_at(mouse, (camera.zoom() * 1.25f32.powi(y)).clamp(0.05, 64.0));
+        },
+        _ => {}
+      }
+    }
+
+    // Typing only while naming (on some platforms, text input is on by default)
+    if editor.naming.is_some() != text_input.is_active() {
+      if editor.naming.is_some() { text_input.start(); } else { text_input.stop(); }
+    }
+
+    canvas.set_draw_color(Color::RGB(0x30, 0x30, 0x30));
+    canvas.clear();
+    let sheet_on_screen = camera.world_rect(sheet_rect);
+    canvas.set_draw_color(Color::RGB(0x50, 0x50, 0x50));
+    canvas.fill_rect(sheet_on_screen)?;
+    canvas.copy(&texture, None, sheet_on_screen)?;
+
+    if editor.grid_snap && editor.grid as f32 * camera.zoom() >= 6.0 {
+      canvas.set_draw_color(GRID_COLOR);
+      for x in (editor.grid..sheet_rect.width()).step_by(editor.grid as usize) {
+        let (sx, _) = camera.world_to_viewport(x as f32, 0.0);
+        canvas.draw_line((sx as i32, sheet_on_screen.top()), (sx as i32, sheet_on_screen.bottom()))?;
+      }
+      for y in (editor.grid..sheet_rect.height()).step_by(editor.grid as usize) {
+        let (_, sy) = camera.world_to_viewport(0.0, y as f32);
+        canvas.draw_line((sheet_on_screen.left(), sy as i32), (sheet_on_screen.right(), sy as i32))?;
+      }
+    }
+
+    for (i, data) in editor.sprites().iter().enumerate() {
+      let rect = camera.world_rect(data.rect());
+      let selected = editor.selected == Some(i);
+      let color = if selected { SELECTED_COLOR } else { RECT_COLOR };
+      canvas.set_draw_color(color);
+      canvas.draw_rect(rect)?;
+      let label = match &editor.naming
+      {
+        Some(name) if selected => format!("{}_", name),
+        _ => data.name().clone()
+      };
+      draw_debug_label(&mut canvas, &label, rect.x(), rect.y(), 2, color, LABEL_BACKGROUND)?;
+      if selected {
+        let half = HANDLE_SIZE / 2;
+        canvas.fill_rect(Rect::new(rect.right() - half, rect.bottom() - half, HANDLE_SIZE as u32, HANDLE_SIZE as u32))?;
+      }
+    }
+    if let Some(Drag::Create { start, current }) = &drag {
+      canvas.set_draw_color(NEW_RECT_COLOR);
+      canvas.draw_rect(camera.world_rect(rect_from_corners(*start, *current)))?;
+    }
+
+    // Status, at the bottom left
+    let mut status = vec![format!("grid {} {} - alpha snap {} - {} sprites",
+      editor.grid, if editor.grid_snap { "on" } else { "off" }, if editor.alpha_snap { "on" } else { "off" },
+      editor.sprites().len())];
+    if let Some(pixel) = pixel_at(&camera, mouse) {
+      status[0] += &format!(" - pixel {}, {}", pixel.x(), pixel.y());
+    }
+    if let Some(i) = editor.selected {
+      let (data, rect) = (&editor.sprites()[i], editor.sprites()[i].rect());
+      status.push(match &editor.naming
+      {
+        Some(name) => format!("name: {}_ (Enter: ok, Escape: cancel)", name),
+        None => format!("{}: {}, {} {}x{}", data.name(), rect.x(), rect.y(), rect.width(), rect.height())
+      });
+    }
+    status.push(editor.message.clone());
+    let text = status.join("\n");
+    let (_, h) = debug_text_size(&text, 2);
+    let bottom = camera.viewport().height() as i32;
+    draw_debug_label(&mut canvas, &text, 8, bottom - h as i32 - 12, 2, Color::WHITE, LABEL_BACKGROUND)?;
+
+    let new_title = format!("slice_editor - {}{}", editor.output.display(), if editor.modified { " *" } else { "" });
+    if new_title != title {
+      canvas.window_mut().set_title(&new_title).map_err(|e| e.to_string())?;
+      title = new_title;
+    }
+
+    canvas.present();
+  }
+
+  Ok(())
+}
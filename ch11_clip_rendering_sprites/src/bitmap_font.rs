@@ -0,0 +1,398 @@
+// Bitmap fonts: text drawn from the glyphs of a spritesheet (cf. the lazyfoo bitmap font
+// lesson), no SDL_ttf needed. The glyphs are described in a json next to the png, either one
+// by one like the sprites of meta.json, or as a grid which is sliced automatically:
+//
+// { "spritesheet": "data/font.png", "color_key": "auto", "fallback": "?",
+//   "grid": { "cell_w": 16, "cell_h": 16, "columns": 16, "first_char": 32, "count": 95, "trim": true },
+//   "glyphs": [ { "char": "j", "x": 160, "y": 64, "w": 6, "h": 16, "advance": 5, "y_offset": 2 } ],
+//   "kerning": [ { "pair": "AV", "amount": -2 } ] }
+//
+// "glyphs" override the grid. With "trim", each cell is narrowed to its visible pixels (the
+// lazyfoo way), so that an 'i' doesn't take as much room as a 'W'.
+
+use crate::assets::{load_surface, read_asset};
+use crate::sprite::{with_style, SpriteStyle};
+use crate::text_layout::{FontMetrics, SpanStyle, TextLayout};
+use crate::texture::{apply_color_keys, ColorKey, SharedTexture, TextureManager};
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::surface::Surface;
+use sdl2::video::WindowContext;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+
+#[derive(Deserialize)]
+struct GridData
+{
+  cell_w: u32,
+  cell_h: u32,
+  columns: u32,
+  #[serde(default)]
+  x: i32, // where the grid starts in the sheet
+  #[serde(default)]
+  y: i32,
+  // the characters of the cells, in order: either "chars", or "count" characters from "first_char"
+  chars: Option<String>,
+  #[serde(default = "default_first_char")]
+  first_char: u32,
+  #[serde(default = "default_count")]
+  count: u32,
+  #[serde(default)]
+  trim: bool
+}
+
+fn default_first_char() -> u32 { 32 }
+fn default_count() -> u32 { 95 } // printable ascii
+
+#[derive(Deserialize)]
+struct GlyphData
+{
+  #[serde(rename = "char")]
+  character: char,
+  x: i32,
+  y: i32,
+  w: u32,
+  h: u32,
+  advance: Option<i32>, // default: w
+  #[serde(default)]
+  y_offset: i32
+}
+
+#[derive(Deserialize)]
+struct KerningData
+{
+  pair: String,
+  amount: i32
+}
+
+#[derive(Deserialize)]
+struct BitmapFontFile
+{
+  spritesheet: String,
+  color_key: Option<ColorKey>,
+  line_height: Option<u32>,
+  #[serde(default)]
+  spacing: i32,
+  fallback: Option<char>,
+  grid: Option<GridData>,
+  #[serde(default)]
+  glyphs: Vec<GlyphData>,
+  #[serde(default)]
+  kerning: Vec<KerningData>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph
+{
+  pub rect: Option<Rect>, // None for blank glyphs (space)
+  pub y_offset: i32,
+  pub advance: i32        // how far the pen moves after the glyph
+}
+
+// Everything but the texture: layout and measures don't need SDL
+#[derive(Debug, Clone)]
+pub struct BitmapFontData
+{
+  pub glyphs: HashMap<char, Glyph>,
+  pub kerning: HashMap<(char, char), i32>,
+  pub line_height: u32,
+  pub spacing: i32, // added to every advance
+  pub fallback: Option<char>
+}
+
+impl BitmapFontData
+{
+  // The glyph drawn for a character: its own, or the fallback one
+  pub fn glyph(&self, c: char) -> Option<&Glyph>
+  {
+    self.glyphs.get(&c).or_else(|| self.fallback.and_then(|f| self.glyphs.get(&f)))
+  }
+
+  pub fn kerning(&self, previous: char, c: char) -> i32
+  {
+    self.kerning.get(&(previous, c)).copied().unwrap_or(0)
+  }
+
+  // Where each glyph goes, relative to the top left corner of the text
+  pub fn layout(&self, text: &str) -> Vec<(Glyph, i32, i32)>
+  {
+    let mut placed = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    let mut previous = None;
+    for c in text.chars() {
+      if c == '\n' {
+        (x, y, previous) = (0, y + self.line_height as i32, None);
+        continue;
+      }
+      let Some(glyph) = self.glyph(c) else { continue };
+      if let Some(p) = previous {
+        x += self.kerning(p, c);
+      }
+      placed.push((*glyph, x, y + glyph.y_offset));
+      x += glyph.advance + self.spacing;
+      previous = Some(c);
+    }
+    placed
+  }
+
+  // Width of one line (no newline in it)
+  pub fn line_width(&self, line: &str) -> u32
+  {
+    let mut width = 0;
+    let mut previous = None;
+    for c in line.chars() {
+      let Some(glyph) = self.glyph(c) else { continue };
+      if let Some(p) = previous {
+        width += self.spacing + self.kerning(p, c);
+      }
+      width += glyph.advance;
+      previous = Some(c);
+    }
+    width.max(0) as u32
+  }
+
+  // Size of the text as drawn, lines included
+  pub fn measure(&self, text: &str) -> (u32, u32)
+  {
+    let width = text.split('\n').map(|line| self.line_width(line)).max().unwrap_or(0);
+    (width, text.split('\n').count() as u32 * self.line_height)
+  }
+}
+
+// First and last visible columns of a cell, None when the cell is blank
+fn visible_columns(cell: Rect, is_visible: impl Fn(i32, i32) -> bool) -> Option<(i32, i32)>
+{
+  let column_visible = |x: i32| (cell.top()..cell.bottom()).any(|y| is_visible(x, y));
+  let first = (cell.left()..cell.right()).find(|&x| column_visible(x))?;
+  let last = (cell.left()..cell.right()).rev().find(|&x| column_visible(x))?;
+  Some((first, last))
+}
+
+// Glyphs of a grid. pixels: the locked sheet as RGBA32, color key applied, its pitch and size (only used to trim)
+fn slice_grid(grid: &GridData, pixels: Option<(&[u8], usize, (u32, u32))>) -> Result<HashMap<char, Glyph>, String>
+{
+  let chars: Vec<char> = match &grid.chars {
+    Some(chars) => chars.chars().collect(),
+    None => (grid.first_char..grid.first_char + grid.count)
+      .map(|c| char::from_u32(c).ok_or(format!("bad character code {}", c)))
+      .collect::<Result<_, String>>()?
+  };
+  if grid.columns == 0 {
+    return Err("grid without columns".to_string());
+  }
+
+  // (the cells are clipped to the sheet before, so x and y are in it)
+  let is_visible = |x: i32, y: i32| pixels.is_some_and(|(pixels, pitch, _)| {
+    pixels[y as usize * pitch + x as usize * 4 + 3] != 0
+  });
+  // the part of a cell in the sheet, a grid at its edge having cells cut off
+  let scanned = |cell: Rect| pixels.and_then(|(_, _, (w, h))| {
+    let (left, top) = (cell.left().max(0), cell.top().max(0));
+    let (right, bottom) = (cell.right().min(w as i32), cell.bottom().min(h as i32));
+    (left < right && top < bottom).then(|| Rect::new(left, top, (right - left) as u32, (bottom - top) as u32))
+  });
+
+  let mut glyphs = HashMap::new();
+  for (i, &c) in chars.iter().enumerate() {
+    let (column, row) = (i as u32 % grid.columns, i as u32 / grid.columns);
+    let cell = Rect::new(grid.x + (column * grid.cell_w) as i32, grid.y + (row * grid.cell_h) as i32, grid.cell_w, grid.cell_h);
+    let glyph = if !grid.trim {
+      Glyph { rect: Some(cell), y_offset: 0, advance: grid.cell_w as i32 }
+    }
+    else {
+      match scanned(cell).and_then(|scan| visible_columns(scan, is_visible))
+      {
+        Some((first, last)) =>
+        {
+          let w = (last - first + 1) as u32;
+          Glyph { rect: Some(Rect::new(first, cell.y(), w, cell.height())), y_offset: 0, advance: w as i32 }
+        },
+        None => Glyph { rect: None, y_offset: 0, advance: grid.cell_w as i32 / 2 } // e.g. space
+      }
+    };
+    glyphs.insert(c, glyph);
+  }
+  Ok(glyphs)
+}
+
+fn font_data(file: &BitmapFontFile, surface: Option<&Surface>) -> Result<BitmapFontData, String>
+{
+  let mut glyphs = match &file.grid {
+    // locked once for the whole grid
+    Some(grid) => match surface
+    {
+      Some(s) => { let (pitch, size) = (s.pitch() as usize, s.size()); s.with_lock(|pixels| slice_grid(grid, Some((pixels, pitch, size))))? },
+      None => slice_grid(grid, None)?
+    },
+    None => HashMap::new()
+  };
+  for g in &file.glyphs {
+    let glyph = Glyph { rect: Some(Rect::new(g.x, g.y, g.w, g.h)), y_offset: g.y_offset, advance: g.advance.unwrap_or(g.w as i32) };
+    glyphs.insert(g.character, glyph);
+  }
+  if glyphs.is_empty() {
+    return Err("no glyphs (neither \"grid\" nor \"glyphs\")".to_string());
+  }
+  if let Some(fallback) = file.fallback.filter(|f| !glyphs.contains_key(f)) {
+    return Err(format!("fallback '{}' isn't a glyph of the font", fallback));
+  }
+
+  let mut kerning = HashMap::new();
+  for k in &file.kerning {
+    let mut chars = k.pair.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+      (Some(a), Some(b), None) => { kerning.insert((a, b), k.amount); },
+      _ => return Err(format!("kerning pair \"{}\" isn't two characters", k.pair))
+    }
+  }
+
+  let line_height = file.line_height.unwrap_or_else(|| {
+    glyphs.values().filter_map(|g| g.rect.map(|r| r.height() as i32 + g.y_offset)).max().unwrap_or(0).max(0) as u32
+  });
+  Ok(BitmapFontData { glyphs, kerning, line_height, spacing: file.spacing, fallback: file.fallback })
+}
+
+pub struct BitmapFont<'a>
+{
+  data: BitmapFontData,
+  texture: SharedTexture<'a>,
+  style: SpriteStyle
+}
+
+impl<'a> BitmapFont<'a>
+{
+  pub fn load(texture_creator: &'a TextureCreator<WindowContext>,
+    texture_manager: &mut TextureManager<'a>,
+    json_path: &str) -> Result<BitmapFont<'a>, String>
+  {
+    let bytes = read_asset(json_path)?;
+    let file: BitmapFontFile = serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", json_path, e))?;
+
+    // trimming needs the pixels, as they'll be once keyed
+    let surface = match &file.grid {
+      Some(grid) if grid.trim => {
+        let mut surface = load_surface(&file.spritesheet)?.convert_format(PixelFormatEnum::RGBA32)?;
+        apply_color_keys(&mut surface, file.color_key, &[]);
+        Some(surface)
+      },
+      _ => None
+    };
+    let data = font_data(&file, surface.as_ref()).map_err(|e| format!("{}: {}", json_path, e))?;
+    let texture = texture_manager.try_load_texture_with_keys(texture_creator, &file.spritesheet, file.color_key, &[])?;
+    Ok(BitmapFont { data, texture, style: SpriteStyle::default() })
+  }
+
+  pub fn data(&self) -> &BitmapFontData { &self.data }
+
+  // Tinting works best with a white font
+  pub fn set_color(&mut self, tint: Color) { self.style.tint = tint; }
+  pub fn set_style(&mut self, style: SpriteStyle) { self.style = style; }
+
+  pub fn measure(&self, text: &str) -> (u32, u32) { self.data.measure(text) }
+
+  // (x, y): top left corner of the text. '\n' starts a new line.
+  pub fn render(&self, canvas: &mut WindowCanvas, text: &str, x: i32, y: i32)
+  {
+    self.render_styled(canvas, text, x, y, &self.style);
+  }
+
+  fn render_styled(&self, canvas: &mut WindowCanvas, text: &str, x: i32, y: i32, style: &SpriteStyle)
+  {
+    with_style(&self.texture, style, |tex| {
+      for (glyph, gx, gy) in self.data.layout(text) {
+        if let Some(src) = glyph.rect {
+          canvas.copy(tex, src, Rect::new(x + gx, y + gy, src.width(), src.height())).unwrap();
+        }
+      }
+    });
+  }
+
+  // Draws a layout made with this font (text_layout.rs), (x, y) being the top left corner of its
+  // box. Colors, underline and strikethrough are applied; bold and italic would need other fonts.
+  pub fn render_layout(&self, canvas: &mut WindowCanvas, layout: &TextLayout, x: i32, y: i32)
+  {
+    for run in &layout.runs {
+      let style = SpriteStyle { tint: run.style.color.unwrap_or(self.style.tint), ..self.style };
+      self.render_styled(canvas, &run.text, x + run.x, y + run.y, &style);
+
+      let line_height = self.data.line_height as i32;
+      let lines = [(run.style.underline, line_height - 1), (run.style.strikethrough, line_height / 2)];
+      if run.width > 0 && lines.iter().any(|l| l.0) {
+        let draw_color = canvas.draw_color();
+        canvas.set_draw_color(Color::RGBA(style.tint.r, style.tint.g, style.tint.b, style.alpha));
+        for (_, line_y) in lines.iter().filter(|l| l.0) {
+          canvas.fill_rect(Rect::new(x + run.x, y + run.y + line_y, run.width, 1)).unwrap();
+        }
+        canvas.set_draw_color(draw_color);
+      }
+    }
+  }
+}
+
+// Bitmap fonts have a single style: it doesn't change the measures
+impl FontMetrics for BitmapFontData
+{
+  fn text_width(&self, text: &str, _style: &SpanStyle) -> u32 { self.line_width(text) }
+  fn line_height(&self) -> u32 { self.line_height }
+}
+
+impl FontMetrics for BitmapFont<'_>
+{
+  fn text_width(&self, text: &str, style: &SpanStyle) -> u32 { self.data.text_width(text, style) }
+  fn line_height(&self) -> u32 { self.data.line_height }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn font() -> BitmapFontData {
+    let file: BitmapFontFile = serde_json::from_str(r#"{
+      "spritesheet": "font.png", "spacing": 1, "fallback": "?",
+      "grid": { "cell_w": 8, "cell_h": 10, "columns": 4, "chars": "AV?" },
+      "glyphs": [ { "char": " ", "x": 0, "y": 0, "w": 1, "h": 1, "advance": 4 } ],
+      "kerning": [ { "pair": "AV", "amount": -2 } ] }"#).unwrap();
+    font_data(&file, None).unwrap()
+  }
+
+  #[test]
+  fn test_layout_with_kerning_and_fallback() {
+    let font = font();
+    let xs: Vec<i32> = font.layout("AVx").iter().map(|&(_, x, _)| x).collect();
+    assert_eq!(xs, vec![0, 7, 16]); // A, V pulled back by 2, then '?' for x
+    assert_eq!(font.layout("A\nV")[1].2, 10);
+  }
+
+  #[test]
+  fn test_measure() {
+    let font = font();
+    assert_eq!(font.measure("AV"), (8 + 1 - 2 + 8, 10));
+    assert_eq!(font.measure("A A\nV"), (8 + 1 + 4 + 1 + 8, 20));
+    assert_eq!(font.measure(""), (0, 10));
+  }
+
+  #[test]
+  fn test_visible_columns() {
+    let cell = Rect::new(8, 0, 8, 4);
+    assert_eq!(visible_columns(cell, |x, y| (10..=12).contains(&x) && y == 2), Some((10, 12)));
+    assert_eq!(visible_columns(cell, |_, _| false), None);
+  }
+
+  #[test]
+  fn test_trim_at_the_sheet_edge() {
+    // a 6x2 sheet (rows of 28 bytes) and cells of 4: the second one is cut off at x = 6
+    let grid: GridData = serde_json::from_str(r#"{ "cell_w": 4, "cell_h": 2, "columns": 2, "chars": "ab", "trim": true }"#).unwrap();
+    let mut pixels = vec![0u8; 2 * 28];
+    pixels[28 + 5 * 4 + 3] = 0xFF; // (5, 1)
+    pixels[28 + 3] = 0xFF;         // (0, 1), which is past the end of row 0
+    let glyphs = slice_grid(&grid, Some((&pixels, 28, (6, 2)))).unwrap();
+    assert_eq!(glyphs[&'b'].rect, Some(Rect::new(5, 0, 1, 2)));
+    assert_eq!(glyphs[&'a'].rect, Some(Rect::new(0, 0, 1, 2)));
+  }
+}
@@ -0,0 +1,281 @@
+// A 2D camera: what part of the world (in world pixels) is shown in the canvas viewport, and
+// how big. Its position is the world point at the middle of the viewport; a zoom of 2 shows
+// everything twice as big (and half as much of the world). It can follow a target, smoothly,
+// and be kept inside the level bounds.
+//
+// Three kinds of coordinates:
+// - world: where things are in the level
+// - viewport: what the canvas draws at, relative to the viewport set on it (cf. ch09)
+// - screen: window pixels, e.g. the mouse position of the events
+
+use crate::sprite::{RenderEx, Sprite};
+
+use sdl2::rect::{Point, Rect};
+use sdl2::render::WindowCanvas;
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera2D
+{
+  position: (f32, f32),       // world point at the middle of the viewport
+  zoom: f32,
+  viewport: Rect,             // in the window
+  bounds: Option<Rect>,       // world part the view stays in
+  target: Option<(f32, f32)>, // followed point
+  follow_speed: f32           // 0: jumps on the target
+}
+
+impl Camera2D
+{
+  pub fn new(viewport: Rect) -> Camera2D
+  {
+    Camera2D { position: (0.0, 0.0), zoom: 1.0, viewport, bounds: None, target: None, follow_speed: 0.0 }
+  }
+
+  // With the viewport the canvas has now (the whole window if none was set)
+  pub fn from_canvas(canvas: &WindowCanvas) -> Camera2D
+  {
+    Camera2D::new(canvas.viewport())
+  }
+
+  pub fn viewport(&self) -> Rect { self.viewport }
+
+  // E.g. after a window resize, or to draw the same world in several viewports (split screen)
+  pub fn set_viewport(&mut self, viewport: Rect)
+  {
+    self.viewport = viewport;
+    self.clamp_to_bounds();
+  }
+
+  // Picks up the viewport currently set on the canvas
+  pub fn sync_viewport(&mut self, canvas: &WindowCanvas)
+  {
+    self.set_viewport(canvas.viewport());
+  }
+
+  pub fn position(&self) -> (f32, f32) { self.position }
+
+  pub fn set_position(&mut self, x: f32, y: f32)
+  {
+    self.position = (x, y);
+    self.clamp_to_bounds();
+  }
+
+  pub fn move_by(&mut self, dx: f32, dy: f32)
+  {
+    self.set_position(self.position.0 + dx, self.position.1 + dy);
+  }
+
+  pub fn zoom(&self) -> f32 { self.zoom }
+
+  pub fn set_zoom(&mut self, zoom: f32)
+  {
+    if zoom > 0.0 && zoom.is_finite() {
+      self.zoom = zoom;
+      self.clamp_to_bounds();
+    }
+  }
+
+  // Zooms keeping the world point under a window pixel where it is, e.g. under the mouse wheel
+  pub fn zoom_at(&mut self, point: Point, zoom: f32)
+  {
+    let before = self.screen_to_world(point);
+    self.set_zoom(zoom);
+    if let (Some(before), Some(after)) = (before, self.screen_to_world(point)) {
+      self.move_by(before.0 - after.0, before.1 - after.1);
+    }
+  }
+
+  // Shows the whole world rect, as big as possible (margin: part of the viewport left around it)
+  pub fn fit(&mut self, rect: Rect, margin: f32)
+  {
+    let zoom = (self.viewport.width() as f32 / rect.width() as f32)
+      .min(self.viewport.height() as f32 / rect.height() as f32) * (1.0 - margin);
+    self.set_zoom(zoom);
+    self.set_position(rect.x() as f32 + rect.width() as f32 / 2.0, rect.y() as f32 + rect.height() as f32 / 2.0);
+  }
+
+  pub fn bounds(&self) -> Option<Rect> { self.bounds }
+
+  // Usually the level size: Rect::new(0, 0, map_width, map_height). None: no limit.
+  pub fn set_bounds(&mut self, bounds: Option<Rect>)
+  {
+    self.bounds = bounds;
+    self.clamp_to_bounds();
+  }
+
+  // The camera moves toward the target at each update(). None: stays where it is.
+  pub fn follow(&mut self, target: Option<(f32, f32)>)
+  {
+    self.target = target;
+  }
+
+  // How fast the target is caught up: about the part of the distance covered per 1/speed second
+  // (exponential smoothing, so the same for any frame rate). 0 = instantly.
+  pub fn set_follow_speed(&mut self, speed: f32)
+  {
+    self.follow_speed = speed.max(0.0);
+  }
+
+  pub fn update(&mut self, dt: Duration)
+  {
+    if let Some((tx, ty)) = self.target {
+      let t = if self.follow_speed == 0.0 { 1.0 } else { 1.0 - (-self.follow_speed * dt.as_secs_f32()).exp() };
+      let (x, y) = self.position;
+      self.set_position(x + (tx - x) * t, y + (ty - y) * t);
+    }
+  }
+
+  // Size of the world part shown
+  fn view_size(&self) -> (f32, f32)
+  {
+    (self.viewport.width() as f32 / self.zoom, self.viewport.height() as f32 / self.zoom)
+  }
+
+  // The world part shown, e.g. the camera of TiledMap::render / TileMap::render (which draw at zoom 1)
+  pub fn view(&self) -> Rect
+  {
+    let (w, h) = self.view_size();
+    let (x, y) = (self.position.0 - w / 2.0, self.position.1 - h / 2.0);
+    Rect::new(x.floor() as i32, y.floor() as i32, w.ceil() as u32, h.ceil() as u32)
+  }
+
+  // If the view is bigger than the bounds on an axis, the bounds are centered on it
+  fn clamp_to_bounds(&mut self)
+  {
+    let Some(bounds) = self.bounds else { return; };
+    let (w, h) = self.view_size();
+    let clamp = |center: f32, half: f32, min: i32, size: u32| {
+      let (min, max) = (min as f32, min as f32 + size as f32);
+      if max - min <= 2.0 * half { (min + max) / 2.0 } else { center.clamp(min + half, max - half) }
+    };
+    self.position = (
+      clamp(self.position.0, w / 2.0, bounds.x(), bounds.width()),
+      clamp(self.position.1, h / 2.0, bounds.y(), bounds.height())
+    );
+  }
+
+  pub fn world_to_viewport(&self, x: f32, y: f32) -> (f32, f32)
+  {
+    let (w, h) = self.view_size();
+    ((x - self.position.0 + w / 2.0) * self.zoom, (y - self.position.1 + h / 2.0) * self.zoom)
+  }
+
+  pub fn viewport_to_world(&self, x: f32, y: f32) -> (f32, f32)
+  {
+    let (w, h) = self.view_size();
+    (x / self.zoom + self.position.0 - w / 2.0, y / self.zoom + self.position.1 - h / 2.0)
+  }
+
+  // The window pixel a world point is drawn at
+  pub fn world_to_screen(&self, x: f32, y: f32) -> Point
+  {
+    let (vx, vy) = self.world_to_viewport(x, y);
+    Point::new(vx.round() as i32 + self.viewport.x(), vy.round() as i32 + self.viewport.y())
+  }
+
+  // The world point under a window pixel, e.g. the mouse. None outside the viewport.
+  pub fn screen_to_world(&self, point: Point) -> Option<(f32, f32)>
+  {
+    if !self.viewport.contains_point(point) {
+      return None;
+    }
+    Some(self.viewport_to_world((point.x() - self.viewport.x()) as f32, (point.y() - self.viewport.y()) as f32))
+  }
+
+  // A world rect where it's drawn (viewport coordinates), e.g. for canvas.copy or fill_rect
+  pub fn world_rect(&self, rect: Rect) -> Rect
+  {
+    let (x, y) = self.world_to_viewport(rect.x() as f32, rect.y() as f32);
+    let (right, bottom) = self.world_to_viewport(rect.right() as f32, rect.bottom() as f32);
+    let (x, y) = (x.round() as i32, y.round() as i32);
+    Rect::new(x, y, (right.round() as i32 - x).max(1) as u32, (bottom.round() as i32 - y).max(1) as u32)
+  }
+
+  pub fn is_visible(&self, rect: Rect) -> bool
+  {
+    self.view().has_intersection(rect)
+  }
+
+  // Sprite::render at a world position (its top left corner), scaled by the zoom
+  pub fn render<N>(&self, canvas: &mut WindowCanvas, sprite: &Sprite<N>, x: i32, y: i32)
+  {
+    self.render_ex(canvas, sprite, x, y, &RenderEx::default());
+  }
+
+  pub fn render_ex<N>(&self, canvas: &mut WindowCanvas, sprite: &Sprite<N>, x: i32, y: i32, params: &RenderEx)
+  {
+    let (vx, vy) = self.world_to_viewport(x as f32, y as f32);
+    let params = RenderEx {
+      scale: (params.scale.0 * self.zoom, params.scale.1 * self.zoom),
+      center: params.center.map(|c| Point::new(
+        (c.x() as f32 * self.zoom).round() as i32, (c.y() as f32 * self.zoom).round() as i32)),
+      ..*params
+    };
+    sprite.render_ex(canvas, vx.round() as i32, vy.round() as i32, &params);
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_conversions_with_viewport_and_zoom() {
+    // a 200x100 viewport at (50, 20) in the window
+    let mut camera = Camera2D::new(Rect::new(50, 20, 200, 100));
+    camera.set_position(500.0, 300.0);
+    assert_eq!(camera.view(), Rect::new(400, 250, 200, 100));
+    assert_eq!(camera.world_to_screen(400.0, 250.0), Point::new(50, 20));
+    camera.set_zoom(2.0);
+    assert_eq!(camera.view(), Rect::new(450, 275, 100, 50));
+    assert_eq!(camera.world_to_screen(460.0, 280.0), Point::new(70, 30));
+    assert_eq!(camera.screen_to_world(Point::new(70, 30)), Some((460.0, 280.0)));
+    assert_eq!(camera.screen_to_world(Point::new(10, 30)), None);
+    assert_eq!(camera.world_rect(Rect::new(450, 275, 10, 5)), Rect::new(0, 0, 20, 10));
+    camera.zoom_at(Point::new(70, 30), 4.0);
+    assert_eq!(camera.screen_to_world(Point::new(70, 30)), Some((460.0, 280.0)));
+    camera.fit(Rect::new(0, 0, 400, 100), 0.5);
+    assert_eq!((camera.zoom(), camera.position()), (0.25, (200.0, 50.0)));
+  }
+
+  #[test]
+  fn test_bounds() {
+    let mut camera = Camera2D::new(Rect::new(0, 0, 200, 100));
+    camera.set_bounds(Some(Rect::new(0, 0, 1000, 80)));
+    camera.set_position(-50.0, 10.0);
+    // stopped at the left edge; the level is less high than the view: centered
+    assert_eq!(camera.position(), (100.0, 40.0));
+    camera.set_position(2000.0, 0.0);
+    assert_eq!(camera.view(), Rect::new(800, -10, 200, 100));
+    // zooming in, the view can go further
+    camera.set_zoom(4.0);
+    camera.set_position(2000.0, 0.0);
+    assert_eq!(camera.position(), (975.0, 12.5));
+  }
+
+  #[test]
+  fn test_follow() {
+    let mut camera = Camera2D::new(Rect::new(0, 0, 200, 100));
+    camera.follow(Some((100.0, 0.0)));
+    camera.update(Duration::from_millis(16));
+    assert_eq!(camera.position(), (100.0, 0.0)); // no smoothing: jumps there
+
+    camera.follow(Some((200.0, 0.0)));
+    camera.set_follow_speed(2.0);
+    camera.update(Duration::from_millis(500)); // 1 - e^-1 of the way
+    assert!((camera.position().0 - 163.2).abs() < 0.1);
+    // the same in two steps
+    let mut stepped = Camera2D::new(Rect::new(0, 0, 200, 100));
+    stepped.set_position(100.0, 0.0);
+    stepped.follow(Some((200.0, 0.0)));
+    stepped.set_follow_speed(2.0);
+    stepped.update(Duration::from_millis(250));
+    stepped.update(Duration::from_millis(250));
+    assert!((stepped.position().0 - camera.position().0).abs() < 0.01);
+  }
+}
@@ -0,0 +1,243 @@
+// Pixel-perfect collisions. A mask is one bit per pixel of a sprite (set = solid), built once
+// at load time from the alpha of the spritesheet, color keys applied: it has to be read from
+// the Surface, a Texture's pixels staying on the GPU. Two positioned masks are tested for
+// overlap with an AABB early-out, then 64 pixels at a time.
+
+use crate::sprite::{SpriteKey, SpriteName, SpriteSheetData};
+use crate::texture::load_keyed_surface;
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::{Point, Rect};
+use sdl2::surface::Surface;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollisionMask
+{
+  width: u32,
+  height: u32,
+  words_per_row: usize,
+  bits: Vec<u64> // row by row, bit i of a word = pixel i of its 64
+}
+
+impl CollisionMask
+{
+  pub fn from_fn(width: u32, height: u32, is_solid: impl Fn(u32, u32) -> bool) -> CollisionMask
+  {
+    let words_per_row = (width as usize).div_ceil(64);
+    let mut bits = vec![0u64; words_per_row * height as usize];
+    for y in 0..height {
+      for x in 0..width {
+        if is_solid(x, y) {
+          bits[y as usize * words_per_row + x as usize / 64] |= 1 << (x % 64);
+        }
+      }
+    }
+    CollisionMask { width, height, words_per_row, bits }
+  }
+
+  // Pixels of `rect` with an alpha of at least `alpha_threshold`. The surface must be RGBA32.
+  pub fn from_surface(surface: &Surface, rect: Rect, alpha_threshold: u8) -> CollisionMask
+  {
+    let pitch = surface.pitch() as usize;
+    surface.with_lock(|pixels| {
+      CollisionMask::from_fn(rect.width(), rect.height(), |x, y| {
+        let offset = (rect.y() as usize + y as usize) * pitch + (rect.x() as usize + x as usize) * 4 + 3;
+        pixels.get(offset).is_some_and(|&alpha| alpha >= alpha_threshold.max(1))
+      })
+    })
+  }
+
+  pub fn width(&self) -> u32 { self.width }
+  pub fn height(&self) -> u32 { self.height }
+
+  pub fn is_solid(&self, x: u32, y: u32) -> bool
+  {
+    x < self.width && y < self.height && self.bits[y as usize * self.words_per_row + x as usize / 64] & (1 << (x % 64)) != 0
+  }
+
+  // The 64 pixels of row y starting at x (fewer at the end of the row, the others being 0)
+  fn bits_at(&self, x: u32, y: u32) -> u64
+  {
+    let row = &self.bits[y as usize * self.words_per_row..(y as usize + 1) * self.words_per_row];
+    let (word, shift) = (x as usize / 64, x % 64);
+    let low = row.get(word).map_or(0, |w| w >> shift);
+    let high = if shift == 0 { 0 } else { row.get(word + 1).map_or(0, |w| w << (64 - shift)) };
+    let bits = low | high;
+    let remaining = self.width - x;
+    if remaining >= 64 { bits } else { bits & ((1 << remaining) - 1) }
+  }
+
+  // First solid pixel (top to bottom, left to right) shared by the two masks, in world
+  // coordinates, `position` being the top left corner of each mask. None if they don't touch.
+  pub fn overlap(&self, position: Point, other: &CollisionMask, other_position: Point) -> Option<Point>
+  {
+    if self.width == 0 || self.height == 0 || other.width == 0 || other.height == 0 {
+      return None;
+    }
+    // AABB early-out
+    let (left, top) = (position.x().max(other_position.x()), position.y().max(other_position.y()));
+    let right = (position.x() + self.width as i32).min(other_position.x() + other.width as i32);
+    let bottom = (position.y() + self.height as i32).min(other_position.y() + other.height as i32);
+    if left >= right || top >= bottom {
+      return None;
+    }
+
+    for y in top..bottom {
+      let mut x = left;
+      while x < right {
+        let a = self.bits_at((x - position.x()) as u32, (y - position.y()) as u32);
+        let b = other.bits_at((x - other_position.x()) as u32, (y - other_position.y()) as u32);
+        let both = a & b;
+        let span = (right - x).min(64);
+        let both = if span == 64 { both } else { both & ((1 << span) - 1) };
+        if both != 0 {
+          return Some(Point::new(x + both.trailing_zeros() as i32, y));
+        }
+        x += 64;
+      }
+    }
+    None
+  }
+
+  pub fn overlaps(&self, position: Point, other: &CollisionMask, other_position: Point) -> bool
+  {
+    self.overlap(position, other, other_position).is_some()
+  }
+}
+
+// Where a pixel of the sprite as drawn is in the sheet, for the sprites packed rotated
+// (stored turned 90° clockwise) and trimmed (only the stored part has pixels).
+fn sheet_pixel(x: u32, y: u32, frame: Rect, rotated: bool, trim_offset: Point) -> Option<(i32, i32)>
+{
+  let (fx, fy) = (x as i32 - trim_offset.x(), y as i32 - trim_offset.y());
+  let (w, h) = if rotated { (frame.height(), frame.width()) } else { (frame.width(), frame.height()) };
+  if fx < 0 || fy < 0 || fx >= w as i32 || fy >= h as i32 {
+    return None; // trimmed out: transparent
+  }
+  Some(if rotated {
+    (frame.x() + h as i32 - 1 - fy, frame.y() + fx)
+  }
+  else {
+    (frame.x() + fx, frame.y() + fy)
+  })
+}
+
+// The masks of all the sprites of a sheet, in the orientation and size they are drawn.
+// The png is read, keyed like by the TextureManager (sheet and per-sprite keys). To get the
+// masks with the sprites from one read of the png, cf. try_create_sprites_with_masks.
+pub fn create_collision_masks<N: SpriteKey>(sprite_data: &SpriteSheetData<N>, alpha_threshold: u8)
+  -> Result<HashMap<N, CollisionMask>, String>
+{
+  let surface = load_keyed_surface(sprite_data.spritesheet(), sprite_data.color_key(), &sprite_data.region_keys())?;
+  collision_masks_from_surface(sprite_data, &surface, alpha_threshold)
+}
+
+// Same from the keyed surface of the sheet (cf. load_keyed_surface)
+pub fn collision_masks_from_surface<N: SpriteKey>(sprite_data: &SpriteSheetData<N>, surface: &Surface,
+  alpha_threshold: u8) -> Result<HashMap<N, CollisionMask>, String>
+{
+  // a simple color key is still a key: SDL turns it into alpha in the conversion
+  let converted;
+  let surface = if surface.pixel_format_enum() == PixelFormatEnum::RGBA32 {
+    surface
+  }
+  else {
+    converted = surface.convert_format(PixelFormatEnum::RGBA32)?;
+    &converted
+  };
+
+  let pitch = surface.pitch() as usize;
+  let bounds = surface.rect();
+  let alpha_threshold = alpha_threshold.max(1);
+  let masks = surface.with_lock(|pixels| {
+    sprite_data.sprites().iter().map(|data| {
+      let frame = data.rect();
+      let (size, trim_offset) = match data.trim() {
+        Some(trim) => (trim.source_size, trim.offset),
+        None => (if data.is_rotated() { (frame.height(), frame.width()) } else { (frame.width(), frame.height()) }, Point::new(0, 0))
+      };
+      let mask = CollisionMask::from_fn(size.0, size.1, |x, y| {
+        sheet_pixel(x, y, frame, data.is_rotated(), trim_offset)
+          .filter(|&(sx, sy)| bounds.contains_point((sx, sy)))
+          .is_some_and(|(sx, sy)| pixels[sy as usize * pitch + sx as usize * 4 + 3] >= alpha_threshold)
+      });
+      (data.name().clone(), mask)
+    }).collect()
+  });
+  Ok(masks)
+}
+
+// Masks by sprite name, e.g. to test two sprites where they're drawn
+pub struct CollisionMasks<N = SpriteName>
+{
+  masks: HashMap<N, CollisionMask>
+}
+
+impl<N: SpriteKey> CollisionMasks<N>
+{
+  pub fn new(sprite_data: &SpriteSheetData<N>, alpha_threshold: u8) -> Result<CollisionMasks<N>, String>
+  {
+    Ok(CollisionMasks { masks: create_collision_masks(sprite_data, alpha_threshold)? })
+  }
+
+  // Several sheets
+  pub fn extend(&mut self, sprite_data: &SpriteSheetData<N>, alpha_threshold: u8) -> Result<(), String>
+  {
+    self.masks.extend(create_collision_masks(sprite_data, alpha_threshold)?);
+    Ok(())
+  }
+
+  pub fn get(&self, name: &N) -> Option<&CollisionMask> { self.masks.get(name) }
+
+  // Sprites drawn at (x, y) with Sprite::render. None if they don't touch, or if one has no mask.
+  pub fn overlap(&self, a: &N, a_position: Point, b: &N, b_position: Point) -> Option<Point>
+  {
+    self.masks.get(a)?.overlap(a_position, self.masks.get(b)?, b_position)
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  // a disc of radius r in a square of 2r + 1
+  fn disc(r: i32) -> CollisionMask {
+    let size = (2 * r + 1) as u32;
+    CollisionMask::from_fn(size, size, |x, y| (x as i32 - r).pow(2) + (y as i32 - r).pow(2) <= r * r)
+  }
+
+  #[test]
+  fn test_discs() {
+    let a = disc(10);
+    // apart, then bounding boxes touching by the corners only, then overlapping
+    assert_eq!(a.overlap(Point::new(0, 0), &a, Point::new(30, 0)), None);
+    assert_eq!(a.overlap(Point::new(0, 0), &a, Point::new(15, 15)), None);
+    assert_eq!(a.overlap(Point::new(0, 0), &a, Point::new(20, 0)), Some(Point::new(20, 10)));
+  }
+
+  #[test]
+  fn test_wide_masks() {
+    // single pixels beyond the first 64 bits of the rows
+    let a = CollisionMask::from_fn(200, 2, |x, y| x == 150 && y == 1);
+    let b = CollisionMask::from_fn(100, 3, |x, y| x == 70 && y == 2);
+    assert!(a.is_solid(150, 1) && !a.is_solid(149, 1));
+    assert_eq!(a.overlap(Point::new(-5, 4), &b, Point::new(75, 3)), Some(Point::new(145, 5)));
+    assert_eq!(a.overlap(Point::new(-5, 4), &b, Point::new(76, 3)), None);
+  }
+
+  #[test]
+  fn test_rotated_and_trimmed_pixels() {
+    // a 3x2 sprite stored rotated as 2x3 at (10, 20)
+    let frame = Rect::new(10, 20, 2, 3);
+    assert_eq!(sheet_pixel(0, 0, frame, true, Point::new(0, 0)), Some((11, 20)));
+    assert_eq!(sheet_pixel(2, 1, frame, true, Point::new(0, 0)), Some((10, 22)));
+    // trimmed by 1 on the left
+    assert_eq!(sheet_pixel(0, 0, frame, false, Point::new(1, 0)), None);
+    assert_eq!(sheet_pixel(1, 0, frame, false, Point::new(1, 0)), Some((10, 20)));
+  }
+}
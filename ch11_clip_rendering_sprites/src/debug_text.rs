@@ -0,0 +1,153 @@
+// A tiny 3x5 pixel font drawn with fill_rect (cf. ch08), for the tools and debug overlays:
+// no font file, no SDL_ttf. Letters are upper case only, the unknown characters show as '?'.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+
+// 5 rows of 3 pixels, the leftmost being the highest bit
+fn glyph(c: char) -> [u8; 5]
+{
+  match c.to_ascii_uppercase()
+  {
+    ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+    '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+    '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+    '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+    '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+    '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+    '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+    '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+    '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+    '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+    '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+    'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+    'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+    'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+    'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+    'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+    'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+    'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+    'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+    'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+    'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+    'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+    'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+    'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+    'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+    'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+    'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+    'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+    'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+    'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+    'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+    'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+    'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+    'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+    'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+    'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+    'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+    '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+    ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+    ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+    ';' => [0b000, 0b010, 0b000, 0b010, 0b100],
+    '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+    '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+    '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+    '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+    '*' => [0b101, 0b010, 0b101, 0b000, 0b000],
+    '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+    '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+    '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+    '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+    ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+    '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+    ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+    '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+    '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+    '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+    '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+    '"' => [0b101, 0b101, 0b000, 0b000, 0b000],
+    '|' => [0b010, 0b010, 0b010, 0b010, 0b010],
+    _ => [0b110, 0b001, 0b010, 0b000, 0b010] // '?'
+  }
+}
+
+// Size of a (multiline) text, each font pixel being scale x scale
+pub fn debug_text_size(text: &str, scale: u32) -> (u32, u32)
+{
+  let columns = text.lines().map(|line| line.chars().count() as u32).max().unwrap_or(0);
+  let lines = text.lines().count() as u32;
+  if columns == 0 {
+    return (0, lines.saturating_sub(1) * LINE_HEIGHT * scale);
+  }
+  ((columns * ADVANCE - 1) * scale, (lines * LINE_HEIGHT - 2) * scale)
+}
+
+// The pixels of a text, as rects
+fn text_rects(text: &str, x: i32, y: i32, scale: u32) -> Vec<Rect>
+{
+  let s = scale as i32;
+  let mut rects = Vec::new();
+  for (row, line) in text.lines().enumerate() {
+    let top = y + row as i32 * (LINE_HEIGHT as i32 * s);
+    for (column, c) in line.chars().enumerate() {
+      let left = x + column as i32 * (ADVANCE as i32 * s);
+      for (gy, bits) in glyph(c).iter().enumerate() {
+        for gx in 0..GLYPH_WIDTH {
+          if bits & (0b100 >> gx) != 0 {
+            rects.push(Rect::new(left + gx as i32 * s, top + gy as i32 * s, scale, scale));
+          }
+        }
+      }
+    }
+  }
+  rects
+}
+
+// (x, y) is the top left corner of the text. Keeps the draw color of the canvas.
+pub fn draw_debug_text(canvas: &mut WindowCanvas, text: &str, x: i32, y: i32, scale: u32, color: Color) -> Result<(), String>
+{
+  let rects = text_rects(text, x, y, scale.max(1));
+  let previous = canvas.draw_color();
+  canvas.set_draw_color(color);
+  let result = canvas.fill_rects(&rects);
+  canvas.set_draw_color(previous);
+  result
+}
+
+// Same over a filled box, to be readable on anything
+pub fn draw_debug_label(canvas: &mut WindowCanvas, text: &str, x: i32, y: i32, scale: u32, color: Color, background: Color)
+  -> Result<(), String>
+{
+  let (w, h) = debug_text_size(text, scale.max(1));
+  let previous = canvas.draw_color();
+  canvas.set_draw_color(background);
+  canvas.fill_rect(Rect::new(x, y, w + 2 * scale.max(1), h + 2 * scale.max(1)))?;
+  canvas.set_draw_color(previous);
+  draw_debug_text(canvas, text, x + scale.max(1) as i32, y + scale.max(1) as i32, scale, color)
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_text_size_and_pixels() {
+    assert_eq!(debug_text_size("AB", 1), (7, 5));
+    assert_eq!(debug_text_size("AB\nC", 2), (14, 24));
+    // 'I' is 3 + 1 + 1 + 1 + 3 pixels, the second glyph starting 4 font pixels further
+    let rects = text_rects("aI", 10, 0, 2);
+    assert_eq!(rects.iter().filter(|r| r.x() >= 18).count(), 9);
+    assert!(rects.contains(&Rect::new(18, 0, 2, 2)));
+    assert_eq!(glyph('~'), glyph('?'));
+  }
+}
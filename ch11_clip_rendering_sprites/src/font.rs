@@ -0,0 +1,348 @@
+// TrueType text (the "ttf" cargo feature, which needs SDL2_ttf).
+//
+// Fonts are loaded once per (path, point size) by the FontManager, like images by the
+// TextureManager. Rendering a string with SDL_ttf is slow (a surface, then a texture), so the
+// TextRenderer keeps the textures of the strings it drew, keyed by font, size, style, color,
+// quality and text. A HUD redrawing the same labels every frame only pays for them once;
+// the least recently used strings are dropped when the cache is full.
+
+use crate::assets;
+use crate::text_layout::{layout_text, FontMetrics, LayoutOptions, Span, SpanStyle, TextLayout};
+use crate::texture::SharedTexture;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::ttf::{Font, FontStyle, Sdl2TtfContext};
+use sdl2::video::WindowContext;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub type SharedFont<'ttf> = Rc<RefCell<Font<'ttf, 'static>>>;
+
+pub struct FontManager<'ttf>
+{
+  context: &'ttf Sdl2TtfContext,
+  fonts: HashMap<(String, u16), SharedFont<'ttf>>
+}
+
+impl<'ttf> FontManager<'ttf>
+{
+  pub fn new(context: &'ttf Sdl2TtfContext) -> FontManager<'ttf>
+  {
+    FontManager { context, fonts: HashMap::new() }
+  }
+
+  pub fn load_font(&mut self, path: &str, point_size: u16) -> Result<SharedFont<'ttf>, String>
+  {
+    let key = (path.to_string(), point_size);
+    if !self.fonts.contains_key(&key) {
+      let font = assets::load_font(self.context, path, point_size)?;
+      self.fonts.insert(key.clone(), Rc::new(RefCell::new(font)));
+    }
+    Ok(Rc::clone(&self.fonts[&key]))
+  }
+
+  // The font is closed once the last Rc is dropped
+  pub fn unload_font(&mut self, path: &str, point_size: u16)
+  {
+    self.fonts.remove(&(path.to_string(), point_size));
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextQuality
+{
+  Solid,          // fast, no antialiasing, transparent background
+  Shaded(Color),  // antialiased over an opaque background color
+  Blended         // antialiased with alpha, the nicest and the slowest
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextStyle
+{
+  pub font: String, // path
+  pub size: u16,    // in points
+  pub color: Color,
+  pub quality: TextQuality,
+  pub font_style: FontStyle // bold, italic, underline, strikethrough
+}
+
+impl TextStyle
+{
+  pub fn new(font: &str, size: u16, color: Color) -> TextStyle
+  {
+    TextStyle { font: font.to_string(), size, color, quality: TextQuality::Blended, font_style: FontStyle::NORMAL }
+  }
+
+  pub fn with_quality(mut self, quality: TextQuality) -> TextStyle
+  {
+    self.quality = quality;
+    self
+  }
+
+  pub fn with_font_style(mut self, font_style: FontStyle) -> TextStyle
+  {
+    self.font_style = font_style;
+    self
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TextKey
+{
+  font: String,
+  size: u16,
+  font_style: i32,
+  color: Color,
+  quality: TextQuality,
+  text: String
+}
+
+impl TextKey
+{
+  fn new(style: &TextStyle, text: &str) -> TextKey
+  {
+    TextKey {
+      font: style.font.clone(),
+      size: style.size,
+      font_style: style.font_style.bits(),
+      color: style.color,
+      quality: style.quality,
+      text: text.to_string()
+    }
+  }
+}
+
+// A rendered string
+#[derive(Clone)]
+pub struct TextTexture<'a>
+{
+  pub texture: SharedTexture<'a>,
+  pub width: u32,
+  pub height: u32
+}
+
+// The strings rendered, the least recently used one dropped when a new one doesn't fit.
+// (Generic over what's kept, so that it can be tested without a renderer.)
+struct TextCache<V>
+{
+  entries: HashMap<TextKey, (V, u64)>, // (value, last use)
+  capacity: usize,
+  clock: u64 // bumped at each lookup
+}
+
+impl<V: Clone> TextCache<V>
+{
+  fn new(capacity: usize) -> TextCache<V>
+  {
+    TextCache { entries: HashMap::new(), capacity: capacity.max(1), clock: 0 }
+  }
+
+  fn get(&mut self, key: &TextKey) -> Option<V>
+  {
+    self.clock += 1;
+    let (value, last_used) = self.entries.get_mut(key)?;
+    *last_used = self.clock;
+    Some(value.clone())
+  }
+
+  fn insert(&mut self, key: TextKey, value: V)
+  {
+    if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+      let oldest = self.entries.iter().min_by_key(|(_, (_, last_used))| *last_used).map(|(key, _)| key.clone());
+      if let Some(oldest) = oldest {
+        self.entries.remove(&oldest);
+      }
+    }
+    self.entries.insert(key, (value, self.clock));
+  }
+}
+
+pub const DEFAULT_TEXT_CACHE_CAPACITY: usize = 256;
+
+pub struct TextRenderer<'a>
+{
+  texture_creator: &'a TextureCreator<WindowContext>,
+  cache: TextCache<TextTexture<'a>>
+}
+
+impl<'a> TextRenderer<'a>
+{
+  pub fn new(texture_creator: &'a TextureCreator<WindowContext>) -> TextRenderer<'a>
+  {
+    TextRenderer::with_capacity(texture_creator, DEFAULT_TEXT_CACHE_CAPACITY)
+  }
+
+  // capacity: number of strings kept as textures
+  pub fn with_capacity(texture_creator: &'a TextureCreator<WindowContext>, capacity: usize) -> TextRenderer<'a>
+  {
+    TextRenderer { texture_creator, cache: TextCache::new(capacity) }
+  }
+
+  // Texture of a string, rendered on the first call. Empty strings have no texture.
+  pub fn texture(&mut self, fonts: &mut FontManager, style: &TextStyle, text: &str) -> Result<Option<TextTexture<'a>>, String>
+  {
+    if text.is_empty() {
+      return Ok(None);
+    }
+    let key = TextKey::new(style, text);
+    if let Some(cached) = self.cache.get(&key) {
+      return Ok(Some(cached));
+    }
+
+    let font = fonts.load_font(&style.font, style.size)?;
+    let surface = {
+      let mut font = font.borrow_mut();
+      font.set_style(style.font_style);
+      let rendering = font.render(text);
+      let surface = match style.quality
+      {
+        TextQuality::Solid => rendering.solid(style.color),
+        TextQuality::Shaded(background) => rendering.shaded(style.color, background),
+        TextQuality::Blended => rendering.blended(style.color)
+      };
+      font.set_style(FontStyle::NORMAL); // the font is shared
+      surface.map_err(|e| e.to_string())?
+    };
+    let texture = surface.as_texture(self.texture_creator).map_err(|e| e.to_string())?;
+    let text = TextTexture { texture: Rc::new(RefCell::new(texture)), width: surface.width(), height: surface.height() };
+
+    self.cache.insert(key, text.clone());
+    Ok(Some(text))
+  }
+
+  // Draws the string with its top left corner at (x, y), and gives its size
+  pub fn render(&mut self, canvas: &mut WindowCanvas, fonts: &mut FontManager, style: &TextStyle,
+    text: &str, x: i32, y: i32) -> Result<(u32, u32), String>
+  {
+    match self.texture(fonts, style, text)?
+    {
+      Some(text) =>
+      {
+        canvas.copy(&text.texture.borrow(), None, Rect::new(x, y, text.width, text.height))?;
+        Ok((text.width, text.height))
+      },
+      None => Ok((0, 0))
+    }
+  }
+
+  // Size the string would take, without rendering it (nothing cached: no renderer needed)
+  pub fn size_of(fonts: &mut FontManager, style: &TextStyle, text: &str) -> Result<(u32, u32), String>
+  {
+    let font = fonts.load_font(&style.font, style.size)?;
+    let mut font = font.borrow_mut();
+    font.set_style(style.font_style);
+    let size = font.size_of(text).map_err(|e| e.to_string());
+    font.set_style(FontStyle::NORMAL);
+    size
+  }
+
+  // Lays out styled text (text_layout.rs) in a box of `size`, with the font of `base`
+  pub fn layout(fonts: &mut FontManager, base: &TextStyle, spans: &[Span], size: (u32, u32), options: &LayoutOptions)
+    -> Result<TextLayout, String>
+  {
+    let font = fonts.load_font(&base.font, base.size)?;
+    let metrics = TtfMetrics { font: &font, base: base.font_style };
+    Ok(layout_text(&metrics, spans, size, options))
+  }
+
+  // Draws a layout made with the font of `base`, (x, y) being the top left corner of its box
+  pub fn render_layout(&mut self, canvas: &mut WindowCanvas, fonts: &mut FontManager, base: &TextStyle,
+    layout: &TextLayout, x: i32, y: i32) -> Result<(), String>
+  {
+    for run in &layout.runs {
+      let style = TextStyle {
+        color: run.style.color.unwrap_or(base.color),
+        font_style: span_font_style(base.font_style, &run.style),
+        ..base.clone()
+      };
+      self.render(canvas, fonts, &style, &run.text, x + run.x, y + run.y)?;
+    }
+    Ok(())
+  }
+
+  pub fn cached_count(&self) -> usize { self.cache.entries.len() }
+
+  // E.g. after a resolution change, or when leaving a screen full of one-off strings
+  pub fn clear(&mut self)
+  {
+    self.cache.entries.clear();
+  }
+}
+
+// The markup styles on top of the base one
+fn span_font_style(base: FontStyle, span: &SpanStyle) -> FontStyle
+{
+  let mut style = base;
+  style.set(FontStyle::BOLD, span.bold || base.contains(FontStyle::BOLD));
+  style.set(FontStyle::ITALIC, span.italic || base.contains(FontStyle::ITALIC));
+  style.set(FontStyle::UNDERLINE, span.underline || base.contains(FontStyle::UNDERLINE));
+  style.set(FontStyle::STRIKETHROUGH, span.strikethrough || base.contains(FontStyle::STRIKETHROUGH));
+  style
+}
+
+struct TtfMetrics<'f, 'ttf>
+{
+  font: &'f SharedFont<'ttf>,
+  base: FontStyle
+}
+
+impl FontMetrics for TtfMetrics<'_, '_>
+{
+  fn text_width(&self, text: &str, style: &SpanStyle) -> u32
+  {
+    let mut font = self.font.borrow_mut();
+    font.set_style(span_font_style(self.base, style));
+    let width = font.size_of(text).map_or(0, |(w, _)| w);
+    font.set_style(FontStyle::NORMAL);
+    width
+  }
+
+  fn line_height(&self) -> u32
+  {
+    self.font.borrow().recommended_line_spacing().max(0) as u32
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn key(text: &str) -> TextKey {
+    TextKey::new(&TextStyle::new("data/font.ttf", 24, Color::WHITE), text)
+  }
+
+  #[test]
+  fn test_least_recently_used_dropped() {
+    let mut cache = TextCache::new(2);
+    cache.insert(key("a"), 1);
+    cache.insert(key("b"), 2);
+    assert_eq!(cache.get(&key("a")), Some(1)); // b is now the oldest
+    cache.insert(key("c"), 3);
+    assert_eq!((cache.get(&key("b")), cache.get(&key("a")), cache.get(&key("c"))), (None, Some(1), Some(3)));
+    cache.insert(key("c"), 4); // already there: nothing dropped
+    assert_eq!((cache.entries.len(), cache.get(&key("a")), cache.get(&key("c"))), (2, Some(1), Some(4)));
+  }
+
+  #[test]
+  fn test_cache_keys() {
+    let style = TextStyle::new("data/font.ttf", 24, Color::WHITE);
+    assert_eq!(TextKey::new(&style, "Score"), TextKey::new(&style.clone(), "Score"));
+    for other in [
+      TextStyle::new("data/other.ttf", 24, Color::WHITE),
+      TextStyle::new("data/font.ttf", 12, Color::WHITE),
+      TextStyle::new("data/font.ttf", 24, Color::RED),
+      style.clone().with_quality(TextQuality::Shaded(Color::BLACK)),
+      style.clone().with_font_style(FontStyle::BOLD)
+    ] {
+      assert_ne!(TextKey::new(&style, "Score"), TextKey::new(&other, "Score"), "{:?}", other);
+    }
+    assert_ne!(TextKey::new(&style, "Score"), TextKey::new(&style, "score"));
+  }
+}
@@ -0,0 +1,319 @@
+// Importers for the json written by TexturePacker ("JSON (Hash)" and "JSON (Array)") and by
+// Aseprite (File > Export Sprite Sheet, hash or array, with frame tags), converted to our own
+// SpriteSheetData + animations. So the artists' exports can be used without writing meta.json.
+//
+// Both tools share the same base format:
+// { "frames": { "hero 0.png": { "frame": {x,y,w,h}, "rotated", "trimmed", "spriteSourceSize", "sourceSize", ... } },
+//   "meta": { "image": "hero.png", ... } }
+// Aseprite adds a "duration" per frame and "meta.frameTags" (the animations); the pixi flavour
+// of TexturePacker adds "animations": { "walk": ["walk_01.png", ...] }.
+
+use crate::animation::{AnimationData, AnimationMode, FrameData};
+use crate::assets::read_asset;
+use crate::sprite::{PivotData, SpriteData, SpriteKey, SpriteSheetData, TrimData};
+
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+// TexturePacker doesn't give frame durations
+pub const DEFAULT_FRAME_DURATION: u32 = 100;
+
+#[derive(Deserialize)]
+struct XRect
+{
+  x: i32,
+  y: i32,
+  w: u32,
+  h: u32
+}
+
+#[derive(Deserialize)]
+struct XSize
+{
+  w: u32,
+  h: u32
+}
+
+#[derive(Deserialize)]
+struct XPoint
+{
+  x: f64,
+  y: f64
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct XFrame
+{
+  #[serde(default)]
+  filename: Option<String>, // array flavour only
+  frame: XRect,
+  #[serde(default)]
+  rotated: bool,
+  #[serde(default)]
+  trimmed: bool,
+  sprite_source_size: Option<XRect>,
+  source_size: Option<XSize>,
+  pivot: Option<XPoint>,  // normalized
+  duration: Option<u32>   // Aseprite
+}
+
+// Frames as a hash or as an array, in the order of the file: the order matters for Aseprite's
+// tags, which refer to frame indices (and serde_json's maps are sorted by key).
+struct XFrames(Vec<(String, XFrame)>);
+
+impl<'de> Deserialize<'de> for XFrames
+{
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<XFrames, D::Error>
+  {
+    struct FramesVisitor;
+
+    impl<'de> Visitor<'de> for FramesVisitor
+    {
+      type Value = XFrames;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result
+      {
+        f.write_str("a map or an array of frames")
+      }
+
+      fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<XFrames, A::Error>
+      {
+        let mut frames = Vec::new();
+        while let Some(entry) = map.next_entry::<String, XFrame>()? {
+          frames.push(entry);
+        }
+        Ok(XFrames(frames))
+      }
+
+      fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<XFrames, A::Error>
+      {
+        let mut frames = Vec::new();
+        while let Some(frame) = seq.next_element::<XFrame>()? {
+          let name = frame.filename.clone()
+            .ok_or_else(|| serde::de::Error::custom("frame without a filename"))?;
+          frames.push((name, frame));
+        }
+        Ok(XFrames(frames))
+      }
+    }
+
+    deserializer.deserialize_any(FramesVisitor)
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct XTag
+{
+  name: String,
+  from: usize,
+  to: usize,
+  #[serde(default)]
+  direction: String,                 // forward, reverse, pingpong, pingpong_reverse
+  repeat: Option<serde_json::Value>  // recent versions, "0" or absent: forever
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct XMeta
+{
+  image: String, // relative to the json
+  #[serde(default)]
+  frame_tags: Vec<XTag>
+}
+
+#[derive(Deserialize)]
+struct XSheet
+{
+  frames: XFrames,
+  meta: XMeta,
+  #[serde(default)]
+  animations: BTreeMap<String, Vec<String>> // pixi flavour of TexturePacker
+}
+
+// "hero 0.aseprite" -> "hero 0", "walk/01.png" -> "walk/01"
+fn sprite_name(filename: &str) -> &str
+{
+  const EXTENSIONS: [&str; 6] = [".png", ".aseprite", ".ase", ".gif", ".bmp", ".jpg"];
+  EXTENSIONS.iter()
+    .find_map(|ext| filename.len().checked_sub(ext.len())
+      .filter(|&i| filename.is_char_boundary(i) && filename[i..].eq_ignore_ascii_case(ext))
+      .map(|i| &filename[..i]))
+    .unwrap_or(filename)
+}
+
+// Names become keys through serde: any String works, and SpriteName when the names are in the enum
+fn to_key<N: SpriteKey>(name: &str) -> Result<N, String>
+{
+  serde_json::from_value(serde_json::Value::String(name.to_string()))
+    .map_err(|_| format!("sprite name \"{}\" isn't a valid key", name))
+}
+
+fn convert_frame<N: SpriteKey>(name: &str, frame: &XFrame) -> Result<SpriteData<N>, String>
+{
+  let trim = match (&frame.sprite_source_size, &frame.source_size) {
+    (Some(pos), Some(size)) if frame.trimmed => Some(TrimData { x: pos.x, y: pos.y, w: size.w, h: size.h }),
+    _ => None
+  };
+  let (w, h) = trim.map_or((frame.frame.w, frame.frame.h), |t| (t.w, t.h));
+  let pivot = frame.pivot.as_ref().map(|p| PivotData {
+    x: (p.x * w as f64).round() as i32,
+    y: (p.y * h as f64).round() as i32
+  });
+
+  Ok(SpriteData {
+    name: to_key(name)?,
+    x: frame.frame.x,
+    y: frame.frame.y,
+    w: frame.frame.w, // upright size, even when rotated
+    h: frame.frame.h,
+    pivot,
+    color_key: None,
+    rotated: frame.rotated,
+    trim,
+    nine_slice: None
+  })
+}
+
+fn tag_to_animation<N: SpriteKey>(tag: &XTag, frames: &[(String, XFrame)]) -> Result<AnimationData<N>, String>
+{
+  if tag.from > tag.to || tag.to >= frames.len() {
+    return Err(format!("tag {}: frames {} to {} out of range", tag.name, tag.from, tag.to));
+  }
+
+  let mut indices: Vec<usize> = (tag.from..=tag.to).collect();
+  if tag.direction.starts_with("reverse") || tag.direction == "pingpong_reverse" {
+    indices.reverse();
+  }
+  let repeat = tag.repeat.as_ref().and_then(|r| match r {
+    serde_json::Value::String(s) => s.parse::<u32>().ok(),
+    other => other.as_u64().map(|n| n as u32)
+  });
+  let mode = if tag.direction.starts_with("pingpong") { AnimationMode::PingPong }
+    else if repeat == Some(1) { AnimationMode::Once }
+    else { AnimationMode::Loop };
+
+  let frames = indices.iter()
+    .map(|&i| Ok(FrameData {
+      sprite: to_key(sprite_name(&frames[i].0))?,
+      duration: frames[i].1.duration.unwrap_or(DEFAULT_FRAME_DURATION)
+    }))
+    .collect::<Result<Vec<_>, String>>()?;
+  Ok(AnimationData { name: tag.name.clone(), mode, frames })
+}
+
+// TexturePacker (pixi): file names, without durations
+fn list_to_animation<N: SpriteKey>(name: &str, filenames: &[String]) -> Result<AnimationData<N>, String>
+{
+  let frames = filenames.iter()
+    .map(|f| Ok(FrameData { sprite: to_key(sprite_name(f))?, duration: DEFAULT_FRAME_DURATION }))
+    .collect::<Result<Vec<_>, String>>()?;
+  Ok(AnimationData { name: name.to_string(), mode: AnimationMode::Loop, frames })
+}
+
+// json_dir: the directory of the json, the image path being relative to it
+fn parse_sheet<N: SpriteKey>(json: &[u8], json_dir: &Path) -> Result<SpriteSheetData<N>, String>
+{
+  let sheet: XSheet = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+
+  let sprites = sheet.frames.0.iter()
+    .map(|(filename, frame)| convert_frame(sprite_name(filename), frame))
+    .collect::<Result<Vec<_>, String>>()?;
+
+  // Only the animations differ: Aseprite's tags, or the lists of the pixi flavour
+  let mut animations = sheet.meta.frame_tags.iter()
+    .map(|tag| tag_to_animation(tag, &sheet.frames.0))
+    .collect::<Result<Vec<_>, String>>()?;
+  for (name, filenames) in &sheet.animations {
+    animations.push(list_to_animation(name, filenames)?);
+  }
+
+  // Our paths are relative to the working directory, with forward slashes (cf. assets.rs)
+  let spritesheet = json_dir.join(&sheet.meta.image).to_string_lossy().replace('\\', "/");
+  Ok(SpriteSheetData { spritesheet, color_key: None, sprites, animations })
+}
+
+// A TexturePacker ("JSON (Hash)" or "JSON (Array)") or Aseprite ("Hash" or "Array") export.
+// Sprites are named after the frame file names without extension, e.g. "hero 0"; Aseprite's
+// frame tags become animations, direction and durations included.
+pub fn import_sprite_sheet<N: SpriteKey>(json_path: &str) -> Result<SpriteSheetData<N>, String>
+{
+  let bytes = read_asset(json_path)?;
+  let json_dir = Path::new(json_path).parent().unwrap_or(Path::new(""));
+  parse_sheet(&bytes, json_dir).map_err(|e| format!("{}: {}", json_path, e))
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  const TEXTURE_PACKER_HASH: &str = r#"{
+    "frames": {
+      "zombie.png": { "frame": {"x":0,"y":0,"w":30,"h":50}, "rotated": true, "trimmed": true,
+        "spriteSourceSize": {"x":2,"y":4,"w":30,"h":50}, "sourceSize": {"w":40,"h":60}, "pivot": {"x":0.5,"y":1} },
+      "apple.png": { "frame": {"x":50,"y":0,"w":16,"h":16}, "rotated": false, "trimmed": false,
+        "spriteSourceSize": {"x":0,"y":0,"w":16,"h":16}, "sourceSize": {"w":16,"h":16} }
+    },
+    "meta": { "image": "sheet.png", "size": {"w":66,"h":30} }
+  }"#;
+
+  const ASEPRITE_ARRAY: &str = r#"{
+    "frames": [
+      { "filename": "hero 0.aseprite", "frame": {"x":0,"y":0,"w":16,"h":16}, "rotated": false, "trimmed": false,
+        "spriteSourceSize": {"x":0,"y":0,"w":16,"h":16}, "sourceSize": {"w":16,"h":16}, "duration": 100 },
+      { "filename": "hero 1.aseprite", "frame": {"x":16,"y":0,"w":16,"h":16}, "rotated": false, "trimmed": false,
+        "spriteSourceSize": {"x":0,"y":0,"w":16,"h":16}, "sourceSize": {"w":16,"h":16}, "duration": 150 },
+      { "filename": "hero 2.aseprite", "frame": {"x":32,"y":0,"w":16,"h":16}, "rotated": false, "trimmed": false,
+        "spriteSourceSize": {"x":0,"y":0,"w":16,"h":16}, "sourceSize": {"w":16,"h":16}, "duration": 200 }
+    ],
+    "meta": { "app": "https://www.aseprite.org/", "image": "hero.png",
+      "frameTags": [ { "name": "walk", "from": 0, "to": 2, "direction": "pingpong" },
+                     { "name": "back", "from": 1, "to": 2, "direction": "reverse", "repeat": "1" } ] }
+  }"#;
+
+  #[test]
+  fn test_texture_packer_hash() {
+    let sheet: SpriteSheetData<String> = parse_sheet(TEXTURE_PACKER_HASH.as_bytes(), Path::new("data")).unwrap();
+    assert_eq!(sheet.spritesheet(), "data/sheet.png");
+    // the order of the file is kept
+    let names: Vec<&str> = sheet.sprites().iter().map(|s| s.name().as_str()).collect();
+    assert_eq!(names, vec!["zombie", "apple"]);
+
+    let zombie = &sheet.sprites()[0];
+    assert!(zombie.is_rotated());
+    assert_eq!((zombie.rect().width(), zombie.rect().height()), (50, 30)); // as stored in the sheet
+    let trim = zombie.trim().unwrap();
+    assert_eq!((trim.offset.x(), trim.offset.y(), trim.source_size), (2, 4, (40, 60)));
+    assert_eq!(zombie.pivot().map(|p| (p.x(), p.y())), Some((20, 60)));
+    assert!(sheet.sprites()[1].trim().is_none());
+  }
+
+  #[test]
+  fn test_aseprite_tags() {
+    let sheet: SpriteSheetData<String> = parse_sheet(ASEPRITE_ARRAY.as_bytes(), Path::new("")).unwrap();
+    assert_eq!(sheet.spritesheet(), "hero.png");
+    assert_eq!(sheet.sprites()[2].name(), "hero 2");
+
+    let walk = &sheet.animations()[0];
+    assert_eq!(walk.mode, AnimationMode::PingPong);
+    assert_eq!(walk.frames.iter().map(|f| f.duration).collect::<Vec<_>>(), vec![100, 150, 200]);
+
+    let back = &sheet.animations()[1];
+    assert_eq!(back.mode, AnimationMode::Once);
+    assert_eq!(back.frames.iter().map(|f| f.sprite.as_str()).collect::<Vec<_>>(), vec!["hero 2", "hero 1"]);
+  }
+
+  #[test]
+  fn test_tag_out_of_range() {
+    let json = ASEPRITE_ARRAY.replace(r#""to": 2, "direction": "pingpong""#, r#""to": 3, "direction": "pingpong""#);
+    assert!(parse_sheet::<String>(json.as_bytes(), Path::new("")).is_err());
+  }
+}
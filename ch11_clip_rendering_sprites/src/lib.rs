@@ -0,0 +1,32 @@
+// The sprite machinery lives in a library so that the demo (main.rs) and the tools (src/bin)
+// share the very same code and json format.
+extern crate sdl2;
+
+pub mod animation;
+pub mod assets;
+pub mod batch;
+pub mod bitmap_font;
+pub mod atlas;
+pub mod camera;
+pub mod collision;
+pub mod debug_text;
+pub mod errors;
+#[cfg(feature = "ttf")]
+pub mod font;
+pub mod import;
+pub mod nine_slice;
+pub mod parallax;
+pub mod particles;
+pub mod registry;
+pub mod render_target;
+pub mod slicing;
+pub mod sprite;
+pub mod sprite_names;
+pub mod streaming_texture;
+pub mod text_layout;
+pub mod texture;
+pub mod tile_map;
+pub mod tiled;
+pub mod tween;
+
+use errors::*;
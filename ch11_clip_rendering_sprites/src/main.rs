@@ -1,9 +1,5 @@
 extern crate sdl2; 
 
-mod errors;
-mod texture;
-mod sprite;
-
 use sdl2::Sdl;
 use sdl2::EventPump;
 use sdl2::IntegerOrSdlError;
@@ -12,13 +8,22 @@ use sdl2::VideoSubsystem;
 use sdl2::video::Window;
 use sdl2::event::Event;
 use sdl2::pixels::Color;
-use sdl2::render::Canvas;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+
+use std::time::{Duration, Instant};
 
-use errors::*;
-use sprite::create_sprites;
-use sprite::load_sprites_from_json;
-use sprite::SpriteName::*;
-use texture::TextureManager;
+use ch11_clip_rendering_sprites::animation::AnimatedSprite;
+use ch11_clip_rendering_sprites::batch::SpriteBatch;
+use ch11_clip_rendering_sprites::errors::*;
+use ch11_clip_rendering_sprites::render_target::RenderTarget;
+use ch11_clip_rendering_sprites::nine_slice::NineSlice;
+use ch11_clip_rendering_sprites::particles::{load_emitter_settings, ParticleEmitter};
+use ch11_clip_rendering_sprites::registry::SpriteRegistry;
+use ch11_clip_rendering_sprites::sprite::RenderEx;
+use ch11_clip_rendering_sprites::sprite::SpriteName::*;
+use ch11_clip_rendering_sprites::texture::TextureManager;
+use ch11_clip_rendering_sprites::tween::{Easing, Parallel, Repeat, Sequence, Tween, TweenedSprite, Tweener};
 
 // To group initializations, mainly for readability: I may group them differently in the future.
 // ... maybe in a single struct with the different contexts ...
@@ -67,28 +72,132 @@ fn main() -> Result<(), String>
 
   let texture_creator = canvas.texture_creator();
   let mut texture_manager = TextureManager::new();
-  let sprite_data = load_sprites_from_json("data/meta.json");
-  let sprites = create_sprites(&texture_creator, sprite_data, &mut texture_manager);
+  let mut sprites = SpriteRegistry::new();
+  sprites.load(&texture_creator, &mut texture_manager, "data/meta.json")
+    .unwrap_or_else(|e| { prompt_err_and_panic("SpriteRegistry::load failed", &e, None); });
+  let mut cycling = AnimatedSprite::new(sprites.animation("CycleColors").unwrap());
+
+  // Other instances of the same sprites, with their own color modulation / alpha / blend mode
+  let mut ghost = sprites[&BlueCircle].clone();
+  ghost.set_blend_mode(BlendMode::Blend);
+  ghost.set_alpha(0x60);
+  let mut tinted = sprites[&RedCircle].clone();
+  tinted.set_color(Color::RGB(0x80, 0x80, 0xFF));
+  let mut glowing = sprites[&YellowCircle].clone();
+  glowing.set_blend_mode(BlendMode::Add);
+  
+  // The green circle, cut in 9 (insets in meta.json), frames a rectangle of any size: a pill
+  let pill = NineSlice::from_sprite(&sprites[&GreenCircle])
+    .unwrap_or_else(|e| { prompt_err_and_panic("NineSlice creation failed", &e, None); });
+  
+  // Procedural texture, rewritten every frame: a moving "heat" wave
+  let heat_map = texture_manager.create_streaming_texture(&texture_creator, "heat_map", 100, 100);
+  let mut heat_pixels = vec![Color::BLACK; 100 * 100];
+  let mut heat_phase = 0.0f32;
+  
+  // Rotation around the pivot declared in meta.json (bottom of the red circle), at half size, mirrored
+  let mut rotation = RenderEx { scale: (0.5, 0.5), flip_horizontal: true, ..RenderEx::default() };
 
-  canvas.set_draw_color(Color::RGBA(0xFF, 0xFF, 0xFF, 0xFF)); // white, won't change this time
+  // Offscreen composition: the four circles in a frame, drawn once and then copied as a whole
+  let mut panel = RenderTarget::new(&texture_creator, 220, 220)
+    .unwrap_or_else(|e| { prompt_err_and_panic("RenderTarget creation failed", &e, None); });
+
+  canvas.set_draw_color(Color::RGBA(0xFF, 0xFF, 0xFF, 0xFF)); // white
+  
+  // Draws sorted by layer and z rather than call order
+  let mut batch = SpriteBatch::new();
+  let mut batch_stats = batch.stats();
+  
+  // A fountain of sparks (data/particles.json), the yellow circle drawn small, tinted and additive
+  let emitters = load_emitter_settings("data/particles.json")
+    .unwrap_or_else(|e| { prompt_err_and_panic("load_emitter_settings failed", &e, None); });
+  let mut fountain = ParticleEmitter::new(&emitters["Fountain"], sprites.sprites())
+    .unwrap_or_else(|e| { prompt_err_and_panic("ParticleEmitter creation failed", &e, None); });
+  fountain.set_position(860.0, 560.0);
+  fountain.start();
+  
+  // A blue circle popping in, bouncing down and vanishing, over and over
+  let mut bouncing = TweenedSprite::new(sprites[&BlueCircle].clone(), 800.0, 20.0);
+  bouncing.sprite_mut().set_blend_mode(BlendMode::Blend);
+  let mut tweener = Tweener::new();
+  tweener.add(Sequence::new()
+    .then(Parallel::new()
+      .with(Tween::new(&bouncing.position, (800.0, 20.0), Duration::ZERO))
+      .with(Tween::new(&bouncing.alpha, 0xFF, Duration::from_millis(300)).from(0))
+      .with(Tween::new(&bouncing.scale, (1.0, 1.0), Duration::from_millis(600)).from((0.2, 0.2)).with_easing(Easing::ElasticOut)))
+    .then(Tween::new(&bouncing.position, (800.0, 150.0), Duration::from_secs(1)).with_easing(Easing::BounceOut))
+    .wait(Duration::from_millis(500))
+    .then(Parallel::new()
+      .with(Tween::new(&bouncing.alpha, 0, Duration::from_millis(400)))
+      .with(Tween::new(&bouncing.angle, 90.0, Duration::from_millis(400)).from(0.0).with_easing(Easing::QuadIn)))
+    .with_repeat(Repeat::Forever));
+  
+  let mut last_frame = Instant::now();
   
   'game : loop 
   {
+    let dt = last_frame.elapsed();
+    last_frame = Instant::now();
+    
     for event in event_pump.poll_iter() 
     {
       match event 
       {
         Event::Quit {..} => { break 'game; },
-        _ => {}
+        _ => { panel.handle_event(&event); }
       }
     }
     
+    // (only draws the first time, and again if the renderer lost the target's content)
+    panel.draw_if_needed(&mut canvas, |c| {
+      sprites[&RedCircle].render(c, 10, 10);
+      sprites[&GreenCircle].render(c, 110, 10);
+      sprites[&YellowCircle].render(c, 10, 110);
+      sprites[&BlueCircle].render(c, 110, 110);
+      c.set_draw_color(Color::RGBA(0, 0, 0, 0xFF));
+      c.draw_rect(Rect::new(0, 0, 220, 220)).unwrap();
+    })?;
+    
+    canvas.set_draw_color(Color::RGBA(0xFF, 0xFF, 0xFF, 0xFF));
     canvas.clear();
     
-    sprites[&RedCircle].render(&mut canvas, 0, 50);
-    sprites[&GreenCircle].render(&mut canvas, 100, 50);
-    sprites[&YellowCircle].render(&mut canvas, 200, 50);
-    sprites[&BlueCircle].render(&mut canvas, 300, 50);
+    // (submitted in reverse, yet drawn left to right: each one is above its left neighbour)
+    batch.draw(&sprites[&BlueCircle], 270, 50, 0, 3.0);
+    batch.draw(&sprites[&YellowCircle], 180, 50, 0, 2.0);
+    batch.draw(&sprites[&GreenCircle], 90, 50, 0, 1.0);
+    batch.draw(&sprites[&RedCircle], 0, 50, 0, 0.0);
+    if batch.flush(&mut canvas) != batch_stats {
+      batch_stats = batch.stats();
+      canvas.window_mut().set_title(&format!("MatouTest - {} draws, {} texture switches", 
+        batch_stats.draws, batch_stats.texture_switches)).map_err(|e| e.to_string())?;
+    }
+    ghost.render(&mut canvas, 0, 300);
+    tinted.render(&mut canvas, 100, 300);
+    glowing.render(&mut canvas, 150, 300); // overlapping the tinted one
+    
+    rotation.angle = (rotation.angle + 2.0) % 360.0;
+    sprites[&RedCircle].render_ex(&mut canvas, 375, 300, &rotation);
+    
+    heat_phase += 0.05;
+    for (i, pixel) in heat_pixels.iter_mut().enumerate() {
+      let (x, y) = ((i % 100) as f32, (i / 100) as f32);
+      let heat = (((x + y) * 0.06 + heat_phase).sin() * 0.5 + 0.5) * 255.0;
+      *pixel = Color::RGB(heat as u8, 0, 0xFF - heat as u8);
+    }
+    heat_map.upload(&heat_pixels)?;
+    canvas.copy(&heat_map.texture().borrow(), None, Rect::new(500, 300, 100, 100))?;
+    
+    cycling.update(dt);
+    cycling.render(&mut canvas, 650, 300);
+    
+    pill.render(&mut canvas, Rect::new(0, 450, 400, 100));
+    panel.render(&mut canvas, 500, 50);
+    
+    fountain.update(dt);
+    fountain.render(&mut canvas);
+    
+    tweener.update(dt);
+    bouncing.render(&mut canvas);
     
     canvas.present(); 
   }
@@ -0,0 +1,159 @@
+// Nine-slice sprites: one small panel graphic, cut in 3x3 by its border insets, frames a
+// rectangle of any size. The corners are drawn as they are, the edges are stretched or tiled
+// along the border, and the center fills the rest.
+//
+// Insets are declared per sprite in the json:
+// { "name": "Panel", "x": 0, "y": 0, "w": 48, "h": 48, "nine_slice": { "left": 8, "right": 8, "top": 8, "bottom": 8 } }
+
+use crate::sprite::{with_style, Sprite, SpriteName};
+
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct Insets
+{
+  pub left: u32,
+  pub right: u32,
+  pub top: u32,
+  pub bottom: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceFill
+{
+  Stretch,
+  Tile // repeated at natural size, the last tile being cut
+}
+
+// One axis of the 3x3 grid: (source start, source length, destination start, destination length)
+// for the three slices. When the destination is smaller than both borders, the borders shrink
+// proportionally and the middle disappears.
+type AxisSlices = [(i32, u32, i32, u32); 3];
+
+fn axis_slices(src_start: i32, src_len: u32, before: u32, after: u32, dst_start: i32, dst_len: u32) -> AxisSlices
+{
+  let (dst_before, dst_after) = if before + after <= dst_len {
+    (before, after)
+  }
+  else {
+    let shrunk = (before as u64 * dst_len as u64 / (before + after) as u64) as u32;
+    (shrunk, dst_len - shrunk)
+  };
+
+  [
+    (src_start, before, dst_start, dst_before),
+    (src_start + before as i32, src_len - before - after, dst_start + dst_before as i32, dst_len - dst_before - dst_after),
+    (src_start + (src_len - after) as i32, after, dst_start + (dst_len - dst_after) as i32, dst_after)
+  ]
+}
+
+pub struct NineSlice<'a, N = SpriteName>
+{
+  sprite: Sprite<'a, N>,
+  insets: Insets,
+  edges: SliceFill,
+  center: SliceFill
+}
+
+impl<'a, N: Clone> NineSlice<'a, N>
+{
+  // The sprite's style (color, alpha, blend mode) applies to the whole panel
+  pub fn new(sprite: &Sprite<'a, N>, insets: Insets) -> Result<NineSlice<'a, N>, String>
+  {
+    if sprite.is_rotated() || sprite.trim().is_some() {
+      return Err("nine-slice sprites can't be rotated or trimmed in the sheet".to_string());
+    }
+    let rect = sprite.src_rect();
+    if insets.left + insets.right > rect.width() || insets.top + insets.bottom > rect.height() {
+      return Err(format!("nine-slice insets {:?} larger than the sprite ({}x{})", insets, rect.width(), rect.height()));
+    }
+    Ok(NineSlice { sprite: sprite.clone(), insets, edges: SliceFill::Stretch, center: SliceFill::Stretch })
+  }
+
+  // With the insets declared in the json
+  pub fn from_sprite(sprite: &Sprite<'a, N>) -> Result<NineSlice<'a, N>, String>
+  {
+    let insets = sprite.nine_slice().ok_or("sprite without nine_slice insets")?;
+    NineSlice::new(sprite, insets)
+  }
+
+  pub fn with_fill(mut self, edges: SliceFill, center: SliceFill) -> NineSlice<'a, N>
+  {
+    self.edges = edges;
+    self.center = center;
+    self
+  }
+
+  pub fn insets(&self) -> Insets { self.insets }
+
+  pub fn render(&self, canvas: &mut WindowCanvas, dest_rect: Rect)
+  {
+    let src = self.sprite.src_rect();
+    let columns = axis_slices(src.x(), src.width(), self.insets.left, self.insets.right, dest_rect.x(), dest_rect.width());
+    let rows = axis_slices(src.y(), src.height(), self.insets.top, self.insets.bottom, dest_rect.y(), dest_rect.height());
+
+    with_style(self.sprite.texture(), &self.sprite.style(), |tex| {
+      for (j, &(sy, sh, dy, dh)) in rows.iter().enumerate() {
+        for (i, &(sx, sw, dx, dw)) in columns.iter().enumerate() {
+          if sw == 0 || sh == 0 || dw == 0 || dh == 0 {
+            continue; // (sdl2's Rect can't be empty)
+          }
+          // corners are never tiled: their destination is their size, or shrunk
+          let fill = match (i, j) {
+            (1, 1) => self.center,
+            (1, _) | (_, 1) => self.edges,
+            _ => SliceFill::Stretch
+          };
+          let src_cell = Rect::new(sx, sy, sw, sh);
+          match fill
+          {
+            SliceFill::Stretch => { canvas.copy(tex, src_cell, Rect::new(dx, dy, dw, dh)).unwrap(); },
+            SliceFill::Tile =>
+            {
+              // edges only tile along the border, the other axis is stretched
+              let tile_w = if i == 1 { sw } else { dw };
+              let tile_h = if j == 1 { sh } else { dh };
+              let mut y = 0;
+              while y < dh {
+                let h = tile_h.min(dh - y);
+                let mut x = 0;
+                while x < dw {
+                  let w = tile_w.min(dw - x);
+                  // a cut tile takes the matching part of the source
+                  let src_w = if i == 1 { w } else { sw };
+                  let src_h = if j == 1 { h } else { sh };
+                  canvas.copy(tex, Rect::new(sx, sy, src_w, src_h), Rect::new(dx + x as i32, dy + y as i32, w, h)).unwrap();
+                  x += w;
+                }
+                y += h;
+              }
+            }
+          }
+        }
+      }
+    });
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_axis_slices() {
+    // 48 px sprite at x = 100 with 8 px borders, drawn on 200 px at x = 10
+    let slices = axis_slices(100, 48, 8, 8, 10, 200);
+    assert_eq!(slices, [(100, 8, 10, 8), (108, 32, 18, 184), (140, 8, 202, 8)]);
+  }
+
+  #[test]
+  fn test_axis_slices_smaller_than_borders() {
+    let slices = axis_slices(0, 48, 10, 30, 0, 20);
+    assert_eq!(slices, [(0, 10, 0, 5), (10, 8, 5, 0), (18, 30, 5, 15)]);
+  }
+}
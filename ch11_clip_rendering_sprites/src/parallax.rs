@@ -0,0 +1,153 @@
+// Parallax scrolling backgrounds (cf. the lazyfoo scrolling background lesson): ch10 copied one
+// background to the whole window, here layers of textures scroll with the camera, the far ones
+// slower than the near ones, each repeated as needed to fill the viewport.
+
+use crate::camera::Camera2D;
+use crate::texture::SharedTexture;
+
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+#[derive(Clone)]
+pub struct ParallaxLayer<'a>
+{
+  texture: SharedTexture<'a>,
+  size: (u32, u32),
+  scroll_factor: (f32, f32), // 0: fixed on screen (the sky), 1: moves with the world, > 1: foreground
+  repeat: (bool, bool),
+  offset: (f32, f32)         // where the texture is when the camera is at the world origin
+}
+
+impl<'a> ParallaxLayer<'a>
+{
+  // E.g. with texture_manager.load_texture(&texture_creator, "data/hills.png", None).
+  // Repeated on both axes, moving with the world.
+  pub fn new(texture: SharedTexture<'a>) -> ParallaxLayer<'a>
+  {
+    let query = texture.borrow().query();
+    ParallaxLayer { texture, size: (query.width, query.height), scroll_factor: (1.0, 1.0), repeat: (true, true), offset: (0.0, 0.0) }
+  }
+
+  pub fn with_scroll_factor(mut self, x: f32, y: f32) -> ParallaxLayer<'a>
+  {
+    self.scroll_factor = (x, y);
+    self
+  }
+
+  // Not repeated on an axis, the texture is drawn once (e.g. hills along the bottom, repeated on x only)
+  pub fn with_repeat(mut self, x: bool, y: bool) -> ParallaxLayer<'a>
+  {
+    self.repeat = (x, y);
+    self
+  }
+
+  pub fn with_offset(mut self, x: f32, y: f32) -> ParallaxLayer<'a>
+  {
+    self.offset = (x, y);
+    self
+  }
+
+  pub fn texture(&self) -> &SharedTexture<'a> { &self.texture }
+  pub fn scroll_factor(&self) -> (f32, f32) { self.scroll_factor }
+  pub fn repeat(&self) -> (bool, bool) { self.repeat }
+  pub fn offset(&self) -> (f32, f32) { self.offset }
+  pub fn set_offset(&mut self, x: f32, y: f32) { self.offset = (x, y); } // e.g. drifting clouds
+
+  // scroll: how far the camera moved, in viewport pixels (the world point at the top left of the
+  // viewport, times the zoom), viewport_size: the part of the canvas to fill
+  pub fn render(&self, canvas: &mut WindowCanvas, scroll: (f32, f32), viewport_size: (u32, u32))
+  {
+    let origin_x = self.offset.0 - scroll.0 * self.scroll_factor.0;
+    let origin_y = self.offset.1 - scroll.1 * self.scroll_factor.1;
+    let columns = tile_starts(origin_x, self.size.0, viewport_size.0, self.repeat.0);
+    let rows = tile_starts(origin_y, self.size.1, viewport_size.1, self.repeat.1);
+    let texture = self.texture.borrow();
+    for &y in &rows {
+      for &x in &columns {
+        canvas.copy(&texture, None, Rect::new(x, y, self.size.0, self.size.1)).unwrap();
+      }
+    }
+  }
+}
+
+// Where copies of a tile of `size` starting at `origin` go to cover 0..length (once if not repeated).
+// Rounding the origin only (not each copy) keeps the copies edge to edge: no seams.
+fn tile_starts(origin: f32, size: u32, length: u32, repeat: bool) -> Vec<i32>
+{
+  let origin = origin.round() as i32;
+  if !repeat {
+    let visible = origin < length as i32 && origin + size as i32 > 0;
+    return if visible { vec![origin] } else { vec![] };
+  }
+  if size == 0 {
+    return vec![];
+  }
+  let shift = origin.rem_euclid(size as i32);
+  let first = if shift > 0 { shift - size as i32 } else { 0 };
+  (first..length as i32).step_by(size as usize).collect()
+}
+
+// The layers, drawn back to front
+#[derive(Clone, Default)]
+pub struct ParallaxBackground<'a>
+{
+  layers: Vec<ParallaxLayer<'a>>
+}
+
+impl<'a> ParallaxBackground<'a>
+{
+  pub fn new() -> ParallaxBackground<'a>
+  {
+    ParallaxBackground { layers: Vec::new() }
+  }
+
+  // Over the previous ones
+  pub fn add_layer(&mut self, layer: ParallaxLayer<'a>)
+  {
+    self.layers.push(layer);
+  }
+
+  pub fn with_layer(mut self, layer: ParallaxLayer<'a>) -> ParallaxBackground<'a>
+  {
+    self.add_layer(layer);
+    self
+  }
+
+  pub fn layers(&self) -> &[ParallaxLayer<'a>] { &self.layers }
+  pub fn layers_mut(&mut self) -> &mut [ParallaxLayer<'a>] { &mut self.layers }
+
+  // Fills the viewport of the camera. The textures are drawn at their size, whatever the zoom,
+  // but scroll as fast as the zoomed world does (times their factor).
+  pub fn render(&self, canvas: &mut WindowCanvas, camera: &Camera2D)
+  {
+    let (x, y) = camera.viewport_to_world(0.0, 0.0);
+    let scroll = (x * camera.zoom(), y * camera.zoom());
+    let viewport = camera.viewport();
+    for layer in &self.layers {
+      layer.render(canvas, scroll, (viewport.width(), viewport.height()));
+    }
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_repeated_tiles_cover_the_viewport() {
+    assert_eq!(tile_starts(0.0, 100, 250, true), vec![0, 100, 200]);
+    assert_eq!(tile_starts(-30.0, 100, 250, true), vec![-30, 70, 170]);
+    assert_eq!(tile_starts(30.0, 100, 250, true), vec![-70, 30, 130, 230]);
+    assert_eq!(tile_starts(-1030.4, 100, 250, true), vec![-30, 70, 170]);
+  }
+
+  #[test]
+  fn test_single_tile() {
+    assert_eq!(tile_starts(-30.0, 100, 250, false), vec![-30]);
+    assert_eq!(tile_starts(-100.0, 100, 250, false), Vec::<i32>::new());
+    assert_eq!(tile_starts(250.0, 100, 250, false), Vec::<i32>::new());
+  }
+}
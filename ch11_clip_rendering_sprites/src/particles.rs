@@ -0,0 +1,393 @@
+// Particle emitters (cf. the lazyfoo particle engine lesson), their settings coming from a json:
+//
+// { "emitters": [
+//   { "name": "Sparks", "sprite": "YellowCircle", "capacity": 300, "rate": 120, "burst": 0,
+//     "lifetime": [0.6, 1.2], "velocity_x": [-60, 60], "velocity_y": [-260, -160], "gravity": [0, 300],
+//     "spawn_x": [-10, 10], "spawn_y": 0,
+//     "colors": ["#FFFF80", "#FF4000"], "alpha": [255, 0], "size": [0.2, 0.05], "additive": true } ] }
+//
+// Ranges are [min, max] (or a single number), picked at random per particle. Times are in
+// seconds, speeds in pixels per second. "colors", "alpha" and "size" (a scale of the sprite)
+// change over the life of a particle, their values being evenly spread from birth to death.
+//
+// Each emitter has a fixed pool of particles, allocated once: when it's full, no more spawn.
+
+use crate::assets::read_asset;
+use crate::sprite::{RenderEx, Sprite, SpriteKey, SpriteName, SpriteStyle};
+use crate::texture::{parse_hex_color, ColorKeyData, HexColor};
+
+use sdl2::pixels::Color;
+use sdl2::render::{BlendMode, WindowCanvas};
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(from = "RangeData")]
+pub struct Range
+{
+  pub min: f32,
+  pub max: f32
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RangeData
+{
+  Value(f32),
+  MinMax([f32; 2])
+}
+
+impl From<RangeData> for Range
+{
+  fn from(data: RangeData) -> Range
+  {
+    match data
+    {
+      RangeData::Value(v) => Range { min: v, max: v },
+      RangeData::MinMax([min, max]) => Range { min, max }
+    }
+  }
+}
+
+impl Range
+{
+  fn pick(&self, rng: &mut fastrand::Rng) -> f32
+  {
+    self.min + (self.max - self.min) * rng.f32()
+  }
+}
+
+impl Default for Range
+{
+  fn default() -> Range { Range { min: 0.0, max: 0.0 } }
+}
+
+// "#RRGGBB" or [r, g, b], read like the color keys
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "ColorKeyData")]
+struct ParticleColor(Color);
+
+impl TryFrom<ColorKeyData> for ParticleColor
+{
+  type Error = String;
+
+  fn try_from(data: ColorKeyData) -> Result<ParticleColor, String>
+  {
+    match data
+    {
+      ColorKeyData::Triple([r, g, b]) => Ok(ParticleColor(Color::RGB(r, g, b))),
+      ColorKeyData::Text(text) => parse_hex_color(&text, HexColor::Rgb).map(ParticleColor)
+        .ok_or_else(|| format!("invalid particle color \"{}\" (expected \"#RRGGBB\" or [r, g, b])", text))
+    }
+  }
+}
+
+// Where t (0 = birth, 1 = death) falls among n >= 2 keys evenly spread over the life:
+// between keys i and i + 1, at f from i
+fn curve_step(n: usize, t: f32) -> (usize, f32)
+{
+  let position = t.clamp(0.0, 1.0) * (n - 1) as f32;
+  let i = (position as usize).min(n - 2);
+  (i, position - i as f32)
+}
+
+fn sample_curve(keys: &[f32], t: f32) -> f32
+{
+  match keys.len()
+  {
+    0 => 1.0,
+    1 => keys[0],
+    n =>
+    {
+      let (i, f) = curve_step(n, t);
+      keys[i] + (keys[i + 1] - keys[i]) * f
+    }
+  }
+}
+
+fn default_capacity() -> usize { 256 }
+fn default_size() -> Vec<f32> { vec![1.0] }
+fn default_alpha() -> Vec<f32> { vec![255.0] }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmitterSettings<N = SpriteName>
+{
+  pub name: String,
+  pub sprite: N,
+  #[serde(default = "default_capacity")]
+  pub capacity: usize,
+  #[serde(default)]
+  pub rate: f32, // particles per second while emitting
+  #[serde(default)]
+  pub burst: u32, // particles spawned at once by start()
+  pub lifetime: Range,
+  #[serde(default)]
+  pub velocity_x: Range,
+  #[serde(default)]
+  pub velocity_y: Range,
+  #[serde(default)]
+  pub gravity: (f32, f32),
+  #[serde(default)]
+  pub spawn_x: Range, // around the emitter position
+  #[serde(default)]
+  pub spawn_y: Range,
+  #[serde(default)]
+  colors: Vec<ParticleColor>, // white when empty
+  #[serde(default = "default_alpha")]
+  pub alpha: Vec<f32>,
+  #[serde(default = "default_size")]
+  pub size: Vec<f32>,
+  #[serde(default)]
+  pub additive: bool
+}
+
+impl<N> EmitterSettings<N>
+{
+  // Called for every particle drawn: no allocation
+  pub fn color_at(&self, t: f32) -> Color
+  {
+    match self.colors.len()
+    {
+      0 => Color::WHITE,
+      1 => { let c = self.colors[0].0; Color::RGB(c.r, c.g, c.b) },
+      n =>
+      {
+        let (i, f) = curve_step(n, t);
+        let (a, b) = (self.colors[i].0, self.colors[i + 1].0);
+        let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * f).round() as u8;
+        Color::RGB(channel(a.r, b.r), channel(a.g, b.g), channel(a.b, b.b))
+      }
+    }
+  }
+
+  pub fn alpha_at(&self, t: f32) -> u8 { sample_curve(&self.alpha, t).clamp(0.0, 255.0).round() as u8 }
+  pub fn size_at(&self, t: f32) -> f32 { sample_curve(&self.size, t).max(0.0) }
+}
+
+#[derive(Deserialize)]
+struct EmittersFile<N>
+{
+  emitters: Vec<EmitterSettings<N>>
+}
+
+// The emitter settings of a json, by name
+pub fn load_emitter_settings<N: SpriteKey>(json_path: &str) -> Result<HashMap<String, EmitterSettings<N>>, String>
+{
+  let bytes = read_asset(json_path)?;
+  let file: EmittersFile<N> = serde_json::from_slice(&bytes).map_err(|e| format!("{}: {}", json_path, e))?;
+  let mut emitters = HashMap::new();
+  for settings in file.emitters {
+    if settings.lifetime.min <= 0.0 || settings.lifetime.max < settings.lifetime.min {
+      return Err(format!("{}: emitter {}: bad lifetime {:?}", json_path, settings.name, settings.lifetime));
+    }
+    if let Some(previous) = emitters.insert(settings.name.clone(), settings) {
+      return Err(format!("{}: emitter {} defined twice", json_path, previous.name));
+    }
+  }
+  Ok(emitters)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Particle
+{
+  x: f32,
+  y: f32,
+  vx: f32,
+  vy: f32,
+  age: f32, // seconds
+  lifetime: f32
+}
+
+impl Particle
+{
+  fn life(&self) -> f32 { self.age / self.lifetime }
+}
+
+// The simulation, without SDL. The live particles are the first `live` of the pool:
+// a dead one is swapped with the last live one, so nothing is ever allocated after new().
+struct ParticlePool
+{
+  particles: Vec<Particle>,
+  live: usize,
+  spawn_debt: f32, // fraction of particle owed by the rate
+  rng: fastrand::Rng
+}
+
+impl ParticlePool
+{
+  fn new(capacity: usize, rng: fastrand::Rng) -> ParticlePool
+  {
+    ParticlePool { particles: vec![Particle::default(); capacity], live: 0, spawn_debt: 0.0, rng }
+  }
+
+  // Spawns up to `count` particles at (x, y), as many as the pool allows
+  fn spawn<N>(&mut self, settings: &EmitterSettings<N>, count: usize, x: f32, y: f32)
+  {
+    for _ in 0..count.min(self.particles.len() - self.live) {
+      let rng = &mut self.rng;
+      self.particles[self.live] = Particle {
+        x: x + settings.spawn_x.pick(rng),
+        y: y + settings.spawn_y.pick(rng),
+        vx: settings.velocity_x.pick(rng),
+        vy: settings.velocity_y.pick(rng),
+        age: 0.0,
+        lifetime: settings.lifetime.pick(rng)
+      };
+      self.live += 1;
+    }
+  }
+
+  fn update<N>(&mut self, settings: &EmitterSettings<N>, dt: f32, emitting: bool, x: f32, y: f32)
+  {
+    let mut i = 0;
+    while i < self.live {
+      let p = &mut self.particles[i];
+      p.age += dt;
+      if p.age >= p.lifetime {
+        self.live -= 1;
+        self.particles.swap(i, self.live);
+        continue; // (the swapped one is updated now)
+      }
+      p.vx += settings.gravity.0 * dt;
+      p.vy += settings.gravity.1 * dt;
+      p.x += p.vx * dt;
+      p.y += p.vy * dt;
+      i += 1;
+    }
+
+    if emitting {
+      self.spawn_debt += settings.rate * dt;
+      let count = self.spawn_debt.floor();
+      self.spawn_debt -= count;
+      self.spawn(settings, count as usize, x, y);
+    }
+  }
+
+  fn live(&self) -> &[Particle] { &self.particles[..self.live] }
+}
+
+pub struct ParticleEmitter<'a, N = SpriteName>
+{
+  settings: EmitterSettings<N>,
+  sprite: Sprite<'a, N>,
+  pool: ParticlePool,
+  position: (f32, f32),
+  emitting: bool
+}
+
+impl<'a, N: SpriteKey> ParticleEmitter<'a, N>
+{
+  // The sprite named in the settings is taken from `sprites` (create_sprites, SpriteRegistry::sprites)
+  pub fn new(settings: &EmitterSettings<N>, sprites: &HashMap<N, Sprite<'a, N>>) -> Result<ParticleEmitter<'a, N>, String>
+  {
+    let mut sprite = sprites.get(&settings.sprite)
+      .ok_or_else(|| format!("emitter {}: no sprite {:?}", settings.name, settings.sprite))?
+      .clone();
+    sprite.set_blend_mode(if settings.additive { BlendMode::Add } else { BlendMode::Blend });
+    Ok(ParticleEmitter {
+      settings: settings.clone(),
+      sprite,
+      pool: ParticlePool::new(settings.capacity, fastrand::Rng::new()),
+      position: (0.0, 0.0),
+      emitting: false
+    })
+  }
+
+  pub fn settings(&self) -> &EmitterSettings<N> { &self.settings }
+
+  pub fn set_position(&mut self, x: f32, y: f32) { self.position = (x, y); }
+  pub fn position(&self) -> (f32, f32) { self.position }
+
+  // Spawns the burst, then particles at the rate until stop()
+  pub fn start(&mut self)
+  {
+    self.emitting = true;
+    self.burst(self.settings.burst as usize);
+  }
+
+  // The live particles go on until their end
+  pub fn stop(&mut self) { self.emitting = false; }
+  pub fn is_emitting(&self) -> bool { self.emitting }
+
+  pub fn burst(&mut self, count: usize)
+  {
+    self.pool.spawn(&self.settings, count, self.position.0, self.position.1);
+  }
+
+  pub fn update(&mut self, dt: Duration)
+  {
+    self.pool.update(&self.settings, dt.as_secs_f32(), self.emitting, self.position.0, self.position.1);
+  }
+
+  pub fn live_count(&self) -> usize { self.pool.live }
+  pub fn capacity(&self) -> usize { self.pool.particles.len() }
+
+  // Each particle is the sprite centered on its position, scaled, tinted and faded by its age
+  pub fn render(&mut self, canvas: &mut WindowCanvas)
+  {
+    let (w, h) = self.sprite.size();
+    let mut params = RenderEx::default();
+    for p in self.pool.live() {
+      let t = p.life();
+      let scale = self.settings.size_at(t);
+      if scale <= 0.0 {
+        continue;
+      }
+      let blend_mode = self.sprite.style().blend_mode;
+      self.sprite.set_style(SpriteStyle { tint: self.settings.color_at(t), alpha: self.settings.alpha_at(t), blend_mode });
+      params.scale = (scale, scale);
+      let (x, y) = (p.x - w as f32 * scale / 2.0, p.y - h as f32 * scale / 2.0);
+      self.sprite.render_ex(canvas, x.round() as i32, y.round() as i32, &params);
+    }
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn settings(json: &str) -> EmitterSettings<String> {
+    serde_json::from_str(json).unwrap()
+  }
+
+  #[test]
+  fn test_curves() {
+    let s = settings(r##"{ "name": "s", "sprite": "dot", "lifetime": 1, "colors": ["#FF0000", [0, 0, 255]],
+      "alpha": [255, 0], "size": [1, 3, 1] }"##);
+    assert_eq!(s.color_at(0.5), Color::RGB(128, 0, 128));
+    assert_eq!(s.alpha_at(0.0), 255);
+    assert_eq!(s.alpha_at(1.0), 0);
+    assert_eq!(s.size_at(0.5), 3.0);
+    assert_eq!(s.size_at(0.75), 2.0);
+    assert_eq!(s.lifetime, Range { min: 1.0, max: 1.0 });
+  }
+
+  #[test]
+  fn test_pool_rate_capacity_and_lifetime() {
+    let s = settings(r#"{ "name": "s", "sprite": "dot", "capacity": 10, "rate": 100, "lifetime": [0.5, 0.5] }"#);
+    let mut pool = ParticlePool::new(s.capacity, fastrand::Rng::with_seed(7));
+    pool.update(&s, 0.025, true, 0.0, 0.0); // 2.5 particles: 2, and half a particle owed
+    assert_eq!(pool.live, 2);
+    pool.update(&s, 0.025, true, 0.0, 0.0);
+    assert_eq!(pool.live, 5);
+    pool.update(&s, 0.2, true, 0.0, 0.0); // 20 more asked, the pool is full at 10
+    assert_eq!(pool.live, 10);
+    pool.update(&s, 0.6, false, 0.0, 0.0); // all dead
+    assert_eq!(pool.live, 0);
+  }
+
+  #[test]
+  fn test_gravity() {
+    let s = settings(r#"{ "name": "s", "sprite": "dot", "lifetime": 10, "velocity_x": 10, "gravity": [0, 100] }"#);
+    let mut pool = ParticlePool::new(1, fastrand::Rng::with_seed(1));
+    pool.spawn(&s, 1, 5.0, 5.0);
+    pool.update(&s, 0.5, false, 0.0, 0.0);
+    let p = pool.live()[0];
+    assert_eq!((p.x, p.y, p.vy), (10.0, 30.0, 50.0));
+  }
+}
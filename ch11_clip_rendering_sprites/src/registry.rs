@@ -0,0 +1,150 @@
+// Any number of sprite jsons, each with its own spritesheet, gathered in one lookup.
+// A sheet (its sprites, its animations, and its texture if nobody else uses it) can be unloaded
+// as a whole, e.g. when leaving a level. The textures still go through the TextureManager,
+// so two jsons cut into the same png share one texture.
+
+use crate::animation::{create_animations, AnimationClip};
+use crate::sprite::{try_create_sprites, try_create_sprites_with_masks, try_load_sprites_from_json_as, Sprite, SpriteKey, SpriteName, SpriteSheetData};
+use crate::texture::TextureManager;
+
+use sdl2::render::TextureCreator;
+use sdl2::video::WindowContext;
+
+use std::collections::HashMap;
+use std::ops::Index;
+use std::rc::Rc;
+
+// What was loaded from one json
+struct LoadedSheet<N>
+{
+  spritesheet: String,
+  sprite_names: Vec<N>,
+  animation_names: Vec<String>
+}
+
+pub struct SpriteRegistry<'a, N = SpriteName>
+{
+  sprites: HashMap<N, Sprite<'a, N>>,
+  animations: HashMap<String, Rc<AnimationClip<'a, N>>>,
+  sheets: HashMap<String, LoadedSheet<N>>, // by json path
+  mask_threshold: Option<u8> // the sprites get collision masks, cf. with_collision_masks
+}
+
+impl<'a, N: SpriteKey> Default for SpriteRegistry<'a, N>
+{
+  fn default() -> SpriteRegistry<'a, N>
+  {
+    SpriteRegistry::new()
+  }
+}
+
+impl<'a, N: SpriteKey> SpriteRegistry<'a, N>
+{
+  pub fn new() -> SpriteRegistry<'a, N>
+  {
+    SpriteRegistry { sprites: HashMap::new(), animations: HashMap::new(), sheets: HashMap::new(), mask_threshold: None }
+  }
+
+  // The sprites loaded from now on get their collision mask (Sprite::collision_mask, Sprite::overlap):
+  // the pixels with at least that alpha
+  pub fn with_collision_masks(mut self, alpha_threshold: u8) -> SpriteRegistry<'a, N>
+  {
+    self.mask_threshold = Some(alpha_threshold);
+    self
+  }
+
+  // Loads the sprites and animations of a json. Nothing is added if one of its names
+  // is already used by another sheet. Loading the same json twice does nothing.
+  pub fn load(&mut self,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture_manager: &mut TextureManager<'a>,
+    json_path: &str) -> Result<(), String>
+  {
+    if self.sheets.contains_key(json_path) {
+      return Ok(());
+    }
+    let sprite_data = try_load_sprites_from_json_as::<N>(json_path)?;
+    self.insert(texture_creator, texture_manager, json_path, &sprite_data)
+  }
+
+  // Same with sprite data coming from elsewhere (e.g. import.rs), json_path being only
+  // the name of the sheet in the registry (for unload).
+  pub fn insert(&mut self,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture_manager: &mut TextureManager<'a>,
+    json_path: &str,
+    sprite_data: &SpriteSheetData<N>) -> Result<(), String>
+  {
+    if self.sheets.contains_key(json_path) {
+      return Err(format!("{}: already loaded", json_path));
+    }
+
+    let duplicates: Vec<String> = sprite_data.sprites().iter()
+      .filter(|s| self.sprites.contains_key(s.name()))
+      .map(|s| format!("{:?}", s.name()))
+      .chain(sprite_data.animations().iter()
+        .filter(|a| self.animations.contains_key(a.name()))
+        .map(|a| a.name().to_string()))
+      .collect();
+    if !duplicates.is_empty() {
+      return Err(format!("{}: names already loaded from another sheet: {}", json_path, duplicates.join(", ")));
+    }
+
+    let sprites = match self.mask_threshold
+    {
+      Some(threshold) => try_create_sprites_with_masks(texture_creator, sprite_data, texture_manager, threshold),
+      None => try_create_sprites(texture_creator, sprite_data, texture_manager)
+    }.map_err(|e| format!("{}: {}", json_path, e))?;
+    let animations = create_animations(sprite_data.animations(), &sprites)
+      .map_err(|e| format!("{}: {}", json_path, e))?;
+
+    self.sheets.insert(json_path.to_string(), LoadedSheet {
+      spritesheet: sprite_data.spritesheet().to_string(),
+      sprite_names: sprites.keys().cloned().collect(),
+      animation_names: animations.keys().cloned().collect()
+    });
+    self.sprites.extend(sprites);
+    self.animations.extend(animations);
+    Ok(())
+  }
+
+  // Forgets the sprites and animations of a json, and the spritesheet texture
+  // unless another loaded json uses it too.
+  pub fn unload(&mut self, texture_manager: &mut TextureManager<'a>, json_path: &str) -> Result<(), String>
+  {
+    let sheet = self.sheets.remove(json_path)
+      .ok_or_else(|| format!("{}: not loaded", json_path))?;
+
+    for name in &sheet.sprite_names {
+      self.sprites.remove(name);
+    }
+    for name in &sheet.animation_names {
+      self.animations.remove(name);
+    }
+    if !self.sheets.values().any(|other| other.spritesheet == sheet.spritesheet) {
+      texture_manager.unload_texture(&sheet.spritesheet);
+    }
+    Ok(())
+  }
+
+  pub fn is_loaded(&self, json_path: &str) -> bool { self.sheets.contains_key(json_path) }
+
+  pub fn get(&self, name: &N) -> Option<&Sprite<'a, N>> { self.sprites.get(name) }
+  pub fn sprites(&self) -> &HashMap<N, Sprite<'a, N>> { &self.sprites }
+
+  pub fn animation(&self, name: &str) -> Option<Rc<AnimationClip<'a, N>>>
+  {
+    self.animations.get(name).cloned()
+  }
+}
+
+// registry[&RedCircle], like with the HashMap given by create_sprites
+impl<'a, N: SpriteKey> Index<&N> for SpriteRegistry<'a, N>
+{
+  type Output = Sprite<'a, N>;
+
+  fn index(&self, name: &N) -> &Sprite<'a, N>
+  {
+    self.sprites.get(name).unwrap_or_else(|| panic!("sprite {:?} not loaded", name))
+  }
+}
@@ -0,0 +1,109 @@
+// Offscreen composition: a texture we can draw into (sprites, shapes, ...) with the canvas,
+// then copy like any other texture. Handy to cache a static background or to build a minimap.
+//
+// The renderer may lose the content of target textures (SDL_RENDER_TARGETS_RESET, e.g. on
+// Direct3D when the window is resized), or even the textures themselves (SDL_RENDER_DEVICE_RESET).
+// The RenderTarget watches these events and knows when it has to be drawn again.
+
+use crate::sprite::Sprite;
+use crate::texture::SharedTexture;
+
+use sdl2::event::Event;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub struct RenderTarget<'a>
+{
+  texture_creator: &'a TextureCreator<WindowContext>,
+  texture: SharedTexture<'a>, // shared with the sprites made by sprite()
+  valid: bool,         // false until first drawn, and after a reset
+  device_lost: bool    // the texture itself must be recreated
+}
+
+fn create_target_texture<'a>(texture_creator: &'a TextureCreator<WindowContext>, width: u32, height: u32)
+  -> Result<Texture<'a>, String>
+{
+  let mut texture = texture_creator.create_texture_target(PixelFormatEnum::RGBA8888, width, height)
+    .map_err(|e| e.to_string())?;
+  texture.set_blend_mode(BlendMode::Blend); // so that what's left transparent stays transparent once copied
+  Ok(texture)
+}
+
+impl<'a> RenderTarget<'a>
+{
+  pub fn new(texture_creator: &'a TextureCreator<WindowContext>, width: u32, height: u32) -> Result<RenderTarget<'a>, String>
+  {
+    let texture = Rc::new(RefCell::new(create_target_texture(texture_creator, width, height)?));
+    Ok(RenderTarget { texture_creator, texture, valid: false, device_lost: false })
+  }
+
+  // To be called with every event of the main loop
+  pub fn handle_event(&mut self, event: &Event)
+  {
+    match event
+    {
+      Event::RenderTargetsReset {..} => { self.valid = false; },
+      Event::RenderDeviceReset {..} => { self.valid = false; self.device_lost = true; },
+      _ => {}
+    }
+  }
+
+  // false when the content has to be (re)drawn
+  pub fn is_valid(&self) -> bool { self.valid }
+
+  pub fn width(&self) -> u32 { self.texture.borrow().query().width }
+  pub fn height(&self) -> u32 { self.texture.borrow().query().height }
+
+  // Clears the target (transparent) and lets draw_fn render into it.
+  // Inside draw_fn the canvas renders to the texture: coordinates are relative to the texture.
+  pub fn draw<F>(&mut self, canvas: &mut WindowCanvas, draw_fn: F) -> Result<(), String>
+    where F: FnOnce(&mut WindowCanvas)
+  {
+    if self.device_lost
+    {
+      let (w, h) = (self.width(), self.height());
+      // replaced in place: the sprites of the target get the new texture too
+      *self.texture.borrow_mut() = create_target_texture(self.texture_creator, w, h)?;
+      self.device_lost = false;
+    }
+
+    canvas.with_texture_canvas(&mut self.texture.borrow_mut(), |target_canvas| {
+      // the draw color belongs to the canvas: don't leak ours to the caller
+      let previous_color = target_canvas.draw_color();
+      target_canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
+      target_canvas.clear();
+      target_canvas.set_draw_color(previous_color);
+      draw_fn(target_canvas);
+    }).map_err(|e| e.to_string())?;
+
+    self.valid = true;
+    Ok(())
+  }
+
+  // Same as draw, but only when the content is missing: call it every frame with the
+  // same drawing code, it costs nothing as long as the target is valid.
+  pub fn draw_if_needed<F>(&mut self, canvas: &mut WindowCanvas, draw_fn: F) -> Result<(), String>
+    where F: FnOnce(&mut WindowCanvas)
+  {
+    if self.valid { Ok(()) } else { self.draw(canvas, draw_fn) }
+  }
+
+  pub fn texture(&self) -> &SharedTexture<'a> { &self.texture }
+
+  // The whole target as a sprite: drawn with a style, in a batch, scaled... like the others
+  pub fn sprite<N>(&self, name: N) -> Sprite<'a, N>
+  {
+    Sprite::new(Rc::clone(&self.texture), Rect::new(0, 0, self.width(), self.height()), name)
+  }
+
+  // Copies the whole target at (x, y), at natural size
+  pub fn render(&self, canvas: &mut WindowCanvas, x: i32, y: i32)
+  {
+    canvas.copy(&self.texture.borrow(), None, Rect::new(x, y, self.width(), self.height())).unwrap();
+  }
+}
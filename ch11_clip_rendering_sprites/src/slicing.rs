@@ -0,0 +1,154 @@
+// The logic of the slice_editor tool (src/bin/slice_editor.rs), apart from SDL: undo/redo,
+// rects dragged with the mouse, and their snapping to a grid or to the visible pixels.
+
+use sdl2::rect::{Point, Rect};
+
+// Undo/redo by snapshots: the whole state is saved before each change (the sprites of a
+// sheet are small enough for that, and no change can then be undone wrong).
+pub struct EditHistory<T>
+{
+  undo: Vec<T>,
+  redo: Vec<T>,
+  limit: usize
+}
+
+impl<T> EditHistory<T>
+{
+  // limit: number of changes that can be undone
+  pub fn new(limit: usize) -> EditHistory<T>
+  {
+    EditHistory { undo: Vec::new(), redo: Vec::new(), limit: limit.max(1) }
+  }
+
+  // To call with the state as it is before a change. A new change forgets what was undone.
+  pub fn record(&mut self, before: T)
+  {
+    if self.undo.len() == self.limit {
+      self.undo.remove(0);
+    }
+    self.undo.push(before);
+    self.redo.clear();
+  }
+
+  // The state to go back to, if any. current is kept for redo().
+  pub fn undo(&mut self, current: T) -> Option<T>
+  {
+    let previous = self.undo.pop()?;
+    self.redo.push(current);
+    Some(previous)
+  }
+
+  pub fn redo(&mut self, current: T) -> Option<T>
+  {
+    let next = self.redo.pop()?;
+    self.undo.push(current);
+    Some(next)
+  }
+
+  pub fn can_undo(&self) -> bool { !self.undo.is_empty() }
+  pub fn can_redo(&self) -> bool { !self.redo.is_empty() }
+}
+
+// The pixels from a to b, both included, whatever the drag direction
+pub fn rect_from_corners(a: Point, b: Point) -> Rect
+{
+  let (left, right) = (a.x().min(b.x()), a.x().max(b.x()));
+  let (top, bottom) = (a.y().min(b.y()), a.y().max(b.y()));
+  Rect::new(left, top, (right - left + 1) as u32, (bottom - top + 1) as u32)
+}
+
+// The edges moved to the nearest grid lines, keeping at least one cell
+pub fn snap_to_grid(rect: Rect, cell: u32) -> Rect
+{
+  if cell <= 1 {
+    return rect;
+  }
+  let cell = cell as i32;
+  let snap = |v: i32| (v as f32 / cell as f32).round() as i32 * cell;
+  let (left, top) = (snap(rect.left()), snap(rect.top()));
+  let right = snap(rect.right()).max(left + cell);
+  let bottom = snap(rect.bottom()).max(top + cell);
+  Rect::new(left, top, (right - left) as u32, (bottom - top) as u32)
+}
+
+// The smallest rect holding the pixels of `within` with some alpha, in an RGBA32 image
+// (`pixels` of `size`, rows of `pitch` bytes). None if they're all transparent.
+pub fn alpha_bounds(pixels: &[u8], pitch: usize, size: (u32, u32), within: Rect) -> Option<Rect>
+{
+  let (x0, y0) = (within.left().max(0), within.top().max(0));
+  let (x1, y1) = (within.right().min(size.0 as i32), within.bottom().min(size.1 as i32));
+  let mut bounds: Option<(i32, i32, i32, i32)> = None;
+  for y in y0..y1 {
+    for x in x0..x1 {
+      if pixels[y as usize * pitch + x as usize * 4 + 3] != 0 {
+        bounds = Some(match bounds
+        {
+          None => (x, y, x, y),
+          Some((l, t, r, b)) => (l.min(x), t.min(y), r.max(x), b.max(y))
+        });
+      }
+    }
+  }
+  bounds.map(|(l, t, r, b)| Rect::new(l, t, (r - l + 1) as u32, (b - t + 1) as u32))
+}
+
+// base1, base2... the first one not taken
+pub fn unique_name<'n>(base: &str, taken: impl Iterator<Item = &'n str> + Clone) -> String
+{
+  (1..).map(|i| format!("{}{}", base, i))
+    .find(|name| !taken.clone().any(|t| t == name))
+    .unwrap()
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_history() {
+    let mut history = EditHistory::new(2);
+    let mut state = 0;
+    for next in 1..=3 {
+      history.record(state);
+      state = next;
+    }
+    // only two changes are kept
+    state = history.undo(state).unwrap();
+    state = history.undo(state).unwrap();
+    assert_eq!((state, history.can_undo()), (1, false));
+    state = history.redo(state).unwrap();
+    assert_eq!(state, 2);
+    // a new change drops the redo
+    history.record(state);
+    assert!(!history.can_redo());
+    assert_eq!(history.undo(10), Some(2));
+  }
+
+  #[test]
+  fn test_rects_and_snapping() {
+    assert_eq!(rect_from_corners(Point::new(10, 20), Point::new(5, 25)), Rect::new(5, 20, 6, 6));
+    assert_eq!(snap_to_grid(Rect::new(5, 20, 6, 6), 16), Rect::new(0, 16, 16, 16));
+    assert_eq!(snap_to_grid(Rect::new(9, 7, 40, 10), 16), Rect::new(16, 0, 32, 16));
+    assert_eq!(snap_to_grid(Rect::new(9, 7, 40, 10), 1), Rect::new(9, 7, 40, 10));
+  }
+
+  #[test]
+  fn test_alpha_bounds() {
+    // 6x4 RGBA, rows padded to 28 bytes, two opaque pixels
+    let (pitch, size) = (28, (6, 4));
+    let mut pixels = vec![0u8; pitch * 4];
+    pixels[pitch + 2 * 4 + 3] = 0xFF;     // (2, 1)
+    pixels[3 * pitch + 4 * 4 + 3] = 0x10; // (4, 3)
+    assert_eq!(alpha_bounds(&pixels, pitch, size, Rect::new(0, 0, 10, 10)), Some(Rect::new(2, 1, 3, 3)));
+    assert_eq!(alpha_bounds(&pixels, pitch, size, Rect::new(0, 0, 4, 3)), Some(Rect::new(2, 1, 1, 1)));
+    assert_eq!(alpha_bounds(&pixels, pitch, size, Rect::new(0, 2, 3, 2)), None);
+  }
+
+  #[test]
+  fn test_names() {
+    assert_eq!(unique_name("Sprite", ["Sprite1", "Sprite3"].into_iter()), "Sprite2");
+  }
+}
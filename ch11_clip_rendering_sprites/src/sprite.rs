@@ -1,92 +1,555 @@
+use crate::animation::AnimationData;
+use crate::collision::{collision_masks_from_surface, CollisionMask};
 use crate::errors::*;
-use crate::texture::TextureManager;
+use crate::nine_slice::Insets;
+use crate::texture::{load_keyed_surface, ColorKey, SharedTexture, TextureManager};
 
-use sdl2::rect::Rect;
-use sdl2::render::{Texture, WindowCanvas, TextureCreator};
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Texture, WindowCanvas, TextureCreator};
 use sdl2::video::WindowContext;
+use serde::de::DeserializeOwned;
 use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
 use std::rc::Rc;
 
-pub struct Sprite<'a> 
+// What sprites can be keyed by: the generated SpriteName enum (typed lookups, a typo is a
+// compile error), or String for tools which must accept any json (runtime lookups).
+pub trait SpriteKey: DeserializeOwned + Eq + Hash + Clone + Debug {}
+impl<T: DeserializeOwned + Eq + Hash + Clone + Debug> SpriteKey for T {}
+
+// Color modulation, alpha and blend mode are per sprite instance (cf. lazyfoo chapters 12 and 13),
+// whereas SDL holds them in the texture, which is shared by all the sprites of the sheet.
+// So they are applied right before the draw and the texture state is restored right after.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteStyle
+{
+  pub tint: Color,                   // color modulation (alpha ignored), white = unchanged
+  pub alpha: u8,                     // alpha modulation, only visible with blending on
+  pub blend_mode: Option<BlendMode>  // None: keep the texture's own blend mode
+}
+
+impl Default for SpriteStyle
+{
+  fn default() -> SpriteStyle
+  {
+    SpriteStyle { tint: Color::WHITE, alpha: 0xFF, blend_mode: None }
+  }
+}
+
+// Cloning a sprite is cheap (the texture is shared): handy to get another instance with its own style
+#[derive(Clone)]
+pub struct Sprite<'a, N = SpriteName> 
 {
-  texture: Rc<Texture<'a>>,
+  texture: SharedTexture<'a>,
   src_rect: Rect, // Source rectangle defining the sprite's portion in the texture
-  name: SpriteName
+  name: N,
+  style: SpriteStyle,
+  pivot: Option<Point>, // rotation center, relative to the sprite's top-left corner (from the json)
+  rotated: bool,        // stored rotated 90° clockwise in the sheet (packers do that to save space)
+  trim: Option<Trim>,   // the transparent borders were cut off by the packer
+  nine_slice: Option<Insets>, // borders, for the sprites meant to be used as NineSlice
+  mask: Option<Rc<CollisionMask>> // only when loaded with the masks (cf. try_create_sprites_with_masks)
+}
+
+// Where the stored (trimmed) pixels go in the original image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim
+{
+  pub offset: Point,
+  pub source_size: (u32, u32)
+}
+
+// Parameters of Sprite::render_ex (<=> SDL_RenderCopyEx)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderEx
+{
+  pub angle: f64,             // degrees, clockwise
+  pub center: Option<Point>,  // rotation center relative to the destination; None: the sprite's pivot, or else the middle
+  pub flip_horizontal: bool,
+  pub flip_vertical: bool,
+  pub scale: (f32, f32)       // destination size = source size * scale
+}
+
+impl Default for RenderEx
+{
+  fn default() -> RenderEx
+  {
+    RenderEx { angle: 0.0, center: None, flip_horizontal: false, flip_vertical: false, scale: (1.0, 1.0) }
+  }
 }
 
-impl<'a> Sprite<'a> 
+impl<'a, N> Sprite<'a, N> 
 {
-  pub fn new(texture: Rc<Texture<'a>>, src_rect: Rect, name: SpriteName) -> Sprite<'a> 
+  pub fn new(texture: SharedTexture<'a>, src_rect: Rect, name: N) -> Sprite<'a, N> 
+  {
+    Sprite { texture, src_rect, name, style: SpriteStyle::default(), pivot: None, rotated: false, trim: None,
+      nine_slice: None, mask: None }
+  }
+
+  // How the sprite is stored in the sheet, for sheets made by packers (cf. import.rs)
+  pub fn with_layout(mut self, rotated: bool, trim: Option<Trim>) -> Sprite<'a, N>
+  {
+    self.rotated = rotated;
+    self.trim = trim;
+    self
+  }
+
+  pub fn is_rotated(&self) -> bool { self.rotated }
+  pub fn trim(&self) -> Option<Trim> { self.trim }
+
+  pub fn with_nine_slice(mut self, insets: Option<Insets>) -> Sprite<'a, N>
   {
-    Sprite { texture, src_rect, name }
+    self.nine_slice = insets;
+    self
   }
 
+  pub fn nine_slice(&self) -> Option<Insets> { self.nine_slice }
+
+  pub fn with_collision_mask(mut self, mask: Option<Rc<CollisionMask>>) -> Sprite<'a, N>
+  {
+    self.mask = mask;
+    self
+  }
+
+  pub fn collision_mask(&self) -> Option<&CollisionMask> { self.mask.as_deref() }
+
+  // Sprites drawn at (x, y) with render: the first shared pixel, None if they don't touch
+  // or if one of them has no mask
+  pub fn overlap<M>(&self, position: Point, other: &Sprite<M>, other_position: Point) -> Option<Point>
+  {
+    self.collision_mask()?.overlap(position, other.collision_mask()?, other_position)
+  }
+
+  // Size of the stored pixels, upright
+  fn frame_size(&self) -> (u32, u32)
+  {
+    let (w, h) = (self.src_rect.width(), self.src_rect.height());
+    if self.rotated { (h, w) } else { (w, h) }
+  }
+
+  // Size of the sprite as drawn: the original size if it was trimmed
+  pub fn size(&self) -> (u32, u32)
+  {
+    self.trim.map_or(self.frame_size(), |t| t.source_size)
+  }
+
+  pub fn with_pivot(mut self, pivot: Option<Point>) -> Sprite<'a, N>
+  {
+    self.pivot = pivot;
+    self
+  }
+
+  pub fn pivot(&self) -> Option<Point> { self.pivot }
+
+  pub fn name(&self) -> &N { &self.name }
+  pub fn src_rect(&self) -> Rect { self.src_rect }
+  pub fn texture(&self) -> &SharedTexture<'a> { &self.texture }
+
+  pub fn style(&self) -> SpriteStyle { self.style }
+  pub fn set_style(&mut self, style: SpriteStyle) { self.style = style; }
+  pub fn set_color(&mut self, tint: Color) { self.style.tint = tint; }
+  pub fn set_alpha(&mut self, alpha: u8) { self.style.alpha = alpha; }
+  pub fn set_blend_mode(&mut self, blend_mode: BlendMode) { self.style.blend_mode = Some(blend_mode); }
+
   pub fn render(&self, canvas: &mut WindowCanvas, x: i32, y: i32) 
   {
+    if self.rotated || self.trim.is_some() {
+      return self.render_ex(canvas, x, y, &RenderEx::default());
+    }
     let dest_rect = Rect::new(x, y, self.src_rect.width(), self.src_rect.height());
-    canvas.copy(&self.texture, self.src_rect, dest_rect).unwrap();
+    with_style(&self.texture, &self.style, |tex| {
+      canvas.copy(tex, self.src_rect, dest_rect).unwrap();
+    });
+  }
+
+  // (x, y) is still the top-left corner of the destination, which is scaled, then
+  // rotated around the center (or the pivot, scaled as well) and flipped.
+  pub fn render_ex(&self, canvas: &mut WindowCanvas, x: i32, y: i32, params: &RenderEx)
+  {
+    let (sx, sy) = (params.scale.0 as f64, params.scale.1 as f64);
+    let (w, h) = self.size();
+    let (fw, fh) = self.frame_size();
+
+    // Trimmed pixels go at their offset in the original image (mirrored when flipped)
+    let (mut ox, mut oy) = self.trim.map_or((0, 0), |t| (t.offset.x(), t.offset.y()));
+    if params.flip_horizontal { ox = w as i32 - fw as i32 - ox; }
+    if params.flip_vertical { oy = h as i32 - fh as i32 - oy; }
+    let (ox, oy) = (ox as f64 * sx, oy as f64 * sy);
+    let dest_rect = Rect::new(x + ox.round() as i32, y + oy.round() as i32,
+      (fw as f64 * sx).round() as u32, (fh as f64 * sy).round() as u32);
+
+    // Rotation center relative to the whole sprite, then to the stored pixels
+    let center = params.center.map(|c| (c.x() as f64, c.y() as f64))
+      .or_else(|| self.pivot.map(|p| (p.x() as f64 * sx, p.y() as f64 * sy)))
+      .unwrap_or((w as f64 * sx / 2.0, h as f64 * sy / 2.0));
+    let center = (center.0 - ox, center.1 - oy);
+
+    with_style(&self.texture, &self.style, |tex| {
+      if !self.rotated {
+        let center = Point::new(center.0.round() as i32, center.1.round() as i32);
+        canvas.copy_ex(tex, self.src_rect, dest_rect, params.angle, center,
+          params.flip_horizontal, params.flip_vertical).unwrap();
+      }
+      else {
+        // The stored pixels are turned back by -90°, around the middle of dest_rect. Rotating them
+        // then by `angle` around `center` is the same as rotating by angle - 90 around the middle,
+        // plus a translation: R(middle - center) - (middle - center).
+        let middle = (dest_rect.width() as f64 / 2.0, dest_rect.height() as f64 / 2.0);
+        let (dx, dy) = (middle.0 - center.0, middle.1 - center.1);
+        let (sin, cos) = params.angle.to_radians().sin_cos();
+        let shift = (dx * cos - dy * sin - dx, dx * sin + dy * cos - dy);
+
+        // same middle, width and height swapped: that's the stored rect before it's turned back
+        let (mx, my) = (dest_rect.x() as f64 + middle.0 + shift.0, dest_rect.y() as f64 + middle.1 + shift.1);
+        let stored_dest = Rect::new((mx - middle.1).round() as i32, (my - middle.0).round() as i32,
+          dest_rect.height(), dest_rect.width());
+        // turned by 90°, the horizontal axis of the stored pixels is the vertical one of the sprite
+        canvas.copy_ex(tex, self.src_rect, stored_dest, params.angle - 90.0, None,
+          params.flip_vertical, params.flip_horizontal).unwrap();
+      }
+    });
+  }
+}
+
+// Runs draw_fn with the style applied to the texture, and puts the texture state back afterwards
+pub fn with_style<R>(texture: &SharedTexture, style: &SpriteStyle, draw_fn: impl FnOnce(&Texture) -> R) -> R
+{
+  if *style == SpriteStyle::default() {
+    return draw_fn(&texture.borrow()); // nothing to change, nothing to restore
+  }
+
+  let mut tex = texture.borrow_mut();
+  let (r, g, b) = tex.color_mod();
+  let alpha = tex.alpha_mod();
+  let blend_mode = tex.blend_mode();
+
+  tex.set_color_mod(style.tint.r, style.tint.g, style.tint.b);
+  tex.set_alpha_mod(style.alpha);
+  if let Some(mode) = style.blend_mode {
+    tex.set_blend_mode(mode);
+  }
+
+  let result = draw_fn(&tex);
+
+  tex.set_color_mod(r, g, b);
+  tex.set_alpha_mod(alpha);
+  tex.set_blend_mode(blend_mode);
+  result
+}
+
+// Represent deserialized sprite data (and serialized back, by the tools)
+use serde::{Deserialize, Serialize};
+
+// enum SpriteName, generated by build.rs from the sprite jsons of data/
+include!(concat!(env!("OUT_DIR"), "/sprite_name.rs"));
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SpriteData<N = SpriteName> 
+{
+  pub(crate) name: N,
+  pub(crate) x: i32,
+  pub(crate) y: i32,
+  pub(crate) w: u32,
+  pub(crate) h: u32,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) pivot: Option<PivotData>, // optional, e.g. "pivot": { "x": 50, "y": 100 } to rotate around the feet
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) color_key: Option<ColorKey>, // optional, overrides the sheet's one inside this sprite
+  #[serde(default, skip_serializing_if = "is_false")]
+  pub(crate) rotated: bool, // optional, stored rotated 90° clockwise: it then takes h x w pixels in the sheet
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) trim: Option<TrimData>, // optional, position and size of the original (untrimmed) image
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) nine_slice: Option<Insets> // optional, cf. nine_slice.rs
+}
+
+fn is_false(value: &bool) -> bool { !value }
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct PivotData
+{
+  pub(crate) x: i32,
+  pub(crate) y: i32
+}
+
+// "trim": { "x": 3, "y": 5, "w": 64, "h": 64 }: the stored pixels start at (3, 5) in a 64x64 image
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct TrimData
+{
+  pub(crate) x: i32,
+  pub(crate) y: i32,
+  pub(crate) w: u32,
+  pub(crate) h: u32
+}
+
+impl<N> SpriteData<N>
+{
+  // A plain sprite: none of the optional settings
+  pub fn new(name: N, rect: Rect) -> SpriteData<N>
+  {
+    SpriteData { name, x: rect.x(), y: rect.y(), w: rect.width(), h: rect.height(), pivot: None, color_key: None,
+      rotated: false, trim: None, nine_slice: None }
   }
+
+  pub fn set_name(&mut self, name: N) { self.name = name; }
+
+  // Moves / resizes the pixels taken in the sheet (what rect() gives), rotated or not
+  pub fn set_rect(&mut self, rect: Rect)
+  {
+    (self.x, self.y) = (rect.x(), rect.y());
+    (self.w, self.h) = if self.rotated { (rect.height(), rect.width()) } else { (rect.width(), rect.height()) };
+  }
+
+  pub fn name(&self) -> &N { &self.name }
+  pub fn pivot(&self) -> Option<Point> { self.pivot.map(|p| Point::new(p.x, p.y)) }
+  pub fn is_rotated(&self) -> bool { self.rotated }
+  pub fn nine_slice(&self) -> Option<Insets> { self.nine_slice }
+  pub fn trim(&self) -> Option<Trim> { self.trim.map(|t| Trim { offset: Point::new(t.x, t.y), source_size: (t.w, t.h) }) }
+  pub fn color_key(&self) -> Option<ColorKey> { self.color_key }
+
+  // The pixels actually taken in the sheet
+  pub fn rect(&self) -> Rect 
+  { 
+    if self.rotated { Rect::new(self.x, self.y, self.h, self.w) } else { Rect::new(self.x, self.y, self.w, self.h) }
+  }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SpriteSheetData<N = SpriteName> 
+{
+  pub(crate) spritesheet: String, // path of the png
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) color_key: Option<ColorKey>, // optional: "#00FFFF", [0, 255, 255] or "auto" (top-left pixel)
+  pub(crate) sprites: Vec<SpriteData<N>>,
+  #[serde(default = "Vec::new", skip_serializing_if = "Vec::is_empty")]
+  pub(crate) animations: Vec<AnimationData<N>> // optional, cf. animation.rs
 }
 
-// Represent deserialized sprite data
-use serde::Deserialize;
+impl<N> SpriteSheetData<N>
+{
+  // No sprites yet (cf. the slice_editor and atlas_packer tools)
+  pub fn new(spritesheet: &str) -> SpriteSheetData<N>
+  {
+    SpriteSheetData { spritesheet: spritesheet.to_string(), color_key: None, sprites: Vec::new(), animations: Vec::new() }
+  }
+
+  pub fn spritesheet(&self) -> &str { &self.spritesheet }
+  pub fn sprites(&self) -> &[SpriteData<N>] { &self.sprites }
+  pub fn animations(&self) -> &[AnimationData<N>] { &self.animations }
+  pub fn color_key(&self) -> Option<ColorKey> { self.color_key }
+  pub fn sprites_mut(&mut self) -> &mut Vec<SpriteData<N>> { &mut self.sprites }
+
+  // The color keys of single sprites, for load_keyed_surface
+  pub fn region_keys(&self) -> Vec<(Rect, ColorKey)>
+  {
+    self.sprites.iter().filter_map(|data| data.color_key.map(|key| (data.rect(), key))).collect()
+  }
+}
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
-pub enum SpriteName 
+impl<N: Serialize> SpriteSheetData<N>
 {
-  RedCircle,
-  GreenCircle,
-  YellowCircle,
-  BlueCircle
+  pub fn to_json(&self) -> Result<String, String>
+  {
+    serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+  }
+
+  pub fn save_json(&self, path: &std::path::Path) -> Result<(), String>
+  {
+    std::fs::write(path, self.to_json()? + "\n").map_err(|e| format!("{}: {}", path.display(), e))
+  }
 }
 
-#[derive(Deserialize)]
-pub struct SpriteData 
+// What's wrong in the rects of a sheet (indices in sprites()), for the tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SheetIssue
 {
-  name: SpriteName,
-  x: i32,
-  y: i32,
-  w: u32,
-  h: u32
+  OutOfBounds(usize),
+  Overlap(usize, usize),
+  DuplicateName(usize, usize)
 }
 
-#[derive(Deserialize)]
-pub struct SpriteSheetData 
+// (pure Rust: Rect::has_intersection goes through SDL)
+pub(crate) fn rects_overlap(a: Rect, b: Rect) -> bool
 {
-  spritesheet: String, // path of the png
-  sprites: Vec<SpriteData>
+  a.left() < b.right() && b.left() < a.right() && a.top() < b.bottom() && b.top() < a.bottom()
+}
+
+impl<N: PartialEq> SpriteSheetData<N>
+{
+  // sheet_size: the size of the png
+  pub fn validate(&self, sheet_size: (u32, u32)) -> Vec<SheetIssue>
+  {
+    let bounds = Rect::new(0, 0, sheet_size.0, sheet_size.1);
+    let mut issues = Vec::new();
+    for (i, data) in self.sprites.iter().enumerate() {
+      let rect = data.rect();
+      if rect.left() < 0 || rect.top() < 0 || rect.right() > bounds.right() || rect.bottom() > bounds.bottom() {
+        issues.push(SheetIssue::OutOfBounds(i));
+      }
+      for (j, other) in self.sprites.iter().enumerate().skip(i + 1) {
+        if rects_overlap(rect, other.rect()) {
+          issues.push(SheetIssue::Overlap(i, j));
+        }
+        if data.name == other.name {
+          issues.push(SheetIssue::DuplicateName(i, j));
+        }
+      }
+    }
+    issues
+  }
+
+  // The names of the animations with a frame on that sprite
+  pub fn animations_using(&self, name: &N) -> Vec<&str>
+  {
+    self.animations.iter()
+      .filter(|animation| animation.frames.iter().any(|frame| frame.sprite == *name))
+      .map(|animation| animation.name())
+      .collect()
+  }
+
+  // Renames a sprite, and its frames in the animations so they don't point to nothing
+  pub fn rename_sprite(&mut self, index: usize, name: N) where N: Clone
+  {
+    let old = std::mem::replace(&mut self.sprites[index].name, name.clone());
+    for frame in self.animations.iter_mut().flat_map(|animation| animation.frames.iter_mut()) {
+      if frame.sprite == old {
+        frame.sprite = name.clone();
+      }
+    }
+  }
 }
 
 // Deserialize sprite data from json
-use std::fs::File;
-use std::io::BufReader;
-use serde_json::from_reader;
+use crate::assets::read_asset;
+use serde_json::from_slice;
 
 pub fn load_sprites_from_json(file_path: &str) -> SpriteSheetData 
 {
-  let file = File::open(file_path)
-    .unwrap_or_else(|err| { prompt_err_and_panic("load_sprites_from_json failed(open)", &err.to_string(), None); });
-  let reader = BufReader::new(file);
-  let sprite_data: SpriteSheetData = from_reader(reader)
-    .unwrap_or_else(|err| { prompt_err_and_panic("load_sprites_from_json failed(read)", &err.to_string(), None); });
-  
-  sprite_data
+  load_sprites_from_json_as(file_path)
+}
+
+// Same with another kind of key, e.g. load_sprites_from_json_as::<String> for the tools
+pub fn load_sprites_from_json_as<N: SpriteKey>(file_path: &str) -> SpriteSheetData<N> 
+{
+  try_load_sprites_from_json_as(file_path)
+    .unwrap_or_else(|err| { prompt_err_and_panic("load_sprites_from_json failed", &err, None); })
 }
 
-// For now I consider there's only one spritesheet with only one json.
-pub fn create_sprites<'a>(
+// Without the message box, for the loaders that give their errors back
+pub fn try_load_sprites_from_json_as<N: SpriteKey>(file_path: &str) -> Result<SpriteSheetData<N>, String>
+{
+  let bytes = read_asset(file_path)?;
+  from_slice(&bytes).map_err(|err| format!("{}: {}", file_path, err))
+}
+
+// One json describes one spritesheet (cf. SpriteRegistry to gather several of them).
+pub fn create_sprites<'a, N: SpriteKey>(
   texture_creator: &'a TextureCreator<WindowContext>,
-  sprite_data: SpriteSheetData,
-  texture_manager: &mut TextureManager<'a>) -> HashMap<SpriteName, Sprite<'a>>
+  sprite_data: &SpriteSheetData<N>,
+  texture_manager: &mut TextureManager<'a>) -> HashMap<N, Sprite<'a, N>>
+{
+  try_create_sprites(texture_creator, sprite_data, texture_manager)
+    .unwrap_or_else(|err| { prompt_err_and_panic("create_sprites failed", &err, None); })
+}
+
+// Same, giving back the error of a missing or broken spritesheet
+pub fn try_create_sprites<'a, N: SpriteKey>(
+  texture_creator: &'a TextureCreator<WindowContext>,
+  sprite_data: &SpriteSheetData<N>,
+  texture_manager: &mut TextureManager<'a>) -> Result<HashMap<N, Sprite<'a, N>>, String>
+{
+  let tex = texture_manager.try_load_texture_with_keys(texture_creator, &sprite_data.spritesheet, 
+    sprite_data.color_key, &sprite_data.region_keys())?;
+  Ok(sprites_from_texture(&tex, sprite_data, HashMap::new()))
+}
+
+// Same, each sprite getting its collision mask (cf. collision.rs). The png is read once, for the
+// masks and the texture (the texture may be cached already, the masks are made anyway).
+pub fn try_create_sprites_with_masks<'a, N: SpriteKey>(
+  texture_creator: &'a TextureCreator<WindowContext>,
+  sprite_data: &SpriteSheetData<N>,
+  texture_manager: &mut TextureManager<'a>,
+  alpha_threshold: u8) -> Result<HashMap<N, Sprite<'a, N>>, String>
+{
+  let surface = load_keyed_surface(&sprite_data.spritesheet, sprite_data.color_key, &sprite_data.region_keys())?;
+  let masks = collision_masks_from_surface(sprite_data, &surface, alpha_threshold)?;
+  let tex = texture_manager.try_texture_from_surface(texture_creator, &sprite_data.spritesheet, &surface)?;
+  Ok(sprites_from_texture(&tex, sprite_data, masks))
+}
+
+fn sprites_from_texture<'a, N: SpriteKey>(tex: &SharedTexture<'a>, sprite_data: &SpriteSheetData<N>,
+  mut masks: HashMap<N, CollisionMask>) -> HashMap<N, Sprite<'a, N>>
 {
-  let tex = texture_manager.load_texture(texture_creator, &sprite_data.spritesheet, None); // TODO color keying
-  
   // Create a HashMap to store the sprites with their name as the key
-  let mut sprites_map: HashMap<SpriteName, Sprite<'a>> = HashMap::new();
+  let mut sprites_map: HashMap<N, Sprite<'a, N>> = HashMap::new();
     
-  for data in sprite_data.sprites {
-    sprites_map.insert(data.name, Sprite::new(tex.clone(), Rect::new(data.x, data.y, data.w, data.h), data.name));
+  for data in &sprite_data.sprites {
+    sprites_map.insert(data.name.clone(), 
+      Sprite::new(tex.clone(), data.rect(), data.name.clone())
+        .with_pivot(data.pivot())
+        .with_layout(data.rotated, data.trim())
+        .with_nine_slice(data.nine_slice)
+        .with_collision_mask(masks.remove(&data.name).map(Rc::new)));
   }
     
   sprites_map
 }
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_validate() {
+    let sheet: SpriteSheetData<String> = serde_json::from_str(r#"{ "spritesheet": "sheet.png", "sprites": [
+      { "name": "a", "x": 0, "y": 0, "w": 10, "h": 10 },
+      { "name": "b", "x": 10, "y": 0, "w": 10, "h": 10 },
+      { "name": "c", "x": 15, "y": 5, "w": 20, "h": 4, "rotated": true },
+      { "name": "a", "x": 0, "y": 20, "w": 10, "h": 10 } ] }"#).unwrap();
+    // c takes 4x20 pixels at (15, 5): over b, and past the bottom of the 20 pixel high sheet (like the second a)
+    assert_eq!(sheet.validate((40, 20)), vec![
+      SheetIssue::DuplicateName(0, 3),
+      SheetIssue::Overlap(1, 2),
+      SheetIssue::OutOfBounds(2),
+      SheetIssue::OutOfBounds(3)
+    ]);
+  }
+
+  #[test]
+  fn test_json_round_trip() {
+    let json = r##"{ "spritesheet": "sheet.png", "color_key": [0, 255, 255], "sprites": [
+      { "name": "a", "x": 0, "y": 0, "w": 10, "h": 20, "pivot": { "x": 5, "y": 20 } },
+      { "name": "b", "x": 10, "y": 0, "w": 10, "h": 20, "rotated": true } ] }"##;
+    let mut sheet: SpriteSheetData<String> = serde_json::from_str(json).unwrap();
+    sheet.sprites_mut()[1].set_rect(Rect::new(12, 0, 20, 10));
+    sheet.sprites_mut().push(SpriteData::new("c".to_string(), Rect::new(0, 30, 8, 8)));
+
+    let written = sheet.to_json().unwrap();
+    assert!(written.contains("\"#00FFFF\"") && !written.contains("animations") && !written.contains("trim"));
+    let read: SpriteSheetData<String> = serde_json::from_str(&written).unwrap();
+    assert_eq!(read.color_key(), Some(ColorKey::Rgb(Color::RGB(0, 255, 255))));
+    assert_eq!(read.sprites()[0].pivot(), Some(Point::new(5, 20)));
+    // still rotated: 10x20 stored as 20x10
+    assert_eq!((read.sprites()[1].is_rotated(), read.sprites()[1].w, read.sprites()[1].rect()), (true, 10, Rect::new(12, 0, 20, 10)));
+    assert_eq!(read.sprites()[2].rect(), Rect::new(0, 30, 8, 8));
+  }
+
+  #[test]
+  fn test_rename_sprite() {
+    let mut sheet: SpriteSheetData<String> = serde_json::from_str(r#"{ "spritesheet": "sheet.png",
+      "sprites": [ { "name": "a", "x": 0, "y": 0, "w": 10, "h": 10 }, { "name": "b", "x": 10, "y": 0, "w": 10, "h": 10 } ],
+      "animations": [ { "name": "Blink", "frames": [ { "sprite": "a", "duration": 100 }, { "sprite": "b", "duration": 100 } ] },
+                      { "name": "Still", "frames": [ { "sprite": "b", "duration": 100 } ] } ] }"#).unwrap();
+    assert_eq!(sheet.animations_using(&"b".to_string()), vec!["Blink", "Still"]);
+    sheet.rename_sprite(1, "c".to_string());
+    assert_eq!(sheet.sprites()[1].name(), "c");
+    assert!(sheet.animations_using(&"b".to_string()).is_empty());
+    assert_eq!(sheet.animations_using(&"c".to_string()), vec!["Blink", "Still"]);
+    assert_eq!(sheet.animations_using(&"a".to_string()), vec!["Blink"]);
+  }
+}
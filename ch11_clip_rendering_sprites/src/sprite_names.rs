@@ -0,0 +1,47 @@
+// What a sprite name can be: it becomes a variant of the generated SpriteName enum. Also
+// compiled into build.rs (#[path]), so the tools check exactly what the build does.
+
+// Strict and reserved keywords, which can't be variants
+const KEYWORDS: [&str; 52] = [
+  "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+  "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+  "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+  "use", "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro", "override",
+  "priv", "try", "typeof", "unsized", "virtual", "yield"
+];
+
+// Why a name can't be a sprite name, if it can't
+pub fn check_sprite_name(name: &str) -> Result<(), String>
+{
+  let mut chars = name.chars();
+  let valid_chars = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+  if !valid_chars {
+    Err(format!("\"{}\" isn't made of letters, digits and _ (not starting with a digit)", name))
+  }
+  else if name == "_" {
+    Err("\"_\" alone isn't a name".to_string())
+  }
+  else if KEYWORDS.contains(&name) {
+    Err(format!("\"{}\" is a Rust keyword", name))
+  }
+  else {
+    Ok(())
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_check_sprite_name() {
+    assert!(check_sprite_name("Red_Circle2").is_ok() && check_sprite_name("_Hidden").is_ok());
+    assert!(check_sprite_name("2Red").is_err() && check_sprite_name("Red Circle").is_err() && check_sprite_name("").is_err());
+    assert_eq!(check_sprite_name("Self"), Err("\"Self\" is a Rust keyword".to_string()));
+    assert!(check_sprite_name("_").is_err() && check_sprite_name("type").is_err());
+  }
+}
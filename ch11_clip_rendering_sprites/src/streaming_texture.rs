@@ -0,0 +1,153 @@
+// Procedural textures (cf. lazyfoo chapters 40 and 42, texture streaming and pixel manipulation):
+// a streaming texture whose pixels are written by the CPU, e.g. every frame for a heat map.
+// It is created by the TextureManager and shares its ownership model (SharedTexture), so it can
+// be cut into sprites and drawn like any other texture.
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::{BlendMode, TextureCreator};
+use sdl2::video::WindowContext;
+
+use crate::texture::SharedTexture;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Byte order R, G, B, A in memory whatever the endianness
+pub const STREAMING_FORMAT: PixelFormatEnum = PixelFormatEnum::RGBA32;
+const BYTES_PER_PIXEL: usize = 4;
+
+// Safe view over the locked pixels. The rows may be longer than width * 4 bytes (pitch),
+// which this type hides. The content is undefined when locked: write every pixel.
+pub struct PixelBuffer<'b>
+{
+  pixels: &'b mut [u8],
+  pitch: usize,
+  width: u32,
+  height: u32
+}
+
+impl<'b> PixelBuffer<'b>
+{
+  pub fn width(&self) -> u32 { self.width }
+  pub fn height(&self) -> u32 { self.height }
+
+  // Out-of-bounds coordinates are ignored
+  pub fn set(&mut self, x: u32, y: u32, color: Color)
+  {
+    if x < self.width && y < self.height {
+      let offset = y as usize * self.pitch + x as usize * BYTES_PER_PIXEL;
+      self.pixels[offset..offset + BYTES_PER_PIXEL].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+    }
+  }
+
+  pub fn fill(&mut self, color: Color)
+  {
+    for y in 0..self.height {
+      for x in 0..self.width {
+        self.set(x, y, color);
+      }
+    }
+  }
+
+  // The raw bytes of one row (R, G, B, A for each pixel), without the pitch padding.
+  // Panics if y is out of bounds (there's no row to give back).
+  pub fn row_mut(&mut self, y: u32) -> &mut [u8]
+  {
+    assert!(y < self.height, "row {} out of a {}x{} pixel buffer", y, self.width, self.height);
+    let start = y as usize * self.pitch;
+    &mut self.pixels[start..start + self.width as usize * BYTES_PER_PIXEL]
+  }
+}
+
+#[derive(Clone)] // cheap: the texture is shared
+pub struct StreamingTexture<'a>
+{
+  texture: SharedTexture<'a>,
+  width: u32,
+  height: u32,
+  staging: Rc<RefCell<Vec<u8>>> // the bytes of upload, kept from one frame to the next
+}
+
+impl<'a> StreamingTexture<'a>
+{
+  // Use TextureManager::create_streaming_texture, which owns it
+  pub(crate) fn new(texture_creator: &'a TextureCreator<WindowContext>, width: u32, height: u32) -> Result<StreamingTexture<'a>, String>
+  {
+    let mut texture = texture_creator.create_texture_streaming(STREAMING_FORMAT, width, height)
+      .map_err(|e| e.to_string())?;
+    texture.set_blend_mode(BlendMode::Blend);
+    Ok(StreamingTexture { texture: Rc::new(RefCell::new(texture)), width, height, staging: Rc::default() })
+  }
+
+  pub fn width(&self) -> u32 { self.width }
+  pub fn height(&self) -> u32 { self.height }
+  pub fn texture(&self) -> &SharedTexture<'a> { &self.texture }
+
+  // Locks the whole texture, lets write_fn modify the pixels, and unlocks it
+  pub fn with_lock<F>(&self, write_fn: F) -> Result<(), String>
+    where F: FnOnce(&mut PixelBuffer)
+  {
+    let (width, height) = (self.width, self.height);
+    self.texture.borrow_mut().with_lock(None, |pixels, pitch| {
+      write_fn(&mut PixelBuffer { pixels, pitch, width, height });
+    })
+  }
+
+  // Uploads a CPU-side buffer of width * height colors, row by row
+  pub fn upload(&self, colors: &[Color]) -> Result<(), String>
+  {
+    if colors.len() != (self.width * self.height) as usize {
+      return Err(format!("upload: {} colors for a {}x{} texture", colors.len(), self.width, self.height));
+    }
+    let mut bytes = self.staging.borrow_mut();
+    bytes.clear(); // (keeps the allocation)
+    bytes.extend(colors.iter().flat_map(|c| [c.r, c.g, c.b, c.a]));
+    self.upload_bytes(&bytes)
+  }
+
+  // Same with raw bytes in STREAMING_FORMAT (tightly packed rows)
+  pub fn upload_bytes(&self, bytes: &[u8]) -> Result<(), String>
+  {
+    let pitch = self.width as usize * BYTES_PER_PIXEL;
+    if bytes.len() != pitch * self.height as usize {
+      return Err(format!("upload_bytes: {} bytes for a {}x{} texture", bytes.len(), self.width, self.height));
+    }
+    self.texture.borrow_mut().update(None, bytes, pitch).map_err(|e| e.to_string())
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_pixel_buffer_respects_pitch() {
+    // 2x2 pixels, rows of 12 bytes (4 bytes of padding)
+    let mut bytes = [0u8; 24];
+    let mut buffer = PixelBuffer { pixels: &mut bytes, pitch: 12, width: 2, height: 2 };
+    buffer.set(1, 1, Color::RGBA(1, 2, 3, 4));
+    buffer.set(2, 0, Color::RGBA(9, 9, 9, 9)); // out of bounds, ignored
+    assert_eq!(&bytes[16..20], &[1, 2, 3, 4]);
+    assert_eq!(bytes.iter().filter(|&&b| b != 0).count(), 4);
+  }
+
+  #[test]
+  fn test_pixel_buffer_row() {
+    let mut bytes = [0u8; 24];
+    let mut buffer = PixelBuffer { pixels: &mut bytes, pitch: 12, width: 2, height: 2 };
+    buffer.fill(Color::RGBA(0xFF, 0, 0, 0xFF));
+    assert_eq!(buffer.row_mut(1), &[0xFF, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF]);
+    assert_eq!(&bytes[8..12], &[0, 0, 0, 0]); // padding untouched
+  }
+
+  #[test]
+  #[should_panic(expected = "row 2 out of a 2x2 pixel buffer")]
+  fn test_pixel_buffer_row_out_of_bounds() {
+    let mut bytes = [0u8; 24];
+    let mut buffer = PixelBuffer { pixels: &mut bytes, pitch: 12, width: 2, height: 2 };
+    buffer.row_mut(2);
+  }
+}
@@ -0,0 +1,583 @@
+// Text layout: places a (possibly styled) text inside a box, with wrapping, alignment and
+// overflow handling. It only needs to measure strings (the FontMetrics trait), so the very
+// same layout works with bitmap fonts and TrueType fonts, and UI code can size its panels
+// without drawing anything. The fonts then draw the runs of the layout (render_layout).
+//
+// Markup, for inline style changes: [b]bold[/b], [i]italic[/i], [u]underline[/u],
+// [s]strikethrough[/s], [color=#FF8000]orange[/color]. Tags nest, "[[" is a literal '['.
+// Colors are "#RRGGBB", or "#RRGGBBAA" with the alpha last (not Tiled's "#AARRGGBB").
+
+use crate::texture::{parse_hex_color, HexColor};
+
+use sdl2::pixels::Color;
+use unicode_segmentation::UnicodeSegmentation;
+
+// What the layout needs from a font
+pub trait FontMetrics
+{
+  fn text_width(&self, text: &str, style: &SpanStyle) -> u32;
+  fn line_height(&self) -> u32;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpanStyle
+{
+  pub color: Option<Color>, // None: the font's color
+  pub bold: bool,
+  pub italic: bool,
+  pub underline: bool,
+  pub strikethrough: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span
+{
+  pub text: String,
+  pub style: SpanStyle
+}
+
+// Text without markup
+pub fn plain(text: &str) -> Vec<Span>
+{
+  vec![Span { text: text.to_string(), style: SpanStyle::default() }]
+}
+
+// "#RRGGBB" or "#RRGGBBAA": alpha last, like CSS (Tiled puts it first)
+fn parse_color(text: &str) -> Option<Color>
+{
+  parse_hex_color(text.strip_prefix('#')?, HexColor::Rgba)
+}
+
+pub fn parse_markup(text: &str) -> Result<Vec<Span>, String>
+{
+  let mut spans = Vec::new();
+  let mut open_tags: Vec<(&str, SpanStyle)> = Vec::new(); // (tag, style inside it)
+  let mut current = String::new();
+  let mut rest = text;
+
+  fn flush(spans: &mut Vec<Span>, current: &mut String, style: SpanStyle)
+  {
+    if !current.is_empty() {
+      spans.push(Span { text: std::mem::take(current), style });
+    }
+  }
+
+  while let Some(start) = rest.find('[') {
+    current.push_str(&rest[..start]);
+    rest = &rest[start..];
+    if let Some(after) = rest.strip_prefix("[[") {
+      current.push('[');
+      rest = after;
+      continue;
+    }
+    let position = text.len() - rest.len();
+    let end = rest.find(']').ok_or_else(|| format!("unclosed '[' at byte {}", position))?;
+    let tag = &rest[1..end];
+    rest = &rest[end + 1..];
+
+    let style = open_tags.last().map(|t| t.1).unwrap_or_default();
+    flush(&mut spans, &mut current, style);
+    if let Some(name) = tag.strip_prefix('/') {
+      match open_tags.pop()
+      {
+        Some((open, _)) if open == name => {},
+        Some((open, _)) => return Err(format!("[/{}] at byte {} closes [{}]", name, position, open)),
+        None => return Err(format!("[/{}] at byte {} was never opened", name, position))
+      }
+      continue;
+    }
+
+    let (name, value) = tag.split_once('=').unwrap_or((tag, ""));
+    let mut style = style;
+    match name
+    {
+      "b" => style.bold = true,
+      "i" => style.italic = true,
+      "u" => style.underline = true,
+      "s" => style.strikethrough = true,
+      "color" => style.color = Some(parse_color(value).ok_or_else(|| format!("bad color '{}' at byte {}", value, position))?),
+      _ => return Err(format!("unknown tag [{}] at byte {}", tag, position))
+    }
+    open_tags.push((name, style));
+  }
+  current.push_str(rest);
+  flush(&mut spans, &mut current, open_tags.last().map(|t| t.1).unwrap_or_default());
+
+  match open_tags.last()
+  {
+    Some((open, _)) => Err(format!("[{}] never closed", open)),
+    None => Ok(spans)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HAlign
+{
+  #[default]
+  Left,
+  Center,
+  Right,
+  Justify // but the last line of each paragraph, left aligned
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VAlign
+{
+  #[default]
+  Top,
+  Middle,
+  Bottom
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Wrap
+{
+  #[default]
+  Word,     // between words, words wider than the box being cut between graphemes
+  Grapheme, // anywhere between graphemes (e.g. for CJK text)
+  None      // only at '\n'
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow
+{
+  #[default]
+  Visible,  // all the lines, even below the box
+  Clip,     // the lines which don't fit are dropped
+  Ellipsis  // same, the last line kept ending with the ellipsis (lines too wide too, with Wrap::None)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutOptions
+{
+  pub h_align: HAlign,
+  pub v_align: VAlign,
+  pub wrap: Wrap,
+  pub overflow: Overflow,
+  pub ellipsis: String // "..." by default, '…' not being in every bitmap font
+}
+
+impl Default for LayoutOptions
+{
+  fn default() -> LayoutOptions
+  {
+    LayoutOptions { h_align: HAlign::Left, v_align: VAlign::Top, wrap: Wrap::Word, overflow: Overflow::Visible, ellipsis: "...".to_string() }
+  }
+}
+
+// A piece of text in one style, placed relative to the top left corner of the box
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextRun
+{
+  pub text: String,
+  pub style: SpanStyle,
+  pub x: i32,
+  pub y: i32,
+  pub width: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout
+{
+  pub runs: Vec<TextRun>,
+  pub width: u32,  // of the widest line
+  pub height: u32, // of all the lines kept
+  pub line_height: u32,
+  pub line_count: usize,
+  pub truncated: bool // lines were dropped (Clip, Ellipsis)
+}
+
+#[derive(Debug, Clone)]
+struct Piece
+{
+  span: usize,
+  text: String
+}
+
+enum Item
+{
+  Word(Vec<Piece>), // a word can change style in the middle
+  Space(Vec<Piece>),
+  Newline
+}
+
+#[derive(Debug, Clone)]
+struct LineItem
+{
+  pieces: Vec<Piece>,
+  width: u32,
+  is_space: bool
+}
+
+struct Line
+{
+  items: Vec<LineItem>,
+  hard_break: bool // ends a paragraph (not justified)
+}
+
+impl Line
+{
+  fn width(&self) -> u32 { self.items.iter().map(|i| i.width).sum() }
+}
+
+fn push_piece(pieces: &mut Vec<Piece>, span: usize, text: &str)
+{
+  match pieces.last_mut()
+  {
+    Some(last) if last.span == span => last.text.push_str(text),
+    _ => pieces.push(Piece { span, text: text.to_string() })
+  }
+}
+
+// Words, spaces and line breaks, in order
+fn split_items(spans: &[Span]) -> Vec<Item>
+{
+  let mut items = Vec::new();
+  for (i, span) in spans.iter().enumerate() {
+    for segment in span.text.split_word_bounds() {
+      if segment == "\n" || segment == "\r\n" {
+        items.push(Item::Newline);
+        continue;
+      }
+      // a word goes on until a space, whatever the word bounds and the spans in between
+      let is_space = segment.chars().all(char::is_whitespace);
+      match (items.last_mut(), is_space)
+      {
+        (Some(Item::Word(pieces)), false) | (Some(Item::Space(pieces)), true) => push_piece(pieces, i, segment),
+        _ =>
+        {
+          let pieces = vec![Piece { span: i, text: segment.to_string() }];
+          items.push(if is_space { Item::Space(pieces) } else { Item::Word(pieces) });
+        }
+      }
+    }
+  }
+  items
+}
+
+struct Layouter<'m, M: FontMetrics + ?Sized>
+{
+  metrics: &'m M,
+  spans: &'m [Span],
+  max_width: u32,
+  lines: Vec<Line>,
+  current: Vec<LineItem>,
+  pending_space: Option<LineItem> // only placed if a word follows on the same line
+}
+
+impl<'m, M: FontMetrics + ?Sized> Layouter<'m, M>
+{
+  fn width(&self, pieces: &[Piece]) -> u32
+  {
+    pieces.iter().map(|p| self.metrics.text_width(&p.text, &self.spans[p.span].style)).sum()
+  }
+
+  fn item(&self, pieces: Vec<Piece>, is_space: bool) -> LineItem
+  {
+    LineItem { width: self.width(&pieces), pieces, is_space }
+  }
+
+  fn current_width(&self) -> u32 { self.current.iter().map(|i| i.width).sum() }
+
+  // The longest start of `pieces` that fits in `available`, cut between graphemes, and the rest
+  fn split(&self, pieces: &[Piece], available: u32) -> (Vec<Piece>, Vec<Piece>)
+  {
+    let mut head = Vec::new();
+    let mut used = 0;
+    for (k, piece) in pieces.iter().enumerate() {
+      let style = &self.spans[piece.span].style;
+      let piece_width = self.metrics.text_width(&piece.text, style);
+      if used + piece_width <= available {
+        used += piece_width;
+        head.push(piece.clone());
+        continue;
+      }
+      let fit = piece.text.grapheme_indices(true)
+        .map(|(i, g)| i + g.len())
+        .take_while(|&end| used + self.metrics.text_width(&piece.text[..end], style) <= available)
+        .last()
+        .unwrap_or(0);
+      if fit > 0 {
+        head.push(Piece { span: piece.span, text: piece.text[..fit].to_string() });
+      }
+      let mut tail = vec![Piece { span: piece.span, text: piece.text[fit..].to_string() }];
+      tail.extend_from_slice(&pieces[k + 1..]);
+      return (head, tail);
+    }
+    (head, Vec::new())
+  }
+
+  // At least one grapheme, so that the layout always moves on
+  fn split_at_least_one(&self, pieces: &[Piece], available: u32) -> (Vec<Piece>, Vec<Piece>)
+  {
+    let (head, tail) = self.split(pieces, available);
+    if !head.is_empty() {
+      return (head, tail);
+    }
+    let first = &tail[0];
+    let end = first.text.graphemes(true).next().map_or(0, str::len);
+    let mut rest = tail.clone();
+    rest[0].text = first.text[end..].to_string();
+    if rest[0].text.is_empty() {
+      rest.remove(0);
+    }
+    (vec![Piece { span: first.span, text: first.text[..end].to_string() }], rest)
+  }
+
+  fn finish_line(&mut self, hard_break: bool)
+  {
+    self.pending_space = None; // spaces at the end of a line don't count
+    let items = std::mem::take(&mut self.current);
+    self.lines.push(Line { items, hard_break });
+  }
+
+  fn place(&mut self, item: LineItem)
+  {
+    if let Some(space) = self.pending_space.take() {
+      self.current.push(space);
+    }
+    self.current.push(item);
+  }
+
+  fn add_word(&mut self, wrap: Wrap, pieces: Vec<Piece>)
+  {
+    let mut pieces = pieces;
+    while !pieces.is_empty() {
+      let word = self.item(pieces, false);
+      let space = self.pending_space.as_ref().map_or(0, |s| s.width);
+      let used = self.current_width() + space;
+      if wrap == Wrap::None || used + word.width <= self.max_width {
+        self.place(word);
+        return;
+      }
+      let line_is_empty = self.current.is_empty();
+      if wrap == Wrap::Word && !line_is_empty {
+        self.finish_line(false); // the word goes to the next line
+        pieces = word.pieces;
+        continue;
+      }
+      // cut the word: what fits on this line, the rest on the next ones
+      let available = self.max_width.saturating_sub(used);
+      let (head, tail) = if line_is_empty {
+        self.split_at_least_one(&word.pieces, available)
+      }
+      else {
+        self.split(&word.pieces, available)
+      };
+      if !head.is_empty() {
+        let head = self.item(head, false);
+        self.place(head);
+      }
+      self.finish_line(false);
+      pieces = tail;
+    }
+  }
+
+  // Cuts the line to fit with the ellipsis at its end
+  fn ellipsize(&self, line: &mut Line, ellipsis: &str)
+  {
+    let span = line.items.iter().rev().find_map(|i| i.pieces.last()).map_or(0, |p| p.span);
+    let ellipsis = self.item(vec![Piece { span, text: ellipsis.to_string() }], false);
+    let available = self.max_width.saturating_sub(ellipsis.width);
+
+    let mut items = Vec::new();
+    let mut used = 0;
+    for item in line.items.drain(..) {
+      if used + item.width <= available {
+        used += item.width;
+        items.push(item);
+        continue;
+      }
+      if !item.is_space {
+        let (head, _) = self.split(&item.pieces, available - used);
+        if !head.is_empty() {
+          items.push(self.item(head, false));
+        }
+      }
+      break;
+    }
+    while items.last().is_some_and(|i| i.is_space) {
+      items.pop();
+    }
+    items.push(ellipsis);
+    line.items = items;
+    line.hard_break = true; // not justified
+  }
+}
+
+// Lays the text out in a box of `size` (width, height)
+pub fn layout_text<M: FontMetrics + ?Sized>(metrics: &M, spans: &[Span], size: (u32, u32), options: &LayoutOptions) -> TextLayout
+{
+  let (max_width, max_height) = size;
+  let mut layouter = Layouter { metrics, spans, max_width, lines: Vec::new(), current: Vec::new(), pending_space: None };
+
+  let mut last_was_newline = true; // an empty text still has one (empty) line
+  for item in split_items(spans) {
+    last_was_newline = matches!(item, Item::Newline);
+    match item
+    {
+      Item::Newline => layouter.finish_line(true),
+      Item::Space(pieces) =>
+      {
+        let space = layouter.item(pieces, true);
+        layouter.pending_space = Some(space);
+      },
+      Item::Word(pieces) => layouter.add_word(options.wrap, pieces)
+    }
+  }
+  if !layouter.current.is_empty() || last_was_newline {
+    layouter.finish_line(true);
+  }
+
+  let line_height = metrics.line_height();
+  let mut lines = std::mem::take(&mut layouter.lines);
+  let mut truncated = false;
+  if options.overflow != Overflow::Visible {
+    let max_lines = (max_height / line_height.max(1)).max(1) as usize; // at least one line
+    if lines.len() > max_lines {
+      lines.truncate(max_lines);
+      truncated = true;
+      if options.overflow == Overflow::Ellipsis {
+        layouter.ellipsize(lines.last_mut().unwrap(), &options.ellipsis);
+      }
+    }
+    if options.overflow == Overflow::Ellipsis {
+      for line in lines.iter_mut().filter(|l| l.width() > max_width) {
+        layouter.ellipsize(line, &options.ellipsis);
+      }
+    }
+  }
+
+  let height = lines.len() as u32 * line_height;
+  let top = match options.v_align
+  {
+    VAlign::Top => 0,
+    VAlign::Middle => (max_height as i64 - height as i64) / 2,
+    VAlign::Bottom => max_height as i64 - height as i64
+  } as i32;
+
+  let mut runs: Vec<TextRun> = Vec::new();
+  for (n, line) in lines.iter().enumerate() {
+    let line_width = line.width();
+    let slack = max_width as i64 - line_width as i64;
+    let gaps = line.items.iter().filter(|i| i.is_space).count() as i64;
+    let justify = options.h_align == HAlign::Justify && !line.hard_break && gaps > 0 && slack > 0;
+    let mut x = match options.h_align
+    {
+      HAlign::Left | HAlign::Justify => 0,
+      HAlign::Center => slack / 2,
+      HAlign::Right => slack
+    };
+    let y = top + (n as u32 * line_height) as i32;
+
+    let mut gap = 0;
+    for item in &line.items {
+      if item.is_space && justify {
+        // the slack spread over the spaces, the rounding too
+        x += item.width as i64 + slack * (gap + 1) / gaps - slack * gap / gaps;
+        gap += 1;
+        continue;
+      }
+      for piece in &item.pieces {
+        let width = metrics.text_width(&piece.text, &spans[piece.span].style);
+        let style = spans[piece.span].style;
+        // consecutive text in the same style makes one run (fewer draws)
+        match runs.last_mut()
+        {
+          Some(run) if run.style == style && run.y == y && run.x as i64 + run.width as i64 == x =>
+          {
+            run.text.push_str(&piece.text);
+            run.width += width;
+          },
+          _ => runs.push(TextRun { text: piece.text.clone(), style, x: x as i32, y, width })
+        }
+        x += width as i64;
+      }
+    }
+  }
+
+  TextLayout {
+    runs,
+    width: lines.iter().map(Line::width).max().unwrap_or(0),
+    height,
+    line_height,
+    line_count: lines.len(),
+    truncated
+  }
+}
+
+// Size the text takes once wrapped at max_width, e.g. to size a panel before drawing it
+pub fn measure_text<M: FontMetrics + ?Sized>(metrics: &M, spans: &[Span], max_width: u32, wrap: Wrap) -> (u32, u32)
+{
+  let options = LayoutOptions { wrap, ..LayoutOptions::default() };
+  let layout = layout_text(metrics, spans, (max_width, u32::MAX), &options);
+  (layout.width, layout.height)
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  // 10 px per grapheme, 12 in bold, lines of 20 px
+  struct Monospace;
+
+  impl FontMetrics for Monospace
+  {
+    fn text_width(&self, text: &str, style: &SpanStyle) -> u32 {
+      text.graphemes(true).count() as u32 * if style.bold { 12 } else { 10 }
+    }
+    fn line_height(&self) -> u32 { 20 }
+  }
+
+  fn lines(layout: &TextLayout) -> Vec<(i32, i32, &str)> {
+    layout.runs.iter().map(|r| (r.x, r.y, r.text.as_str())).collect()
+  }
+
+  #[test]
+  fn test_parse_markup() {
+    let spans = parse_markup("a [b]b [color=#FF0000]c[/color][/b] [[d]").unwrap();
+    let red_bold = SpanStyle { bold: true, color: Some(Color::RGB(255, 0, 0)), ..SpanStyle::default() };
+    assert_eq!(spans.iter().map(|s| (s.text.as_str(), s.style)).collect::<Vec<_>>(), vec![
+      ("a ", SpanStyle::default()),
+      ("b ", SpanStyle { bold: true, ..SpanStyle::default() }),
+      ("c", red_bold),
+      (" [d]", SpanStyle::default())
+    ]);
+    assert!(parse_markup("[b]x[/i]").is_err());
+    assert!(parse_markup("[b]x").is_err());
+    assert!(parse_markup("[wave]x[/wave]").is_err());
+  }
+
+  #[test]
+  fn test_word_wrap_and_long_words() {
+    let layout = layout_text(&Monospace, &plain("the quick brown\nfox abcdefghijkl"), (100, 200), &LayoutOptions::default());
+    assert_eq!(lines(&layout), vec![(0, 0, "the quick"), (0, 20, "brown"), (0, 40, "fox"), (0, 60, "abcdefghij"), (0, 80, "kl")]);
+    assert_eq!((layout.width, layout.height), (100, 100));
+    assert_eq!(measure_text(&Monospace, &plain("the quick brown"), 100, Wrap::None), (150, 20));
+  }
+
+  #[test]
+  fn test_alignment() {
+    let options = LayoutOptions { h_align: HAlign::Right, v_align: VAlign::Bottom, ..LayoutOptions::default() };
+    let layout = layout_text(&Monospace, &plain("ab"), (100, 100), &options);
+    assert_eq!(lines(&layout), vec![(80, 80, "ab")]);
+
+    // justified but for the last line
+    let options = LayoutOptions { h_align: HAlign::Justify, ..LayoutOptions::default() };
+    let layout = layout_text(&Monospace, &plain("a b c dddd"), (60, 100), &options);
+    assert_eq!(lines(&layout), vec![(0, 0, "a"), (25, 0, "b"), (50, 0, "c"), (0, 20, "dddd")]);
+  }
+
+  #[test]
+  fn test_ellipsis_and_styles() {
+    let options = LayoutOptions { overflow: Overflow::Ellipsis, ..LayoutOptions::default() };
+    let layout = layout_text(&Monospace, &parse_markup("one two [b]three[/b] four").unwrap(), (80, 40), &options);
+    assert!(layout.truncated);
+    // the ellipsis takes the style of the text it ends, and so joins its run
+    assert_eq!(lines(&layout), vec![(0, 0, "one two"), (0, 20, "thr...")]);
+    assert!(layout.runs[1].style.bold);
+  }
+}
@@ -1,52 +1,325 @@
 use sdl2::render::{Texture, TextureCreator};
 use sdl2::video::WindowContext;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::surface::Surface;
+use serde::{Deserialize, Serialize};
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+use crate::assets::load_surface;
+use crate::streaming_texture::StreamingTexture;
 use crate::prompt_err_and_panic;
 
+// A texture shared by several owners (e.g. all the sprites of a sheet).
+// The RefCell is there because color/alpha modulation and blend mode are texture state:
+// changing them needs a &mut Texture, even if only for the time of one draw.
+pub type SharedTexture<'a> = Rc<RefCell<Texture<'a>>>;
+
+// Color key as declared in the json: "#00FFFF", "00ffff", [0, 255, 255], or "auto",
+// which takes the color of the top-left pixel (of the sheet, or of the sprite).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "ColorKeyData", into = "ColorKeyData")]
+pub enum ColorKey
+{
+  Rgb(Color),
+  TopLeftPixel
+}
+
+// How a color is written in hex, the '#' being optional. The 8 digit forms don't agree on
+// where the alpha goes, hence one name per order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexColor
+{
+  Rgb,  // "#RRGGBB" only
+  Rgba, // "#RRGGBB" or "#RRGGBBAA", like CSS (the text markup)
+  Argb  // "#RRGGBB" or "#AARRGGBB", like Tiled
+}
+
+pub fn parse_hex_color(text: &str, format: HexColor) -> Option<Color>
+{
+  let hex = text.strip_prefix('#').unwrap_or(text);
+  if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return None; // from_str_radix would take a '+'
+  }
+  let value = u32::from_str_radix(hex, 16).ok()?;
+  let byte = |shift: u32| (value >> shift) as u8;
+  match (hex.len(), format)
+  {
+    (6, _) => Some(Color::RGB(byte(16), byte(8), byte(0))),
+    (8, HexColor::Rgba) => Some(Color::RGBA(byte(24), byte(16), byte(8), byte(0))),
+    (8, HexColor::Argb) => Some(Color::RGBA(byte(16), byte(8), byte(0), byte(24))),
+    _ => None
+  }
+}
+
+// Also read by the particle colors ("auto" aside)
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum ColorKeyData
+{
+  Text(String),
+  Triple([u8; 3])
+}
+
+// Written back as "#RRGGBB" or "auto"
+impl From<ColorKey> for ColorKeyData
+{
+  fn from(key: ColorKey) -> ColorKeyData
+  {
+    match key
+    {
+      ColorKey::Rgb(c) => ColorKeyData::Text(format!("#{:02X}{:02X}{:02X}", c.r, c.g, c.b)),
+      ColorKey::TopLeftPixel => ColorKeyData::Text("auto".to_string())
+    }
+  }
+}
+
+impl TryFrom<ColorKeyData> for ColorKey
+{
+  type Error = String;
+
+  fn try_from(data: ColorKeyData) -> Result<ColorKey, String>
+  {
+    match data
+    {
+      ColorKeyData::Triple([r, g, b]) => Ok(ColorKey::Rgb(Color::RGB(r, g, b))),
+      ColorKeyData::Text(text) if text.eq_ignore_ascii_case("auto") => Ok(ColorKey::TopLeftPixel),
+      ColorKeyData::Text(text) => parse_hex_color(&text, HexColor::Rgb).map(ColorKey::Rgb)
+        .ok_or_else(|| format!("invalid color key \"{}\" (expected \"#RRGGBB\", [r, g, b] or \"auto\")", text))
+    }
+  }
+}
+
+// Reads one pixel of a RGBA32 surface
+fn pixel_at(surface: &Surface, x: i32, y: i32) -> Color
+{
+  let pitch = surface.pitch() as usize;
+  surface.with_lock(|pixels| {
+    let offset = y as usize * pitch + x as usize * 4;
+    Color::RGBA(pixels[offset], pixels[offset + 1], pixels[offset + 2], pixels[offset + 3])
+  })
+}
+
+fn resolve_key(surface: &Surface, key: ColorKey, rect: Rect) -> Color
+{
+  match key
+  {
+    ColorKey::Rgb(col) => col,
+    ColorKey::TopLeftPixel => pixel_at(surface, rect.x(), rect.y())
+  }
+}
+
+// Per-region color keying, which SDL can't do (one color key per surface): the keyed pixels
+// of every region become transparent, then the sheet key applies everywhere else.
+// The surface must be RGBA32.
+pub fn apply_color_keys(surface: &mut Surface, sheet_key: Option<ColorKey>, region_keys: &[(Rect, ColorKey)])
+{
+  let bounds = surface.rect();
+  let (width, pitch) = (surface.width() as usize, surface.pitch() as usize);
+
+  // (rect clipped to the surface, resolved color) for each region, then the sheet
+  let mut keys: Vec<(Rect, Color)> = region_keys.iter()
+    .filter_map(|&(rect, key)| rect.intersection(bounds).map(|r| (r, resolve_key(surface, key, r))))
+    .collect();
+  let sheet_key = sheet_key.map(|key| resolve_key(surface, key, bounds));
+
+  let mut has_own_key = vec![false; width * surface.height() as usize];
+  for (rect, _) in &keys {
+    for y in rect.top()..rect.bottom() {
+      for x in rect.left()..rect.right() {
+        has_own_key[y as usize * width + x as usize] = true;
+      }
+    }
+  }
+  if let Some(col) = sheet_key {
+    keys.push((bounds, col));
+  }
+
+  surface.with_lock_mut(|pixels| {
+    for (i, (rect, col)) in keys.iter().enumerate() {
+      let is_sheet_key = sheet_key.is_some() && i == keys.len() - 1;
+      for y in rect.top()..rect.bottom() {
+        for x in rect.left()..rect.right() {
+          if is_sheet_key && has_own_key[y as usize * width + x as usize] {
+            continue; // the sprite's own key wins
+          }
+          let offset = y as usize * pitch + x as usize * 4;
+          if pixels[offset..offset + 3] == [col.r, col.g, col.b] {
+            pixels[offset + 3] = 0;
+          }
+        }
+      }
+    }
+  });
+}
+
+// An image with the color keys of a json applied, ready to become a texture
+pub fn load_keyed_surface(img_path: &str, sheet_key: Option<ColorKey>, region_keys: &[(Rect, ColorKey)])
+  -> Result<Surface<'static>, String>
+{
+  let mut s = load_surface(img_path).map_err(|e| format!("{}: {}", img_path, e))?;
+  match (sheet_key, region_keys.is_empty())
+  {
+    (None, true) => {},
+    (Some(ColorKey::Rgb(col)), true) => s.set_color_key(true, col)?, // the simple case, SDL does it
+    _ =>
+    {
+      s = s.convert_format(PixelFormatEnum::RGBA32)?;
+      apply_color_keys(&mut s, sheet_key, region_keys);
+    }
+  }
+  Ok(s)
+}
 
 pub struct TextureManager<'a> {
-  textures: HashMap<String, Rc<Texture<'a>>>  // HashMap for caching textures by file path
+  textures: HashMap<String, SharedTexture<'a>>,  // HashMap for caching textures by file path
+  streaming_textures: HashMap<String, StreamingTexture<'a>>  // procedural textures, by name
+}
+
+impl<'a> Default for TextureManager<'a>
+{
+  fn default() -> TextureManager<'a>
+  {
+    TextureManager::new()
+  }
 }
 
 impl<'a> TextureManager<'a>
 {
   pub fn new() -> TextureManager<'a>
   {
-    TextureManager { textures: HashMap::new() }
+    TextureManager { textures: HashMap::new(), streaming_textures: HashMap::new() }
   }
 
   pub fn load_texture(&mut self, 
     texture_creator: &'a TextureCreator<WindowContext>,
     img_path: &str, 
-    color_key: Option<Color>) -> Rc<Texture<'a>>
+    color_key: Option<Color>) -> SharedTexture<'a>
+  {
+    self.load_texture_with_keys(texture_creator, img_path, color_key.map(ColorKey::Rgb), &[])
+  }
+
+  // Same, with the color keys declared in a sprite json: one for the whole sheet and/or
+  // one per region (sprite). The texture is cached by path: the keys of the first load are kept.
+  pub fn load_texture_with_keys(&mut self, 
+    texture_creator: &'a TextureCreator<WindowContext>,
+    img_path: &str, 
+    sheet_key: Option<ColorKey>,
+    region_keys: &[(Rect, ColorKey)]) -> SharedTexture<'a>
+  {
+    self.try_load_texture_with_keys(texture_creator, img_path, sheet_key, region_keys)
+      .unwrap_or_else(|err| { prompt_err_and_panic("img_load_color_key failed", &err, None); })
+  }
+
+  // Without the message box, for the loaders that give their errors back
+  pub fn try_load_texture_with_keys(&mut self,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    img_path: &str,
+    sheet_key: Option<ColorKey>,
+    region_keys: &[(Rect, ColorKey)]) -> Result<SharedTexture<'a>, String>
+  {
+    if let Some(tex) = self.textures.get(img_path) {
+      return Ok(Rc::clone(tex));
+    }
+    let s = load_keyed_surface(img_path, sheet_key, region_keys)?;
+    self.try_texture_from_surface(texture_creator, img_path, &s)
+  }
+
+  // A surface already loaded (e.g. also read for the collision masks), cached under img_path
+  // like the png it comes from. If img_path is already loaded, that texture is given back.
+  pub fn try_texture_from_surface(&mut self,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    img_path: &str,
+    surface: &Surface) -> Result<SharedTexture<'a>, String>
   {
-    if !self.textures.contains_key(img_path) 
+    if let Some(tex) = self.textures.get(img_path) {
+      return Ok(Rc::clone(tex));
+    }
+    let tex = surface.as_texture(texture_creator).map_err(|e| format!("{}: {}", img_path, e))?;
+    let tex = Rc::new(RefCell::new(tex));
+    self.textures.insert(img_path.to_string(), Rc::clone(&tex));
+    Ok(tex)
+  }
+
+  // The texture is freed once the last Rc (e.g. the sprites cut into it) is dropped
+  pub fn unload_texture(&mut self, img_path: &str)
+  {
+    self.textures.remove(img_path);
+  }
+
+  // Procedural texture, written by the CPU. Like for images, asking twice for the same name
+  // gives the same texture. With other dimensions, a new texture replaces it under that name
+  // (the sprites cut into the old one keep the old one).
+  pub fn create_streaming_texture(&mut self,
+    texture_creator: &'a TextureCreator<WindowContext>,
+    name: &str,
+    width: u32,
+    height: u32) -> StreamingTexture<'a>
+  {
+    match self.streaming_textures.get(name)
     {
-      use sdl2::surface::Surface;
-      use sdl2::image::LoadSurface;
-      use std::path::Path;
-      let mut s = Surface::from_file(Path::new(img_path))
-        .unwrap_or_else(|err| { prompt_err_and_panic("img_load_color_key failed", &err, None); });
-      
-      match color_key 
+      Some(existing) if existing.width() == width && existing.height() == height => existing.clone(),
+      _ =>
       {
-        Some(col) => 
-        { 
-          s.set_color_key(true, col)
-          .unwrap_or_else(|err| { prompt_err_and_panic("img_load_color_key(set_color_key) failed", &err, None); }); 
-        },
-        None => {}
+        let texture = StreamingTexture::new(texture_creator, width, height)
+          .unwrap_or_else(|err| { prompt_err_and_panic("create_streaming_texture failed", &err, None); });
+        self.streaming_textures.insert(name.to_string(), texture.clone());
+        texture
       }
-        
-      let tex = s.as_texture(texture_creator)
-        .unwrap_or_else(|err| { 
-          prompt_err_and_panic("img_load_color_key(as_texture) failed", &err.to_string(), None); });
-      self.textures.insert(img_path.to_string(), Rc::new(tex));
     }
-    Rc::clone(self.textures.get(img_path).unwrap())
+  }
+
+  // Like unload_texture: freed once the last clone is dropped
+  pub fn unload_streaming_texture(&mut self, name: &str)
+  {
+    self.streaming_textures.remove(name);
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests 
+{
+  use super::*;
+
+  fn parse(json: &str) -> Result<ColorKey, serde_json::Error> {
+    serde_json::from_str(json)
+  }
+
+  #[test]
+  fn test_color_key_formats() {
+    let cyan = ColorKey::Rgb(Color::RGB(0, 0xff, 0xff));
+    assert_eq!(parse(r##""#00FFFF""##).unwrap(), cyan);
+    assert_eq!(parse(r#""00ffff""#).unwrap(), cyan);
+    assert_eq!(parse("[0, 255, 255]").unwrap(), cyan);
+    assert_eq!(parse(r#""Auto""#).unwrap(), ColorKey::TopLeftPixel);
+  }
+
+  #[test]
+  fn test_invalid_color_keys() {
+    assert!(parse(r##""#0FF""##).is_err());
+    assert!(parse(r#""cyan""#).is_err());
+    assert!(parse("[0, 255, 256]").is_err());
+    assert!(parse("[0, 255]").is_err());
+  }
+
+  #[test]
+  fn test_hex_color_formats() {
+    let translucent_orange = Color::RGBA(0xFF, 0x80, 0x00, 0x40);
+    assert_eq!(parse_hex_color("#FF800040", HexColor::Rgba), Some(translucent_orange));
+    assert_eq!(parse_hex_color("#40FF8000", HexColor::Argb), Some(translucent_orange));
+    assert_eq!(parse_hex_color("ff8000", HexColor::Argb), Some(Color::RGB(0xFF, 0x80, 0x00)));
+    assert_eq!(parse_hex_color("#FF800040", HexColor::Rgb), None);
+    assert_eq!(parse_hex_color("+FF800", HexColor::Rgb), None);
+  }
+
+  #[test]
+  fn test_missing_image() {
+    let err = load_keyed_surface("data/missing.png", None, &[]).err().unwrap();
+    assert!(err.starts_with("data/missing.png: "), "{}", err);
   }
 }
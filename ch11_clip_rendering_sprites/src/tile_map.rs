@@ -0,0 +1,313 @@
+// Plain text tile maps, for quick prototypes (like the lazyfoo tiling lesson): a header naming
+// the sprite json, a legend giving the sprite of each character, and the grid itself.
+//
+//   # comment
+//   sheet = data/meta.json
+//   tile_size = 100x100
+//
+//   [legend]
+//   R = RedCircle solid
+//   G = GreenCircle
+//
+//   [map]
+//   RRRR
+//   R.GR
+//
+// '.' is an empty cell. Rows can also be written as csv (R,.,G,R). All rows must have
+// the same number of cells. In [map], every line is a row: no comments there.
+
+use crate::assets::read_asset;
+use crate::sprite::{try_create_sprites, try_load_sprites_from_json_as, Sprite, SpriteKey, SpriteName};
+use crate::texture::TextureManager;
+
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+
+use std::collections::HashMap;
+
+pub const EMPTY_CELL: char = '.';
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegendEntry<N = SpriteName>
+{
+  pub sprite: N,
+  pub solid: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct TileMapData<N = SpriteName>
+{
+  pub sheet: String,
+  pub tile_width: u32,
+  pub tile_height: u32,
+  pub legend: HashMap<char, LegendEntry<N>>,
+  pub width: u32, // in cells
+  pub height: u32,
+  cells: Vec<char> // row by row, EMPTY_CELL included
+}
+
+#[derive(PartialEq)]
+enum Section
+{
+  Header,
+  Legend,
+  Map
+}
+
+// "100x100"
+fn parse_size(text: &str) -> Option<(u32, u32)>
+{
+  let (w, h) = text.split_once('x')?;
+  let (w, h) = (w.trim().parse().ok()?, h.trim().parse().ok()?);
+  if w == 0 || h == 0 { None } else { Some((w, h)) }
+}
+
+// A sprite name as found in the sprite json, for any kind of key
+fn parse_sprite_name<N: SpriteKey>(name: &str) -> Result<N, String>
+{
+  N::deserialize(StrDeserializer::<ValueError>::new(name)).map_err(|_| format!("unknown sprite '{}'", name))
+}
+
+fn parse_row(line: &str) -> Result<Vec<char>, String>
+{
+  if !line.contains(',') {
+    return Ok(line.chars().collect());
+  }
+  line.split(',')
+    .map(|cell| {
+      let mut chars = cell.trim().chars();
+      match (chars.next(), chars.next()) {
+        (None, _) => Ok(EMPTY_CELL),
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("cells are one character, got '{}'", cell.trim()))
+      }
+    })
+    .collect()
+}
+
+impl<N: SpriteKey> TileMapData<N>
+{
+  // Errors give the line number (from 1)
+  pub fn parse(text: &str) -> Result<TileMapData<N>, String>
+  {
+    let mut section = Section::Header;
+    let mut sheet = None;
+    let mut tile_size = None;
+    let mut legend = HashMap::new();
+    let mut rows: Vec<(usize, Vec<char>)> = Vec::new();
+
+    for (i, raw_line) in text.lines().enumerate() {
+      let line_number = i + 1;
+      let err = |message: String| format!("line {}: {}", line_number, message);
+      let line = raw_line.trim_end();
+
+      if section == Section::Map {
+        if !line.trim().is_empty() {
+          rows.push((line_number, parse_row(line.trim_start()).map_err(err)?));
+        }
+        continue;
+      }
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      match line
+      {
+        "[legend]" => { section = Section::Legend; continue; },
+        "[map]" => { section = Section::Map; continue; },
+        _ => {}
+      }
+
+      let (key, value) = line.split_once('=').ok_or_else(|| err(format!("expected 'key = value', got '{}'", line)))?;
+      let (key, value) = (key.trim(), value.trim());
+      if section == Section::Header {
+        match key
+        {
+          "sheet" => sheet = Some(value.to_string()),
+          "tile_size" => tile_size = Some(parse_size(value).ok_or_else(|| err(format!("bad tile size '{}' (e.g. 32x32)", value)))?),
+          _ => return Err(err(format!("unknown header key '{}'", key)))
+        }
+        continue;
+      }
+
+      // legend: "R = RedCircle" or "R = RedCircle solid"
+      let mut chars = key.chars();
+      let symbol = match (chars.next(), chars.next()) {
+        (Some(c), None) if c != EMPTY_CELL && c != ',' => c,
+        _ => return Err(err(format!("legend keys are single characters (but '{}' and ','), got '{}'", EMPTY_CELL, key)))
+      };
+      let mut words = value.split_whitespace();
+      let sprite = parse_sprite_name(words.next().ok_or_else(|| err(format!("no sprite for '{}'", symbol)))?).map_err(err)?;
+      let solid = match words.next() {
+        None => false,
+        Some("solid") => true,
+        Some(other) => return Err(err(format!("unknown tile flag '{}'", other)))
+      };
+      if legend.insert(symbol, LegendEntry { sprite, solid }).is_some() {
+        return Err(err(format!("'{}' defined twice", symbol)));
+      }
+    }
+
+    let sheet = sheet.ok_or("no 'sheet = ...' in the header")?;
+    let (tile_width, tile_height) = tile_size.ok_or("no 'tile_size = WxH' in the header")?;
+    let width = rows.first().ok_or("no [map] rows")?.1.len();
+
+    let mut cells = Vec::with_capacity(width * rows.len());
+    for (line_number, row) in &rows {
+      if row.len() != width {
+        return Err(format!("line {}: {} cells, the first row has {}", line_number, row.len(), width));
+      }
+      if let Some(c) = row.iter().find(|&&c| c != EMPTY_CELL && !legend.contains_key(&c)) {
+        return Err(format!("line {}: '{}' isn't in the legend", line_number, c));
+      }
+      cells.extend(row);
+    }
+
+    Ok(TileMapData { sheet, tile_width, tile_height, legend, width: width as u32, height: rows.len() as u32, cells })
+  }
+
+  pub fn cell(&self, column: u32, row: u32) -> Option<&LegendEntry<N>>
+  {
+    if column >= self.width || row >= self.height {
+      return None;
+    }
+    self.legend.get(&self.cells[(row * self.width + column) as usize])
+  }
+
+  // Cell under a world position (in pixels), None outside the map
+  pub fn cell_at(&self, x: i32, y: i32) -> Option<(u32, u32)>
+  {
+    if x < 0 || y < 0 {
+      return None;
+    }
+    let (column, row) = (x as u32 / self.tile_width, y as u32 / self.tile_height);
+    if column < self.width && row < self.height { Some((column, row)) } else { None }
+  }
+
+  pub fn tile_at(&self, x: i32, y: i32) -> Option<&LegendEntry<N>>
+  {
+    self.cell_at(x, y).and_then(|(column, row)| self.cell(column, row))
+  }
+
+  // Outside the map counts as solid, so that nothing walks out of it
+  pub fn is_solid_at(&self, x: i32, y: i32) -> bool
+  {
+    match self.cell_at(x, y)
+    {
+      Some((column, row)) => self.cell(column, row).is_some_and(|t| t.solid),
+      None => true
+    }
+  }
+
+  // Whether a box (e.g. a character's collider) touches a solid tile
+  pub fn touches_solid(&self, rect: Rect) -> bool
+  {
+    let (tw, th) = (self.tile_width as i32, self.tile_height as i32);
+    let (first_x, last_x) = (rect.left().div_euclid(tw), (rect.right() - 1).div_euclid(tw));
+    let (first_y, last_y) = (rect.top().div_euclid(th), (rect.bottom() - 1).div_euclid(th));
+    (first_y..=last_y).any(|row| (first_x..=last_x).any(|column| self.is_solid_at(column * tw, row * th)))
+  }
+}
+
+pub fn load_tile_map_data<N: SpriteKey>(path: &str) -> Result<TileMapData<N>, String>
+{
+  let bytes = read_asset(path)?;
+  let text = std::str::from_utf8(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+  TileMapData::parse(text).map_err(|e| format!("{}: {}", path, e))
+}
+
+pub struct TileMap<'a, N = SpriteName>
+{
+  data: TileMapData<N>,
+  sprites: HashMap<N, Sprite<'a, N>>
+}
+
+impl<'a, N: SpriteKey> TileMap<'a, N>
+{
+  pub fn load(texture_creator: &'a TextureCreator<WindowContext>,
+    texture_manager: &mut TextureManager<'a>,
+    path: &str) -> Result<TileMap<'a, N>, String>
+  {
+    let data = load_tile_map_data::<N>(path)?;
+    let sprite_data = try_load_sprites_from_json_as::<N>(&data.sheet)?;
+    let sprites = try_create_sprites(texture_creator, &sprite_data, texture_manager).map_err(|e| format!("{}: {}", path, e))?;
+    if let Some(entry) = data.legend.values().find(|e| !sprites.contains_key(&e.sprite)) {
+      return Err(format!("{}: sprite {:?} isn't in {}", path, entry.sprite, data.sheet));
+    }
+    Ok(TileMap { data, sprites })
+  }
+
+  pub fn data(&self) -> &TileMapData<N> { &self.data }
+
+  // In pixels
+  pub fn pixel_size(&self) -> (u32, u32)
+  {
+    (self.data.width * self.data.tile_width, self.data.height * self.data.tile_height)
+  }
+
+  // camera: the part of the map shown (in map pixels), drawn at the top left of the viewport.
+  // Only the cells in view are drawn.
+  pub fn render(&self, canvas: &mut WindowCanvas, camera: Rect)
+  {
+    let (tw, th) = (self.data.tile_width as i32, self.data.tile_height as i32);
+    let first_x = camera.left().div_euclid(tw).max(0);
+    let first_y = camera.top().div_euclid(th).max(0);
+    let last_x = (camera.right() - 1).div_euclid(tw).min(self.data.width as i32 - 1);
+    let last_y = (camera.bottom() - 1).div_euclid(th).min(self.data.height as i32 - 1);
+
+    for row in first_y..=last_y {
+      for column in first_x..=last_x {
+        if let Some(entry) = self.data.cell(column as u32, row as u32) {
+          self.sprites[&entry.sprite].render(canvas, column * tw - camera.x(), row * th - camera.y());
+        }
+      }
+    }
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  const LEVEL: &str = "# test level
+sheet = data/meta.json
+tile_size = 10x10
+
+[legend]
+R = RedCircle solid
+G = GreenCircle
+
+[map]
+RRR
+R.G
+R,G,R
+";
+
+  #[test]
+  fn test_parse_and_query() {
+    let map = TileMapData::<SpriteName>::parse(LEVEL).unwrap();
+    assert_eq!((map.width, map.height, map.sheet.as_str()), (3, 3, "data/meta.json"));
+    assert_eq!(map.tile_at(25, 15), Some(&LegendEntry { sprite: SpriteName::GreenCircle, solid: false }));
+    assert_eq!(map.tile_at(15, 15), None);
+    assert!(map.is_solid_at(5, 15) && !map.is_solid_at(15, 15) && map.is_solid_at(-1, 0));
+    assert!(!map.touches_solid(Rect::new(10, 10, 10, 10)));
+    assert!(map.touches_solid(Rect::new(9, 10, 10, 10)));
+  }
+
+  #[test]
+  fn test_errors_have_line_numbers() {
+    let short_row = LEVEL.replace("R.G", "R.");
+    assert_eq!(TileMapData::<SpriteName>::parse(&short_row).unwrap_err(), "line 11: 2 cells, the first row has 3");
+    let unknown = LEVEL.replace("R.G", "R.X");
+    assert_eq!(TileMapData::<SpriteName>::parse(&unknown).unwrap_err(), "line 11: 'X' isn't in the legend");
+    let bad_sprite = LEVEL.replace("GreenCircle", "PurpleCircle");
+    assert_eq!(TileMapData::<SpriteName>::parse(&bad_sprite).unwrap_err(), "line 7: unknown sprite 'PurpleCircle'");
+    let long_cell = LEVEL.replace("R,G,R", "RG,G,R");
+    assert_eq!(TileMapData::<SpriteName>::parse(&long_cell).unwrap_err(), "line 12: cells are one character, got 'RG'");
+  }
+}
@@ -0,0 +1,654 @@
+// Tiled (https://www.mapeditor.org) maps, saved as .tmx (xml) or .tmj/.json. Orthogonal maps only,
+// finite, with tilesets cut from one image each (inline or external .tsx/.tsj).
+//
+// Parsing gives a plain TiledMapData (tiled/json.rs and tiled/tmx.rs, no SDL involved), TiledMap
+// adds the tileset textures (through the TextureManager) and draws the tile layers around a camera.
+// Object layers are kept as typed objects, for the game to spawn its entities from.
+
+mod json;
+mod tmx;
+
+use crate::animation::{AnimationMode, Timeline};
+use crate::assets::read_asset;
+use crate::sprite::{with_style, SpriteStyle};
+use crate::texture::{parse_hex_color, HexColor, SharedTexture, TextureManager};
+
+use base64::Engine;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+// The 3 high bits of a gid are the flips (the 4th one is for hexagonal maps, ignored)
+pub const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+pub const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+pub const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL_120: u32 = 0x1000_0000;
+const GID_MASK: u32 = !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL_120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRef
+{
+  pub gid: u32,
+  pub flip_h: bool,
+  pub flip_v: bool,
+  pub flip_d: bool // diagonal: x and y swapped (that's how Tiled rotates tiles)
+}
+
+impl TileRef
+{
+  // None for an empty cell (gid 0)
+  pub fn from_raw(raw: u32) -> Option<TileRef>
+  {
+    let gid = raw & GID_MASK;
+    if gid == 0 {
+      return None;
+    }
+    Some(TileRef {
+      gid,
+      flip_h: raw & FLIPPED_HORIZONTALLY != 0,
+      flip_v: raw & FLIPPED_VERTICALLY != 0,
+      flip_d: raw & FLIPPED_DIAGONALLY != 0
+    })
+  }
+
+  // The same thing with SDL's copy_ex, which flips first and then rotates clockwise:
+  // (flip_h, flip_v, angle)
+  pub fn copy_ex_params(&self) -> (bool, bool, f64)
+  {
+    match (self.flip_d, self.flip_h, self.flip_v)
+    {
+      (false, h, v) => (h, v, 0.0),
+      (true, false, false) => (false, true, 90.0),
+      (true, true, false) => (false, false, 90.0),
+      (true, false, true) => (false, false, 270.0),
+      (true, true, true) => (true, false, 90.0)
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue
+{
+  Bool(bool),
+  Int(i64),
+  Float(f64),
+  String(String),
+  Color(Color),
+  File(String),
+  Object(u32) // object id, 0 = none
+}
+
+pub type Properties = HashMap<String, PropertyValue>;
+
+// Per tile data of a tileset, only for the tiles that have some
+#[derive(Debug, Clone, Default)]
+pub struct TileInfo
+{
+  pub class: String,
+  pub properties: Properties,
+  pub animation: Vec<(u32, u32)> // (local tile id, duration in ms)
+}
+
+#[derive(Debug, Clone)]
+pub struct TilesetData
+{
+  pub first_gid: u32,
+  pub name: String,
+  pub image: String, // relative to the working directory, like the spritesheets
+  pub tile_width: u32,
+  pub tile_height: u32,
+  pub columns: u32,
+  pub tile_count: u32,
+  pub spacing: u32,
+  pub margin: u32,
+  pub tiles: HashMap<u32, TileInfo> // by local id
+}
+
+impl TilesetData
+{
+  pub fn contains(&self, gid: u32) -> bool
+  {
+    gid >= self.first_gid && gid - self.first_gid < self.tile_count
+  }
+
+  // Tilesets without a tilecount get as many rows of tiles as fit in the image (image_height 0: unknown)
+  pub(crate) fn complete_tile_count(mut self, image_height: u32) -> Result<TilesetData, String>
+  {
+    if self.tile_count == 0 {
+      let rows = (image_height + self.spacing).saturating_sub(2 * self.margin) / (self.tile_height + self.spacing);
+      if rows == 0 {
+        return Err(format!("tileset {}: no tilecount, nor an image height to count the tiles", self.name));
+      }
+      self.tile_count = rows * self.columns;
+    }
+    Ok(self)
+  }
+
+  // Where the tile is in the tileset image
+  pub fn src_rect(&self, local_id: u32) -> Rect
+  {
+    let column = local_id % self.columns;
+    let row = local_id / self.columns;
+    Rect::new(
+      (self.margin + column * (self.tile_width + self.spacing)) as i32,
+      (self.margin + row * (self.tile_height + self.spacing)) as i32,
+      self.tile_width, self.tile_height)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct TileLayer
+{
+  pub name: String,
+  pub width: u32,
+  pub height: u32,
+  pub data: Vec<u32>, // raw gids (flip bits included), row by row
+  pub visible: bool,
+  pub opacity: f32,
+  pub offset: (f32, f32),
+  pub properties: Properties
+}
+
+impl TileLayer
+{
+  pub fn tile(&self, x: u32, y: u32) -> Option<TileRef>
+  {
+    if x >= self.width || y >= self.height {
+      return None;
+    }
+    TileRef::from_raw(self.data[(y * self.width + x) as usize])
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectShape
+{
+  Rectangle,
+  Ellipse,
+  Point,
+  Polygon(Vec<(f32, f32)>),  // relative to the object position
+  Polyline(Vec<(f32, f32)>),
+  Tile(TileRef),             // tile objects are positioned by their bottom left corner
+  Text(String)
+}
+
+#[derive(Debug, Clone)]
+pub struct MapObject
+{
+  pub id: u32,
+  pub name: String,
+  pub class: String, // "type" before Tiled 1.9
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+  pub rotation: f32, // degrees, clockwise
+  pub visible: bool,
+  pub shape: ObjectShape,
+  pub properties: Properties
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjectLayer
+{
+  pub name: String,
+  pub visible: bool,
+  pub offset: (f32, f32),
+  pub objects: Vec<MapObject>,
+  pub properties: Properties
+}
+
+// Group layers are flattened: their offset, opacity and visibility are applied to their children
+#[derive(Debug, Clone)]
+pub enum Layer
+{
+  Tiles(TileLayer),
+  Objects(ObjectLayer)
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledMapData
+{
+  pub width: u32, // in tiles
+  pub height: u32,
+  pub tile_width: u32,
+  pub tile_height: u32,
+  pub tilesets: Vec<TilesetData>, // sorted by first_gid
+  pub layers: Vec<Layer>,         // drawing order
+  pub properties: Properties
+}
+
+impl TiledMapData
+{
+  pub fn tileset_index(&self, gid: u32) -> Option<usize>
+  {
+    self.tilesets.iter().rposition(|t| t.first_gid <= gid).filter(|&i| self.tilesets[i].contains(gid))
+  }
+
+  pub fn tileset_for(&self, gid: u32) -> Option<&TilesetData>
+  {
+    self.tileset_index(gid).map(|i| &self.tilesets[i])
+  }
+
+  pub fn tile_layer(&self, name: &str) -> Option<&TileLayer>
+  {
+    self.layers.iter().find_map(|l| match l {
+      Layer::Tiles(t) if t.name == name => Some(t),
+      _ => None
+    })
+  }
+
+  pub fn object_layer(&self, name: &str) -> Option<&ObjectLayer>
+  {
+    self.layers.iter().find_map(|l| match l {
+      Layer::Objects(o) if o.name == name => Some(o),
+      _ => None
+    })
+  }
+
+  // All the objects of a class, whatever their layer (e.g. "Spawn")
+  pub fn objects_of_class<'m>(&'m self, class: &'m str) -> impl Iterator<Item = &'m MapObject> + 'm
+  {
+    self.layers.iter()
+      .filter_map(|l| match l { Layer::Objects(o) => Some(o), _ => None })
+      .flat_map(|o| o.objects.iter())
+      .filter(move |o| o.class == class)
+  }
+}
+
+// What the parsers give before the external tilesets are read
+pub(crate) enum TilesetSource
+{
+  Inline(TilesetData),
+  External { first_gid: u32, path: String } // path already relative to the working directory
+}
+
+pub(crate) struct ParsedMap
+{
+  map: TiledMapData, // without the external tilesets
+  external_tilesets: Vec<(u32, String)>
+}
+
+impl ParsedMap
+{
+  fn new(mut map: TiledMapData, tilesets: Vec<TilesetSource>) -> ParsedMap
+  {
+    let mut external_tilesets = Vec::new();
+    for tileset in tilesets {
+      match tileset {
+        TilesetSource::Inline(t) => map.tilesets.push(t),
+        TilesetSource::External { first_gid, path } => external_tilesets.push((first_gid, path))
+      }
+    }
+    ParsedMap { map, external_tilesets }
+  }
+}
+
+// Paths in the files are relative to the file itself
+pub(crate) fn relative_to(dir: &Path, path: &str) -> String
+{
+  dir.join(path).to_string_lossy().replace('\\', "/")
+}
+
+// Layer data as written in both formats: csv, or base64 of little endian u32, possibly compressed
+pub(crate) fn decode_tile_data(text: &str, encoding: Option<&str>, compression: Option<&str>) -> Result<Vec<u32>, String>
+{
+  match encoding
+  {
+    Some("csv") => text.split(',')
+      .map(str::trim)
+      .filter(|s| !s.is_empty())
+      .map(|s| s.parse::<u32>().map_err(|e| format!("bad csv tile data '{}': {}", s, e)))
+      .collect(),
+    Some("base64") =>
+    {
+      let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+      let raw = base64::engine::general_purpose::STANDARD.decode(compact)
+        .map_err(|e| format!("bad base64 tile data: {}", e))?;
+      let mut bytes = Vec::new();
+      match compression
+      {
+        None | Some("") => bytes = raw,
+        Some("zlib") => { ZlibDecoder::new(&raw[..]).read_to_end(&mut bytes).map_err(|e| format!("zlib: {}", e))?; },
+        Some("gzip") => { GzDecoder::new(&raw[..]).read_to_end(&mut bytes).map_err(|e| format!("gzip: {}", e))?; },
+        Some(other) => return Err(format!("unsupported tile data compression '{}'", other))
+      }
+      if bytes.len() % 4 != 0 {
+        return Err("tile data size isn't a multiple of 4".to_string());
+      }
+      Ok(bytes.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    },
+    Some(other) => Err(format!("unsupported tile data encoding '{}'", other)),
+    None => Err("tile data without encoding (xml tiles aren't supported)".to_string())
+  }
+}
+
+// "#AARRGGBB" or "#RRGGBB"
+pub(crate) fn parse_color(text: &str) -> Result<Color, String>
+{
+  parse_hex_color(text, HexColor::Argb).ok_or_else(|| format!("bad color '{}'", text))
+}
+
+pub(crate) fn parse_property(name: &str, kind: &str, value: &str) -> Result<PropertyValue, String>
+{
+  let bad = || format!("property {}: bad {} value '{}'", name, kind, value);
+  Ok(match kind
+  {
+    "" | "string" => PropertyValue::String(value.to_string()),
+    "bool" => PropertyValue::Bool(value.parse().map_err(|_| bad())?),
+    "int" => PropertyValue::Int(value.parse().map_err(|_| bad())?),
+    "float" => PropertyValue::Float(value.parse().map_err(|_| bad())?),
+    "color" => PropertyValue::Color(if value.is_empty() { Color::RGBA(0, 0, 0, 0) } else { parse_color(value)? }),
+    "file" => PropertyValue::File(value.to_string()),
+    "object" => PropertyValue::Object(value.parse().map_err(|_| bad())?),
+    other => return Err(format!("property {}: unsupported type '{}'", name, other))
+  })
+}
+
+fn check_tile_layers(map: &TiledMapData) -> Result<(), String>
+{
+  for layer in &map.layers {
+    if let Layer::Tiles(t) = layer {
+      if t.data.len() != (t.width * t.height) as usize {
+        return Err(format!("layer {}: {} tiles for {}x{}", t.name, t.data.len(), t.width, t.height));
+      }
+    }
+  }
+  Ok(())
+}
+
+fn is_xml(path: &str) -> bool
+{
+  matches!(Path::new(path).extension().and_then(|e| e.to_str()), Some("tmx") | Some("tsx") | Some("xml"))
+}
+
+fn parse_map(path: &str) -> Result<TiledMapData, String>
+{
+  let bytes = read_asset(path)?;
+  let text = std::str::from_utf8(&bytes).map_err(|e| e.to_string())?;
+  let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+  let parsed = if is_xml(path) { tmx::parse_map(text, dir)? } else { json::parse_map(text, dir)? };
+  let mut map = parsed.map;
+  for (first_gid, tileset_path) in parsed.external_tilesets {
+    let bytes = read_asset(&tileset_path)?;
+    let text = std::str::from_utf8(&bytes).map_err(|e| e.to_string())?;
+    let tileset_dir = Path::new(&tileset_path).parent().unwrap_or(Path::new(""));
+    let tileset = if is_xml(&tileset_path) {
+      tmx::parse_tileset(text, first_gid, tileset_dir)
+    }
+    else {
+      json::parse_tileset(text, first_gid, tileset_dir)
+    };
+    map.tilesets.push(tileset.map_err(|e| format!("{}: {}", tileset_path, e))?);
+  }
+  map.tilesets.sort_by_key(|t| t.first_gid);
+  check_tile_layers(&map)?;
+  Ok(map)
+}
+
+// .tmx, .tmj or .json (by extension)
+pub fn load_tiled_map(path: &str) -> Result<TiledMapData, String>
+{
+  parse_map(path).map_err(|e| if e.starts_with(path) { e } else { format!("{}: {}", path, e) })
+}
+
+// An animated tile of a tileset: the frames are local tile ids
+struct TileAnimation
+{
+  timeline: Timeline,
+  frames: Vec<u32>
+}
+
+pub struct TiledMap<'a>
+{
+  data: TiledMapData,
+  textures: Vec<SharedTexture<'a>>,                // one per tileset, same order
+  animations: Vec<HashMap<u32, TileAnimation>>,   // per tileset, by local id
+  clock: Duration                                  // drives all the animated tiles
+}
+
+impl<'a> TiledMap<'a>
+{
+  pub fn load(texture_creator: &'a TextureCreator<WindowContext>,
+    texture_manager: &mut TextureManager<'a>,
+    path: &str) -> Result<TiledMap<'a>, String>
+  {
+    let data = load_tiled_map(path)?;
+    TiledMap::from_data(texture_creator, texture_manager, data)
+  }
+
+  pub fn from_data(texture_creator: &'a TextureCreator<WindowContext>,
+    texture_manager: &mut TextureManager<'a>,
+    data: TiledMapData) -> Result<TiledMap<'a>, String>
+  {
+    let textures = data.tilesets.iter()
+      .map(|t| texture_manager.try_load_texture_with_keys(texture_creator, &t.image, None, &[])
+        .map_err(|e| format!("tileset {}: {}", t.name, e)))
+      .collect::<Result<_, String>>()?;
+
+    let mut animations = Vec::new();
+    for tileset in &data.tilesets {
+      let mut tileset_animations = HashMap::new();
+      for (&id, info) in tileset.tiles.iter().filter(|(_, info)| !info.animation.is_empty()) {
+        let durations: Vec<u32> = info.animation.iter().map(|&(_, d)| d).collect();
+        let timeline = Timeline::new(&durations, AnimationMode::Loop)
+          .map_err(|e| format!("tileset {}, tile {}: {}", tileset.name, id, e))?;
+        let frames = info.animation.iter().map(|&(frame, _)| frame).collect();
+        tileset_animations.insert(id, TileAnimation { timeline, frames });
+      }
+      animations.push(tileset_animations);
+    }
+
+    Ok(TiledMap { data, textures, animations, clock: Duration::ZERO })
+  }
+
+  pub fn data(&self) -> &TiledMapData { &self.data }
+
+  // In pixels
+  pub fn pixel_size(&self) -> (u32, u32)
+  {
+    (self.data.width * self.data.tile_width, self.data.height * self.data.tile_height)
+  }
+
+  // Advances the animated tiles
+  pub fn update(&mut self, dt: Duration)
+  {
+    self.clock += dt;
+  }
+
+  // Local tile id to draw for a tile, animation applied
+  fn current_tile(&self, tileset: usize, local_id: u32) -> u32
+  {
+    match self.animations[tileset].get(&local_id)
+    {
+      Some(anim) => anim.frames[anim.timeline.frame_at((self.clock.as_millis() % anim.timeline.total() as u128) as u32)],
+      None => local_id
+    }
+  }
+
+  // All the visible tile layers, camera being the part of the map shown (in map pixels),
+  // drawn at the top left corner of the canvas viewport
+  pub fn render(&self, canvas: &mut WindowCanvas, camera: Rect)
+  {
+    for i in 0..self.data.layers.len() {
+      self.render_layer(canvas, i, camera);
+    }
+  }
+
+  // One layer, e.g. to draw the sprites between the ground and the roofs.
+  // Object layers and hidden layers draw nothing.
+  pub fn render_layer(&self, canvas: &mut WindowCanvas, index: usize, camera: Rect)
+  {
+    let layer = match self.data.layers.get(index) {
+      Some(Layer::Tiles(layer)) if layer.visible && layer.opacity > 0.0 => layer,
+      _ => return
+    };
+    let (tw, th) = (self.data.tile_width as i32, self.data.tile_height as i32);
+    let (ox, oy) = (layer.offset.0.round() as i32, layer.offset.1.round() as i32);
+
+    // visible cells only. Tiles bigger than the map's grow up and right from their cell,
+    // so a few more cells below and on the left can show.
+    let (extra_w, extra_h) = self.data.tilesets.iter()
+      .fold((0, 0), |(w, h), t| (w.max(t.tile_width as i32), h.max(t.tile_height as i32)));
+    let extra_columns = (extra_w - tw).max(0) / tw + 1;
+    let extra_rows = (extra_h - th).max(0) / th + 1;
+    let first_x = ((camera.x() - ox).div_euclid(tw) - extra_columns).max(0);
+    let first_y = ((camera.y() - oy).div_euclid(th)).max(0);
+    let last_x = ((camera.right() - ox).div_euclid(tw)).min(layer.width as i32 - 1);
+    let last_y = ((camera.bottom() - oy).div_euclid(th) + extra_rows).min(layer.height as i32 - 1);
+
+    let style = SpriteStyle { alpha: (layer.opacity.clamp(0.0, 1.0) * 255.0).round() as u8, ..SpriteStyle::default() };
+
+    for (t, texture) in self.textures.iter().enumerate() {
+      let tileset = &self.data.tilesets[t];
+      with_style(texture, &style, |tex| {
+        for y in first_y..=last_y {
+          for x in first_x..=last_x {
+            let tile = match layer.tile(x as u32, y as u32) {
+              Some(tile) if tileset.contains(tile.gid) => tile,
+              _ => continue
+            };
+            let src = tileset.src_rect(self.current_tile(t, tile.gid - tileset.first_gid));
+            let dst = tile_dst(x * tw + ox - camera.x(), (y + 1) * th + oy - camera.y(),
+              (tileset.tile_width, tileset.tile_height), tile.flip_d);
+            let (flip_h, flip_v, angle) = tile.copy_ex_params();
+            if angle == 0.0 && !flip_h && !flip_v {
+              canvas.copy(tex, src, dst).unwrap();
+            }
+            else {
+              canvas.copy_ex(tex, src, dst, angle, None, flip_h, flip_v).unwrap();
+            }
+          }
+        }
+      });
+    }
+  }
+}
+
+// Where a tile goes for copy/copy_ex: bottom left aligned on its cell, like in Tiled.
+// A diagonal flip turns a w x h tile into h x w. copy_ex stretches the tile to the rect and
+// then rotates it around its middle, so the rect stays w x h (not squashed), centered on the
+// h x w footprint the rotation has to land on.
+fn tile_dst(left: i32, bottom: i32, size: (u32, u32), flip_d: bool) -> Rect
+{
+  let (w, h) = size;
+  if !flip_d {
+    return Rect::new(left, bottom - h as i32, w, h);
+  }
+  let footprint = Rect::new(left, bottom - w as i32, h, w);
+  Rect::from_center(footprint.center(), w, h)
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  #[test]
+  fn test_tile_flags() {
+    assert_eq!(TileRef::from_raw(0), None);
+    assert_eq!(TileRef::from_raw(FLIPPED_HORIZONTALLY), None);
+    let tile = TileRef::from_raw(FLIPPED_DIAGONALLY | FLIPPED_HORIZONTALLY | 42).unwrap();
+    assert_eq!((tile.gid, tile.flip_h, tile.flip_v, tile.flip_d), (42, true, false, true));
+    assert_eq!(tile.copy_ex_params(), (false, false, 90.0));
+  }
+
+  #[test]
+  fn test_decode_tile_data() {
+    let expected = vec![1, 2, 0, FLIPPED_VERTICALLY | 3];
+    assert_eq!(decode_tile_data("1,2,\n0,1073741827", Some("csv"), None).unwrap(), expected);
+
+    let bytes: Vec<u8> = expected.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, &bytes).unwrap();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(encoder.finish().unwrap());
+    assert_eq!(decode_tile_data(&encoded, Some("base64"), Some("zlib")).unwrap(), expected);
+  }
+
+  #[test]
+  fn test_tileset_src_rect() {
+    let tileset = TilesetData { first_gid: 10, name: "t".to_string(), image: "t.png".to_string(),
+      tile_width: 16, tile_height: 16, columns: 4, tile_count: 16, spacing: 2, margin: 1, tiles: HashMap::new() };
+    assert_eq!(tileset.src_rect(5), Rect::new(19, 19, 16, 16));
+    assert!(tileset.contains(25) && !tileset.contains(26) && !tileset.contains(9));
+    // 4 rows of 16 + 2 in 73 pixels
+    let tileset = TilesetData { tile_count: 0, ..tileset };
+    assert_eq!(tileset.clone().complete_tile_count(73).unwrap().tile_count, 16);
+    assert!(tileset.complete_tile_count(0).is_err());
+  }
+
+  #[test]
+  fn test_diagonal_flip_keeps_the_tile_size() {
+    // a 32x16 tile on a cell whose bottom left is (0, 100)
+    assert_eq!(tile_dst(0, 100, (32, 16), false), Rect::new(0, 84, 32, 16));
+    // drawn 16x32 once rotated, from (0, 68) to (16, 100): same middle as this 32x16 rect
+    let dst = tile_dst(0, 100, (32, 16), true);
+    assert_eq!((dst.center(), dst.size()), (sdl2::rect::Point::new(8, 84), (32, 16)));
+  }
+
+  #[test]
+  fn test_parse_tmx_and_json() {
+    let tmx = r##"<map orientation="orthogonal" width="2" height="1" tilewidth="16" tileheight="16" infinite="0">
+      <tileset firstgid="1" name="ground" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+        <image source="ground.png" width="32" height="32"/>
+        <tile id="0"><animation><frame tileid="0" duration="100"/><frame tileid="1" duration="100"/></animation></tile>
+      </tileset>
+      <tileset firstgid="5" source="props.tsx"/>
+      <group name="g" offsetx="4" opacity="0.5">
+        <layer name="floor" width="2" height="1" offsety="2"><data encoding="csv">1,2147483650</data></layer>
+      </group>
+      <objectgroup name="things">
+        <object id="3" name="start" type="Spawn" x="8" y="16"><point/>
+          <properties><property name="hp" type="int" value="3"/></properties></object>
+      </objectgroup>
+    </map>"##;
+    let json = r##"{ "orientation": "orthogonal", "width": 2, "height": 1, "tilewidth": 16, "tileheight": 16, "infinite": false,
+      "tilesets": [ { "firstgid": 1, "name": "ground", "image": "ground.png", "tilewidth": 16, "tileheight": 16,
+                      "tilecount": 4, "columns": 2,
+                      "tiles": [ { "id": 0, "animation": [ {"tileid": 0, "duration": 100}, {"tileid": 1, "duration": 100} ] } ] },
+                    { "firstgid": 5, "source": "props.tsx" } ],
+      "layers": [
+        { "type": "group", "name": "g", "offsetx": 4, "opacity": 0.5, "layers": [
+          { "type": "tilelayer", "name": "floor", "width": 2, "height": 1, "offsety": 2, "data": [1, 2147483650] } ] },
+        { "type": "objectgroup", "name": "things", "objects": [
+          { "id": 3, "name": "start", "class": "Spawn", "x": 8, "y": 16, "point": true,
+            "properties": [ { "name": "hp", "type": "int", "value": 3 } ] } ] } ] }"##;
+
+    for parsed in [tmx::parse_map(tmx, Path::new("maps")).unwrap(), json::parse_map(json, Path::new("maps")).unwrap()] {
+      assert_eq!(parsed.external_tilesets, vec![(5, "maps/props.tsx".to_string())]);
+      let map = parsed.map;
+      assert_eq!(map.tilesets[0].image, "maps/ground.png");
+      assert_eq!(map.tilesets[0].tiles[&0].animation, vec![(0, 100), (1, 100)]);
+
+      let floor = map.tile_layer("floor").unwrap();
+      assert_eq!((floor.offset, floor.opacity), ((4.0, 2.0), 0.5));
+      assert_eq!(floor.tile(1, 0), Some(TileRef { gid: 2, flip_h: true, flip_v: false, flip_d: false }));
+
+      let spawn = map.objects_of_class("Spawn").next().unwrap();
+      assert_eq!((spawn.name.as_str(), spawn.x, spawn.y), ("start", 8.0, 16.0));
+      assert_eq!(spawn.shape, ObjectShape::Point);
+      assert_eq!(spawn.properties["hp"], PropertyValue::Int(3));
+    }
+  }
+
+  #[test]
+  fn test_zero_tile_sizes_are_rejected() {
+    let dir = Path::new("maps");
+    let no_size = Some("the map has no tile size".to_string());
+    assert_eq!(tmx::parse_map(r#"<map width="2" height="1" tilewidth="0" tileheight="16"/>"#, dir).err(), no_size);
+    assert_eq!(json::parse_map(r#"{ "width": 2, "height": 1, "tilewidth": 16, "tileheight": 0, "layers": [], "tilesets": [] }"#, dir).err(),
+      no_size);
+    let tileset = r#"<tileset name="t" tilewidth="0" tileheight="16" columns="2"><image source="t.png"/></tileset>"#;
+    assert_eq!(tmx::parse_tileset(tileset, 1, dir).err(), Some("tileset t: no tile size or columns".to_string()));
+  }
+}
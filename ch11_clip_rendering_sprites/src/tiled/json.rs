@@ -0,0 +1,352 @@
+// Tiled's json flavour (.tmj map, .tsj tileset)
+
+use super::{decode_tile_data, parse_color, parse_property, relative_to};
+use super::{Layer, MapObject, ObjectLayer, ObjectShape, ParsedMap, Properties, PropertyValue};
+use super::{TileInfo, TileLayer, TileRef, TiledMapData, TilesetData, TilesetSource};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct JsonProperty
+{
+  name: String,
+  #[serde(rename = "type", default)]
+  kind: String,
+  value: Value
+}
+
+#[derive(Deserialize)]
+struct JsonMap
+{
+  #[serde(default)]
+  orientation: String,
+  #[serde(default)]
+  infinite: bool,
+  width: u32,
+  height: u32,
+  tilewidth: u32,
+  tileheight: u32,
+  #[serde(default)]
+  tilesets: Vec<JsonTileset>,
+  #[serde(default)]
+  layers: Vec<JsonLayer>,
+  #[serde(default)]
+  properties: Vec<JsonProperty>
+}
+
+#[derive(Deserialize)]
+struct JsonTileset
+{
+  #[serde(default)]
+  firstgid: u32,
+  source: Option<String>,
+  #[serde(default)]
+  name: String,
+  image: Option<String>,
+  #[serde(default)]
+  tilewidth: u32,
+  #[serde(default)]
+  tileheight: u32,
+  #[serde(default)]
+  columns: u32,
+  #[serde(default)]
+  tilecount: u32,
+  #[serde(default)]
+  imageheight: u32,
+  #[serde(default)]
+  spacing: u32,
+  #[serde(default)]
+  margin: u32,
+  #[serde(default)]
+  tiles: Vec<JsonTile>
+}
+
+#[derive(Deserialize)]
+struct JsonTile
+{
+  id: u32,
+  #[serde(alias = "type", default)]
+  class: String,
+  #[serde(default)]
+  properties: Vec<JsonProperty>,
+  #[serde(default)]
+  animation: Vec<JsonFrame>
+}
+
+#[derive(Deserialize)]
+struct JsonFrame
+{
+  tileid: u32,
+  duration: u32
+}
+
+fn default_true() -> bool { true }
+fn default_opacity() -> f32 { 1.0 }
+
+#[derive(Deserialize)]
+struct JsonLayer
+{
+  #[serde(rename = "type")]
+  kind: String,
+  #[serde(default)]
+  name: String,
+  #[serde(default = "default_true")]
+  visible: bool,
+  #[serde(default = "default_opacity")]
+  opacity: f32,
+  #[serde(default)]
+  offsetx: f32,
+  #[serde(default)]
+  offsety: f32,
+  #[serde(default)]
+  properties: Vec<JsonProperty>,
+  // tile layers
+  #[serde(default)]
+  width: u32,
+  #[serde(default)]
+  height: u32,
+  data: Option<Value>, // array of gids, or a string when encoded
+  encoding: Option<String>,
+  compression: Option<String>,
+  // object layers
+  #[serde(default)]
+  objects: Vec<JsonObject>,
+  // groups
+  #[serde(default)]
+  layers: Vec<JsonLayer>
+}
+
+#[derive(Deserialize)]
+struct JsonPoint
+{
+  x: f32,
+  y: f32
+}
+
+#[derive(Deserialize)]
+struct JsonText
+{
+  #[serde(default)]
+  text: String
+}
+
+#[derive(Deserialize)]
+struct JsonObject
+{
+  #[serde(default)]
+  id: u32,
+  #[serde(default)]
+  name: String,
+  #[serde(alias = "type", default)]
+  class: String,
+  #[serde(default)]
+  x: f32,
+  #[serde(default)]
+  y: f32,
+  #[serde(default)]
+  width: f32,
+  #[serde(default)]
+  height: f32,
+  #[serde(default)]
+  rotation: f32,
+  #[serde(default = "default_true")]
+  visible: bool,
+  gid: Option<u32>,
+  #[serde(default)]
+  ellipse: bool,
+  #[serde(default)]
+  point: bool,
+  polygon: Option<Vec<JsonPoint>>,
+  polyline: Option<Vec<JsonPoint>>,
+  text: Option<JsonText>,
+  #[serde(default)]
+  properties: Vec<JsonProperty>
+}
+
+fn convert_properties(properties: &[JsonProperty]) -> Result<Properties, String>
+{
+  properties.iter()
+    .map(|p| {
+      let value = match (p.kind.as_str(), &p.value)
+      {
+        // numbers and booleans are native json, only the rest is parsed from text
+        ("bool", Value::Bool(b)) => PropertyValue::Bool(*b),
+        ("int", Value::Number(n)) => PropertyValue::Int(n.as_i64().ok_or(format!("property {}: not an int", p.name))?),
+        ("float", Value::Number(n)) => PropertyValue::Float(n.as_f64().unwrap_or_default()),
+        ("object", Value::Number(n)) => PropertyValue::Object(n.as_u64().unwrap_or_default() as u32),
+        ("color", Value::String(s)) if !s.is_empty() => PropertyValue::Color(parse_color(s)?),
+        (kind, Value::String(s)) => parse_property(&p.name, kind, s)?,
+        (kind, other) => return Err(format!("property {}: bad {} value {}", p.name, kind, other))
+      };
+      Ok((p.name.clone(), value))
+    })
+    .collect()
+}
+
+fn convert_tileset(tileset: JsonTileset, first_gid: u32, dir: &Path) -> Result<TilesetData, String>
+{
+  let image = tileset.image
+    .ok_or_else(|| format!("tileset {}: image collection tilesets aren't supported", tileset.name))?;
+  if tileset.columns == 0 || tileset.tilewidth == 0 || tileset.tileheight == 0 {
+    return Err(format!("tileset {}: no tile size or columns", tileset.name));
+  }
+  let mut tiles = HashMap::new();
+  for tile in tileset.tiles {
+    let info = TileInfo {
+      class: tile.class,
+      properties: convert_properties(&tile.properties)?,
+      animation: tile.animation.iter().map(|f| (f.tileid, f.duration)).collect()
+    };
+    tiles.insert(tile.id, info);
+  }
+  TilesetData {
+    first_gid,
+    name: tileset.name,
+    image: relative_to(dir, &image),
+    tile_width: tileset.tilewidth,
+    tile_height: tileset.tileheight,
+    columns: tileset.columns,
+    tile_count: tileset.tilecount,
+    spacing: tileset.spacing,
+    margin: tileset.margin,
+    tiles
+  }.complete_tile_count(tileset.imageheight)
+}
+
+fn convert_object(object: JsonObject) -> Result<MapObject, String>
+{
+  let points = |points: Vec<JsonPoint>| points.into_iter().map(|p| (p.x, p.y)).collect();
+  let shape = if let Some(gid) = object.gid {
+    ObjectShape::Tile(TileRef::from_raw(gid).ok_or(format!("object {}: tile object without tile", object.id))?)
+  }
+  else if let Some(polygon) = object.polygon {
+    ObjectShape::Polygon(points(polygon))
+  }
+  else if let Some(polyline) = object.polyline {
+    ObjectShape::Polyline(points(polyline))
+  }
+  else if let Some(text) = object.text {
+    ObjectShape::Text(text.text)
+  }
+  else if object.ellipse {
+    ObjectShape::Ellipse
+  }
+  else if object.point {
+    ObjectShape::Point
+  }
+  else {
+    ObjectShape::Rectangle
+  };
+
+  Ok(MapObject {
+    id: object.id,
+    name: object.name,
+    class: object.class,
+    x: object.x,
+    y: object.y,
+    width: object.width,
+    height: object.height,
+    rotation: object.rotation,
+    visible: object.visible,
+    shape,
+    properties: convert_properties(&object.properties)?
+  })
+}
+
+// Groups are flattened into `layers`, with the accumulated offset/opacity/visibility
+fn convert_layers(json_layers: Vec<JsonLayer>, offset: (f32, f32), opacity: f32, visible: bool, layers: &mut Vec<Layer>)
+  -> Result<(), String>
+{
+  for layer in json_layers {
+    let offset = (offset.0 + layer.offsetx, offset.1 + layer.offsety);
+    let opacity = opacity * layer.opacity;
+    let visible = visible && layer.visible;
+    match layer.kind.as_str()
+    {
+      "tilelayer" =>
+      {
+        let data = match layer.data
+        {
+          Some(Value::Array(gids)) => gids.iter()
+            .map(|g| g.as_u64().map(|g| g as u32).ok_or(format!("layer {}: bad gid {}", layer.name, g)))
+            .collect::<Result<Vec<u32>, String>>()?,
+          Some(Value::String(text)) =>
+            decode_tile_data(&text, layer.encoding.as_deref(), layer.compression.as_deref())
+              .map_err(|e| format!("layer {}: {}", layer.name, e))?,
+          _ => return Err(format!("layer {}: no tile data (infinite maps aren't supported)", layer.name))
+        };
+        layers.push(Layer::Tiles(TileLayer {
+          name: layer.name,
+          width: layer.width,
+          height: layer.height,
+          data,
+          visible,
+          opacity,
+          offset,
+          properties: convert_properties(&layer.properties)?
+        }));
+      },
+      "objectgroup" =>
+      {
+        let objects = layer.objects.into_iter().map(convert_object).collect::<Result<Vec<_>, String>>()
+          .map_err(|e| format!("layer {}: {}", layer.name, e))?;
+        layers.push(Layer::Objects(ObjectLayer {
+          name: layer.name,
+          visible,
+          offset,
+          objects,
+          properties: convert_properties(&layer.properties)?
+        }));
+      },
+      "group" => convert_layers(layer.layers, offset, opacity, visible, layers)?,
+      _ => {} // image layers: not supported, skipped
+    }
+  }
+  Ok(())
+}
+
+pub(crate) fn parse_map(text: &str, dir: &Path) -> Result<ParsedMap, String>
+{
+  let json: JsonMap = serde_json::from_str(text).map_err(|e| e.to_string())?;
+  if json.infinite {
+    return Err("infinite maps aren't supported".to_string());
+  }
+  if !json.orientation.is_empty() && json.orientation != "orthogonal" {
+    return Err(format!("{} maps aren't supported, only orthogonal ones", json.orientation));
+  }
+  if json.tilewidth == 0 || json.tileheight == 0 {
+    return Err("the map has no tile size".to_string());
+  }
+
+  let mut tilesets = Vec::new();
+  for tileset in json.tilesets {
+    tilesets.push(match tileset.source {
+      Some(ref source) => TilesetSource::External { first_gid: tileset.firstgid, path: relative_to(dir, source) },
+      None => { let first_gid = tileset.firstgid; TilesetSource::Inline(convert_tileset(tileset, first_gid, dir)?) }
+    });
+  }
+  let mut layers = Vec::new();
+  convert_layers(json.layers, (0.0, 0.0), 1.0, true, &mut layers)?;
+
+  let map = TiledMapData {
+    width: json.width,
+    height: json.height,
+    tile_width: json.tilewidth,
+    tile_height: json.tileheight,
+    tilesets: Vec::new(),
+    layers,
+    properties: convert_properties(&json.properties)?
+  };
+  Ok(ParsedMap::new(map, tilesets))
+}
+
+// External tileset, the first gid coming from the map
+pub(crate) fn parse_tileset(text: &str, first_gid: u32, dir: &Path) -> Result<TilesetData, String>
+{
+  let json: JsonTileset = serde_json::from_str(text).map_err(|e| e.to_string())?;
+  convert_tileset(json, first_gid, dir)
+}
@@ -0,0 +1,236 @@
+// Tiled's xml flavour (.tmx map, .tsx tileset)
+
+use super::{decode_tile_data, parse_property, relative_to};
+use super::{Layer, MapObject, ObjectLayer, ObjectShape, ParsedMap, Properties};
+use super::{TileInfo, TileLayer, TileRef, TiledMapData, TilesetData, TilesetSource};
+
+use roxmltree::{Document, Node};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+// Optional attribute, parsed
+fn attr<T: FromStr>(node: Node, name: &str, default: T) -> Result<T, String>
+{
+  match node.attribute(name)
+  {
+    Some(value) => value.parse().map_err(|_| format!("<{}>: bad {} '{}'", node.tag_name().name(), name, value)),
+    None => Ok(default)
+  }
+}
+
+fn required<T: FromStr>(node: Node, name: &str) -> Result<T, String>
+{
+  let value = node.attribute(name).ok_or_else(|| format!("<{}> without {}", node.tag_name().name(), name))?;
+  value.parse().map_err(|_| format!("<{}>: bad {} '{}'", node.tag_name().name(), name, value))
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, tag: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a
+{
+  node.children().filter(move |n| n.has_tag_name(tag))
+}
+
+// "visible" is 0/1 in xml
+fn visible(node: Node) -> Result<bool, String>
+{
+  Ok(attr::<u8>(node, "visible", 1)? != 0)
+}
+
+fn class(node: Node) -> String
+{
+  node.attribute("class").or(node.attribute("type")).unwrap_or("").to_string()
+}
+
+fn parse_properties(node: Node) -> Result<Properties, String>
+{
+  let mut properties = HashMap::new();
+  for property in children(node, "properties").flat_map(|p| children(p, "property")) {
+    let name = property.attribute("name").unwrap_or("");
+    // multiline strings are written as text content instead of the value attribute
+    let value = property.attribute("value").or(property.text()).unwrap_or("");
+    let value = parse_property(name, property.attribute("type").unwrap_or(""), value)?;
+    properties.insert(name.to_string(), value);
+  }
+  Ok(properties)
+}
+
+fn parse_tileset_node(node: Node, first_gid: u32, dir: &Path) -> Result<TilesetData, String>
+{
+  let name = node.attribute("name").unwrap_or("").to_string();
+  let image_node = children(node, "image").next()
+    .filter(|i| i.attribute("source").is_some())
+    .ok_or_else(|| format!("tileset {}: image collection tilesets aren't supported", name))?;
+  let image = image_node.attribute("source").unwrap();
+  let columns: u32 = attr(node, "columns", 0)?;
+  let (tile_width, tile_height): (u32, u32) = (required(node, "tilewidth")?, required(node, "tileheight")?);
+  if columns == 0 || tile_width == 0 || tile_height == 0 {
+    return Err(format!("tileset {}: no tile size or columns", name));
+  }
+
+  let mut tiles = HashMap::new();
+  for tile in children(node, "tile") {
+    let animation = children(tile, "animation")
+      .flat_map(|a| children(a, "frame"))
+      .map(|f| Ok((required(f, "tileid")?, required(f, "duration")?)))
+      .collect::<Result<Vec<(u32, u32)>, String>>()?;
+    let info = TileInfo { class: class(tile), properties: parse_properties(tile)?, animation };
+    tiles.insert(required(tile, "id")?, info);
+  }
+
+  TilesetData {
+    first_gid,
+    image: relative_to(dir, image),
+    tile_width,
+    tile_height,
+    columns,
+    tile_count: attr(node, "tilecount", 0)?,
+    spacing: attr(node, "spacing", 0)?,
+    margin: attr(node, "margin", 0)?,
+    tiles,
+    name
+  }.complete_tile_count(attr(image_node, "height", 0)?)
+}
+
+// "x1,y1 x2,y2 ..."
+fn parse_points(text: &str) -> Result<Vec<(f32, f32)>, String>
+{
+  text.split_whitespace()
+    .map(|pair| {
+      let (x, y) = pair.split_once(',').ok_or(format!("bad point '{}'", pair))?;
+      Ok((x.parse().map_err(|_| format!("bad point '{}'", pair))?, y.parse().map_err(|_| format!("bad point '{}'", pair))?))
+    })
+    .collect()
+}
+
+fn parse_object(node: Node) -> Result<MapObject, String>
+{
+  let id = attr(node, "id", 0)?;
+  let shape = if let Some(gid) = node.attribute("gid") {
+    let raw = gid.parse().map_err(|_| format!("object {}: bad gid '{}'", id, gid))?;
+    ObjectShape::Tile(TileRef::from_raw(raw).ok_or(format!("object {}: tile object without tile", id))?)
+  }
+  else if let Some(polygon) = children(node, "polygon").next() {
+    ObjectShape::Polygon(parse_points(polygon.attribute("points").unwrap_or(""))?)
+  }
+  else if let Some(polyline) = children(node, "polyline").next() {
+    ObjectShape::Polyline(parse_points(polyline.attribute("points").unwrap_or(""))?)
+  }
+  else if let Some(text) = children(node, "text").next() {
+    ObjectShape::Text(text.text().unwrap_or("").to_string())
+  }
+  else if children(node, "ellipse").next().is_some() {
+    ObjectShape::Ellipse
+  }
+  else if children(node, "point").next().is_some() {
+    ObjectShape::Point
+  }
+  else {
+    ObjectShape::Rectangle
+  };
+
+  Ok(MapObject {
+    id,
+    name: node.attribute("name").unwrap_or("").to_string(),
+    class: class(node),
+    x: attr(node, "x", 0.0)?,
+    y: attr(node, "y", 0.0)?,
+    width: attr(node, "width", 0.0)?,
+    height: attr(node, "height", 0.0)?,
+    rotation: attr(node, "rotation", 0.0)?,
+    visible: visible(node)?,
+    shape,
+    properties: parse_properties(node)?
+  })
+}
+
+// Same as in json.rs: groups are flattened with the accumulated offset/opacity/visibility
+fn parse_layers(parent: Node, offset: (f32, f32), opacity: f32, is_visible: bool, layers: &mut Vec<Layer>) -> Result<(), String>
+{
+  for node in parent.children().filter(|n| n.is_element()) {
+    let name = node.attribute("name").unwrap_or("").to_string();
+    let offset = (offset.0 + attr(node, "offsetx", 0.0)?, offset.1 + attr(node, "offsety", 0.0)?);
+    let opacity = opacity * attr(node, "opacity", 1.0)?;
+    let is_visible = is_visible && visible(node)?;
+    match node.tag_name().name()
+    {
+      "layer" =>
+      {
+        let data = children(node, "data").next().ok_or(format!("layer {}: no data", name))?;
+        if children(data, "chunk").next().is_some() {
+          return Err(format!("layer {}: infinite maps aren't supported", name));
+        }
+        let gids = decode_tile_data(data.text().unwrap_or(""), data.attribute("encoding"), data.attribute("compression"))
+          .map_err(|e| format!("layer {}: {}", name, e))?;
+        layers.push(Layer::Tiles(TileLayer {
+          width: required(node, "width")?,
+          height: required(node, "height")?,
+          data: gids,
+          visible: is_visible,
+          opacity,
+          offset,
+          properties: parse_properties(node)?,
+          name
+        }));
+      },
+      "objectgroup" =>
+      {
+        let objects = children(node, "object").map(parse_object).collect::<Result<Vec<_>, String>>()
+          .map_err(|e| format!("layer {}: {}", name, e))?;
+        layers.push(Layer::Objects(ObjectLayer { visible: is_visible, offset, objects, properties: parse_properties(node)?, name }));
+      },
+      "group" => parse_layers(node, offset, opacity, is_visible, layers)?,
+      _ => {} // image layers, properties, tilesets...
+    }
+  }
+  Ok(())
+}
+
+pub(crate) fn parse_map(text: &str, dir: &Path) -> Result<ParsedMap, String>
+{
+  let document = Document::parse(text).map_err(|e| e.to_string())?;
+  let root = document.root_element();
+  if !root.has_tag_name("map") {
+    return Err("not a tmx map".to_string());
+  }
+  if attr::<u8>(root, "infinite", 0)? != 0 {
+    return Err("infinite maps aren't supported".to_string());
+  }
+  let orientation = root.attribute("orientation").unwrap_or("orthogonal");
+  if orientation != "orthogonal" {
+    return Err(format!("{} maps aren't supported, only orthogonal ones", orientation));
+  }
+  let (tile_width, tile_height): (u32, u32) = (required(root, "tilewidth")?, required(root, "tileheight")?);
+  if tile_width == 0 || tile_height == 0 {
+    return Err("the map has no tile size".to_string());
+  }
+
+  let mut tilesets = Vec::new();
+  for tileset in children(root, "tileset") {
+    let first_gid = required(tileset, "firstgid")?;
+    tilesets.push(match tileset.attribute("source") {
+      Some(source) => TilesetSource::External { first_gid, path: relative_to(dir, source) },
+      None => TilesetSource::Inline(parse_tileset_node(tileset, first_gid, dir)?)
+    });
+  }
+  let mut layers = Vec::new();
+  parse_layers(root, (0.0, 0.0), 1.0, true, &mut layers)?;
+
+  let map = TiledMapData {
+    width: required(root, "width")?,
+    height: required(root, "height")?,
+    tile_width,
+    tile_height,
+    tilesets: Vec::new(),
+    layers,
+    properties: parse_properties(root)?
+  };
+  Ok(ParsedMap::new(map, tilesets))
+}
+
+// External tileset, the first gid coming from the map
+pub(crate) fn parse_tileset(text: &str, first_gid: u32, dir: &Path) -> Result<TilesetData, String>
+{
+  let document = Document::parse(text).map_err(|e| e.to_string())?;
+  parse_tileset_node(document.root_element(), first_gid, dir)
+}
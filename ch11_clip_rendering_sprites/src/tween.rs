@@ -0,0 +1,754 @@
+// Tweens: values changing from one to another over time, along an easing curve.
+//
+// A tween writes into a TweenTarget (a shared cell) which the game reads when it draws, so
+// anything Copy and interpolable can be animated: positions, alpha, scale, angle, colors.
+// Tweens compose into sequences (one after the other) and parallel groups (all at once),
+// which are animations too, so they nest. Times left over at the end of a step go to the
+// next one: a sequence stays on time whatever the frame rate. Times are kept as Durations
+// (exact), so ten 10 ms frames end a 100 ms wait, no float drift.
+//
+//   let alpha = tween_target(0u8);
+//   let fade_in_out = Sequence::new()
+//     .then(Tween::new(&alpha, 255, Duration::from_millis(300)).with_easing(Easing::QuadOut))
+//     .wait(Duration::from_secs(2))
+//     .then(Tween::new(&alpha, 0, Duration::from_millis(300)))
+//     .call(|| println!("done"));
+//   tweener.add(fade_in_out);
+//   // each frame
+//   tweener.update(dt);
+//   sprite.set_alpha(alpha.get());
+
+use crate::sprite::{RenderEx, Sprite, SpriteName};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::render::WindowCanvas;
+
+use std::cell::Cell;
+use std::f32::consts::PI;
+use std::rc::Rc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Easing
+{
+  Linear,
+  QuadIn, QuadOut, QuadInOut,
+  CubicIn, CubicOut, CubicInOut,
+  QuartIn, QuartOut, QuartInOut,
+  QuintIn, QuintOut, QuintInOut,
+  SineIn, SineOut, SineInOut,
+  ExpoIn, ExpoOut, ExpoInOut,
+  CircIn, CircOut, CircInOut,
+  BackIn, BackOut, BackInOut,       // go a bit past the ends
+  ElasticIn, ElasticOut, ElasticInOut,
+  BounceIn, BounceOut, BounceInOut
+}
+
+fn power_in(t: f32, n: i32) -> f32 { t.powi(n) }
+fn power_out(t: f32, n: i32) -> f32 { 1.0 - (1.0 - t).powi(n) }
+fn power_in_out(t: f32, n: i32) -> f32
+{
+  if t < 0.5 { 2f32.powi(n - 1) * t.powi(n) } else { 1.0 - (2.0 - 2.0 * t).powi(n) / 2.0 }
+}
+
+fn bounce_out(t: f32) -> f32
+{
+  const N: f32 = 7.5625;
+  const D: f32 = 2.75;
+  if t < 1.0 / D { N * t * t }
+  else if t < 2.0 / D { let t = t - 1.5 / D; N * t * t + 0.75 }
+  else if t < 2.5 / D { let t = t - 2.25 / D; N * t * t + 0.9375 }
+  else { let t = t - 2.625 / D; N * t * t + 0.984375 }
+}
+
+impl Easing
+{
+  // Eased progress of t in [0, 1]: 0 at 0 and 1 at 1, possibly out of [0, 1] in between (back, elastic)
+  pub fn apply(self, t: f32) -> f32
+  {
+    const BACK: f32 = 1.70158;
+    const BACK_IN_OUT: f32 = BACK * 1.525;
+    const ELASTIC: f32 = 2.0 * PI / 3.0;
+    const ELASTIC_IN_OUT: f32 = 2.0 * PI / 4.5;
+
+    let t = t.clamp(0.0, 1.0);
+    if t == 0.0 || t == 1.0 {
+      return t;
+    }
+    match self
+    {
+      Easing::Linear => t,
+      Easing::QuadIn => power_in(t, 2),
+      Easing::QuadOut => power_out(t, 2),
+      Easing::QuadInOut => power_in_out(t, 2),
+      Easing::CubicIn => power_in(t, 3),
+      Easing::CubicOut => power_out(t, 3),
+      Easing::CubicInOut => power_in_out(t, 3),
+      Easing::QuartIn => power_in(t, 4),
+      Easing::QuartOut => power_out(t, 4),
+      Easing::QuartInOut => power_in_out(t, 4),
+      Easing::QuintIn => power_in(t, 5),
+      Easing::QuintOut => power_out(t, 5),
+      Easing::QuintInOut => power_in_out(t, 5),
+      Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+      Easing::SineOut => (t * PI / 2.0).sin(),
+      Easing::SineInOut => (1.0 - (t * PI).cos()) / 2.0,
+      Easing::ExpoIn => 2f32.powf(10.0 * t - 10.0),
+      Easing::ExpoOut => 1.0 - 2f32.powf(-10.0 * t),
+      Easing::ExpoInOut =>
+        if t < 0.5 { 2f32.powf(20.0 * t - 10.0) / 2.0 } else { (2.0 - 2f32.powf(10.0 - 20.0 * t)) / 2.0 },
+      Easing::CircIn => 1.0 - (1.0 - t * t).sqrt(),
+      Easing::CircOut => (1.0 - (t - 1.0).powi(2)).sqrt(),
+      Easing::CircInOut =>
+        if t < 0.5 { (1.0 - (1.0 - (2.0 * t).powi(2)).sqrt()) / 2.0 } else { ((1.0 - (2.0 - 2.0 * t).powi(2)).sqrt() + 1.0) / 2.0 },
+      Easing::BackIn => (BACK + 1.0) * t.powi(3) - BACK * t * t,
+      Easing::BackOut => 1.0 + (BACK + 1.0) * (t - 1.0).powi(3) + BACK * (t - 1.0).powi(2),
+      Easing::BackInOut =>
+        if t < 0.5 {
+          (2.0 * t).powi(2) * ((BACK_IN_OUT + 1.0) * 2.0 * t - BACK_IN_OUT) / 2.0
+        }
+        else {
+          ((2.0 * t - 2.0).powi(2) * ((BACK_IN_OUT + 1.0) * (2.0 * t - 2.0) + BACK_IN_OUT) + 2.0) / 2.0
+        },
+      Easing::ElasticIn => -(2f32.powf(10.0 * t - 10.0)) * ((10.0 * t - 10.75) * ELASTIC).sin(),
+      Easing::ElasticOut => 2f32.powf(-10.0 * t) * ((10.0 * t - 0.75) * ELASTIC).sin() + 1.0,
+      Easing::ElasticInOut =>
+        if t < 0.5 {
+          -(2f32.powf(20.0 * t - 10.0) * ((20.0 * t - 11.125) * ELASTIC_IN_OUT).sin()) / 2.0
+        }
+        else {
+          2f32.powf(10.0 - 20.0 * t) * ((20.0 * t - 11.125) * ELASTIC_IN_OUT).sin() / 2.0 + 1.0
+        },
+      Easing::BounceIn => 1.0 - bounce_out(1.0 - t),
+      Easing::BounceOut => bounce_out(t),
+      Easing::BounceInOut =>
+        if t < 0.5 { (1.0 - bounce_out(1.0 - 2.0 * t)) / 2.0 } else { (1.0 + bounce_out(2.0 * t - 1.0)) / 2.0 }
+    }
+  }
+}
+
+// What can be tweened. t may be a bit out of [0, 1] with the back and elastic easings.
+pub trait Lerp: Copy
+{
+  fn lerp(&self, to: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32
+{
+  fn lerp(&self, to: &f32, t: f32) -> f32 { self + (to - self) * t }
+}
+
+impl Lerp for f64
+{
+  fn lerp(&self, to: &f64, t: f32) -> f64 { self + (to - self) * t as f64 }
+}
+
+impl Lerp for i32
+{
+  fn lerp(&self, to: &i32, t: f32) -> i32 { (*self as f32).lerp(&(*to as f32), t).round() as i32 }
+}
+
+impl Lerp for u8
+{
+  fn lerp(&self, to: &u8, t: f32) -> u8 { (*self as f32).lerp(&(*to as f32), t).round().clamp(0.0, 255.0) as u8 }
+}
+
+impl<A: Lerp, B: Lerp> Lerp for (A, B)
+{
+  fn lerp(&self, to: &(A, B), t: f32) -> (A, B) { (self.0.lerp(&to.0, t), self.1.lerp(&to.1, t)) }
+}
+
+impl Lerp for Point
+{
+  fn lerp(&self, to: &Point, t: f32) -> Point { Point::new(self.x().lerp(&to.x(), t), self.y().lerp(&to.y(), t)) }
+}
+
+impl Lerp for Color
+{
+  fn lerp(&self, to: &Color, t: f32) -> Color
+  {
+    Color::RGBA(self.r.lerp(&to.r, t), self.g.lerp(&to.g, t), self.b.lerp(&to.b, t), self.a.lerp(&to.a, t))
+  }
+}
+
+pub type TweenTarget<T> = Rc<Cell<T>>;
+
+pub fn tween_target<T: Lerp>(value: T) -> TweenTarget<T>
+{
+  Rc::new(Cell::new(value))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat
+{
+  Times(u32), // plays again that many times
+  Forever
+}
+
+impl Repeat
+{
+  // After `plays` plays, is there another one?
+  fn again(self, plays: u32) -> bool
+  {
+    match self
+    {
+      Repeat::Times(times) => plays <= times,
+      Repeat::Forever => true
+    }
+  }
+}
+
+// Tweens, sequences, parallel groups, waits and calls
+pub trait Animate
+{
+  // Moves on by dt, and gives back the time left over if it ended during it
+  fn advance(&mut self, dt: Duration) -> Duration;
+  fn is_finished(&self) -> bool;
+  // Back to the start, e.g. to play again
+  fn reset(&mut self);
+
+  fn update(&mut self, dt: Duration) -> bool
+  {
+    self.advance(dt);
+    self.is_finished()
+  }
+}
+
+type Callback = Box<dyn FnMut()>;
+
+pub struct Tween<T: Lerp>
+{
+  target: TweenTarget<T>,
+  from: Option<T>, // None: the target's value when the tween starts (after the delay)
+  start: Option<T>,
+  to: T,
+  duration: Duration,
+  easing: Easing,
+  delay: Duration, // before the first play only
+  delay_left: Duration,
+  repeat: Repeat,
+  yoyo: bool, // every other play goes back
+  elapsed: Duration, // in the current play
+  plays: u32,
+  finished: bool,
+  on_complete: Option<Callback>
+}
+
+impl<T: Lerp> Tween<T>
+{
+  pub fn new(target: &TweenTarget<T>, to: T, duration: Duration) -> Tween<T>
+  {
+    Tween {
+      target: Rc::clone(target),
+      from: None,
+      start: None,
+      to,
+      duration,
+      easing: Easing::Linear,
+      delay: Duration::ZERO,
+      delay_left: Duration::ZERO,
+      repeat: Repeat::Times(0),
+      yoyo: false,
+      elapsed: Duration::ZERO,
+      plays: 0,
+      finished: false,
+      on_complete: None
+    }
+  }
+
+  pub fn from(mut self, from: T) -> Tween<T>
+  {
+    self.from = Some(from);
+    self
+  }
+
+  pub fn with_easing(mut self, easing: Easing) -> Tween<T>
+  {
+    self.easing = easing;
+    self
+  }
+
+  pub fn with_delay(mut self, delay: Duration) -> Tween<T>
+  {
+    self.delay = delay;
+    self.delay_left = self.delay;
+    self
+  }
+
+  pub fn with_repeat(mut self, repeat: Repeat) -> Tween<T>
+  {
+    self.repeat = repeat;
+    self
+  }
+
+  // Back and forth: with Repeat::Times(1), to the end and back again
+  pub fn with_yoyo(mut self, yoyo: bool) -> Tween<T>
+  {
+    self.yoyo = yoyo;
+    self
+  }
+
+  pub fn on_complete(mut self, callback: impl FnMut() + 'static) -> Tween<T>
+  {
+    self.on_complete = Some(Box::new(callback));
+    self
+  }
+
+  fn set_progress(&mut self, progress: f32)
+  {
+    let start = *self.start.get_or_insert_with(|| self.from.unwrap_or_else(|| self.target.get()));
+    let backwards = self.yoyo && self.plays % 2 == 1;
+    let progress = if backwards { 1.0 - progress } else { progress };
+    self.target.set(start.lerp(&self.to, self.easing.apply(progress)));
+  }
+}
+
+impl<T: Lerp> Animate for Tween<T>
+{
+  fn advance(&mut self, dt: Duration) -> Duration
+  {
+    if self.finished {
+      return dt;
+    }
+    let waited = dt.min(self.delay_left);
+    self.delay_left -= waited;
+    let mut dt = dt - waited;
+    if !self.delay_left.is_zero() {
+      return Duration::ZERO;
+    }
+
+    loop {
+      let left = self.duration - self.elapsed;
+      if dt < left {
+        self.elapsed += dt;
+        self.set_progress(self.elapsed.as_secs_f32() / self.duration.as_secs_f32());
+        return Duration::ZERO;
+      }
+      dt -= left;
+      self.set_progress(1.0);
+      self.plays += 1;
+      if !self.repeat.again(self.plays) {
+        self.finished = true;
+        if let Some(callback) = &mut self.on_complete {
+          callback();
+        }
+        return dt;
+      }
+      self.elapsed = Duration::ZERO;
+      if self.duration.is_zero() {
+        return Duration::ZERO; // (forever in no time)
+      }
+    }
+  }
+
+  fn is_finished(&self) -> bool { self.finished }
+
+  fn reset(&mut self)
+  {
+    self.delay_left = self.delay;
+    self.elapsed = Duration::ZERO;
+    self.plays = 0;
+    self.start = None;
+    self.finished = false;
+  }
+}
+
+// A pause, in a sequence
+pub struct Wait
+{
+  duration: Duration,
+  elapsed: Duration
+}
+
+impl Wait
+{
+  pub fn new(duration: Duration) -> Wait
+  {
+    Wait { duration, elapsed: Duration::ZERO }
+  }
+}
+
+impl Animate for Wait
+{
+  fn advance(&mut self, dt: Duration) -> Duration
+  {
+    let used = dt.min(self.duration - self.elapsed);
+    self.elapsed += used;
+    dt - used
+  }
+
+  fn is_finished(&self) -> bool { self.elapsed >= self.duration }
+  fn reset(&mut self) { self.elapsed = Duration::ZERO; }
+}
+
+// A callback, in a sequence (called again after a reset)
+pub struct Call
+{
+  callback: Callback,
+  called: bool
+}
+
+impl Call
+{
+  pub fn new(callback: impl FnMut() + 'static) -> Call
+  {
+    Call { callback: Box::new(callback), called: false }
+  }
+}
+
+impl Animate for Call
+{
+  fn advance(&mut self, dt: Duration) -> Duration
+  {
+    if !self.called {
+      self.called = true;
+      (self.callback)();
+    }
+    dt
+  }
+
+  fn is_finished(&self) -> bool { self.called }
+  fn reset(&mut self) { self.called = false; }
+}
+
+// One step after the other
+#[derive(Default)]
+pub struct Sequence
+{
+  steps: Vec<Box<dyn Animate>>,
+  current: usize,
+  repeat: Option<Repeat>, // (None <=> Times(0), for Default)
+  plays: u32,
+  on_complete: Option<Callback>
+}
+
+impl Sequence
+{
+  pub fn new() -> Sequence
+  {
+    Sequence::default()
+  }
+
+  pub fn then(mut self, step: impl Animate + 'static) -> Sequence
+  {
+    self.steps.push(Box::new(step));
+    self
+  }
+
+  pub fn wait(self, duration: Duration) -> Sequence
+  {
+    self.then(Wait::new(duration))
+  }
+
+  pub fn call(self, callback: impl FnMut() + 'static) -> Sequence
+  {
+    self.then(Call::new(callback))
+  }
+
+  // All the steps again, from their start
+  pub fn with_repeat(mut self, repeat: Repeat) -> Sequence
+  {
+    self.repeat = Some(repeat);
+    self
+  }
+
+  pub fn on_complete(mut self, callback: impl FnMut() + 'static) -> Sequence
+  {
+    self.on_complete = Some(Box::new(callback));
+    self
+  }
+}
+
+impl Animate for Sequence
+{
+  fn advance(&mut self, dt: Duration) -> Duration
+  {
+    let mut dt = dt;
+    loop {
+      if self.is_finished() {
+        return dt;
+      }
+      let before = dt;
+      while let Some(step) = self.steps.get_mut(self.current) {
+        dt = step.advance(dt);
+        if !step.is_finished() {
+          return Duration::ZERO;
+        }
+        self.current += 1;
+      }
+      self.plays += 1;
+      if self.repeat.unwrap_or(Repeat::Times(0)).again(self.plays) {
+        self.steps.iter_mut().for_each(|step| step.reset());
+        self.current = 0;
+        if dt >= before && self.repeat == Some(Repeat::Forever) {
+          return Duration::ZERO; // (forever in no time)
+        }
+      }
+      else if let Some(callback) = &mut self.on_complete {
+        callback();
+      }
+    }
+  }
+
+  fn is_finished(&self) -> bool
+  {
+    self.current >= self.steps.len() && !self.repeat.unwrap_or(Repeat::Times(0)).again(self.plays)
+  }
+
+  fn reset(&mut self)
+  {
+    self.steps.iter_mut().for_each(|step| step.reset());
+    self.current = 0;
+    self.plays = 0;
+  }
+}
+
+// All at once, finished when the longest is
+#[derive(Default)]
+pub struct Parallel
+{
+  animations: Vec<Box<dyn Animate>>,
+  finished: bool,
+  on_complete: Option<Callback>
+}
+
+impl Parallel
+{
+  pub fn new() -> Parallel
+  {
+    Parallel::default()
+  }
+
+  pub fn with(mut self, animation: impl Animate + 'static) -> Parallel
+  {
+    self.animations.push(Box::new(animation));
+    self
+  }
+
+  pub fn on_complete(mut self, callback: impl FnMut() + 'static) -> Parallel
+  {
+    self.on_complete = Some(Box::new(callback));
+    self
+  }
+}
+
+impl Animate for Parallel
+{
+  fn advance(&mut self, dt: Duration) -> Duration
+  {
+    if self.finished {
+      return dt;
+    }
+    let mut left_over = dt;
+    for animation in self.animations.iter_mut().filter(|animation| !animation.is_finished()) {
+      left_over = left_over.min(animation.advance(dt));
+    }
+    self.finished = self.animations.iter().all(|animation| animation.is_finished());
+    if !self.finished {
+      return Duration::ZERO;
+    }
+    if let Some(callback) = &mut self.on_complete {
+      callback();
+    }
+    left_over
+  }
+
+  fn is_finished(&self) -> bool { self.finished }
+
+  fn reset(&mut self)
+  {
+    self.animations.iter_mut().for_each(|animation| animation.reset());
+    self.finished = false;
+  }
+}
+
+// Runs the animations of a scene, dropping them once finished
+#[derive(Default)]
+pub struct Tweener
+{
+  animations: Vec<Box<dyn Animate>>
+}
+
+impl Tweener
+{
+  pub fn new() -> Tweener
+  {
+    Tweener::default()
+  }
+
+  pub fn add(&mut self, animation: impl Animate + 'static)
+  {
+    self.animations.push(Box::new(animation));
+  }
+
+  pub fn update(&mut self, dt: Duration)
+  {
+    self.animations.retain_mut(|animation| {
+      animation.advance(dt);
+      !animation.is_finished()
+    });
+  }
+
+  pub fn len(&self) -> usize { self.animations.len() }
+  pub fn is_empty(&self) -> bool { self.animations.is_empty() }
+  pub fn clear(&mut self) { self.animations.clear(); }
+}
+
+// A sprite with tweenable properties: tween its targets, then render it
+pub struct TweenedSprite<'a, N = SpriteName>
+{
+  sprite: Sprite<'a, N>,
+  pub position: TweenTarget<(f32, f32)>, // top left corner
+  pub alpha: TweenTarget<u8>,
+  pub scale: TweenTarget<(f32, f32)>,
+  pub angle: TweenTarget<f64>,   // degrees, clockwise, around the pivot or the middle
+  pub color: TweenTarget<Color>  // color modulation
+}
+
+impl<'a, N> TweenedSprite<'a, N>
+{
+  // The style of the sprite gives the starting alpha and color
+  pub fn new(sprite: Sprite<'a, N>, x: f32, y: f32) -> TweenedSprite<'a, N>
+  {
+    let style = sprite.style();
+    TweenedSprite {
+      sprite,
+      position: tween_target((x, y)),
+      alpha: tween_target(style.alpha),
+      scale: tween_target((1.0, 1.0)),
+      angle: tween_target(0.0),
+      color: tween_target(style.tint)
+    }
+  }
+
+  pub fn sprite(&self) -> &Sprite<'a, N> { &self.sprite }
+  pub fn sprite_mut(&mut self) -> &mut Sprite<'a, N> { &mut self.sprite }
+
+  pub fn render(&mut self, canvas: &mut WindowCanvas)
+  {
+    self.sprite.set_color(self.color.get());
+    self.sprite.set_alpha(self.alpha.get());
+    let (x, y) = self.position.get();
+    let params = RenderEx { angle: self.angle.get(), scale: self.scale.get(), ..RenderEx::default() };
+    self.sprite.render_ex(canvas, x.round() as i32, y.round() as i32, &params);
+  }
+}
+
+//////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use std::cell::RefCell;
+
+  const ALL_EASINGS: [Easing; 31] = [
+    Easing::Linear,
+    Easing::QuadIn, Easing::QuadOut, Easing::QuadInOut,
+    Easing::CubicIn, Easing::CubicOut, Easing::CubicInOut,
+    Easing::QuartIn, Easing::QuartOut, Easing::QuartInOut,
+    Easing::QuintIn, Easing::QuintOut, Easing::QuintInOut,
+    Easing::SineIn, Easing::SineOut, Easing::SineInOut,
+    Easing::ExpoIn, Easing::ExpoOut, Easing::ExpoInOut,
+    Easing::CircIn, Easing::CircOut, Easing::CircInOut,
+    Easing::BackIn, Easing::BackOut, Easing::BackInOut,
+    Easing::ElasticIn, Easing::ElasticOut, Easing::ElasticInOut,
+    Easing::BounceIn, Easing::BounceOut, Easing::BounceInOut
+  ];
+
+  fn ms(ms: u64) -> Duration { Duration::from_millis(ms) }
+
+  #[test]
+  fn test_easings() {
+    for easing in ALL_EASINGS {
+      assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+      assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+      // continuous: no jump around the middle of the in-outs
+      assert!((easing.apply(0.5 - 1e-6) - easing.apply(0.5 + 1e-6)).abs() < 0.01, "{:?}", easing);
+    }
+    assert_eq!(Easing::QuadIn.apply(0.5), 0.25);
+    assert_eq!(Easing::CubicOut.apply(0.5), 0.875);
+    assert!(Easing::BackIn.apply(0.2) < 0.0);
+  }
+
+  #[test]
+  fn test_tween_delay_repeat_and_yoyo() {
+    let x = tween_target(0.0f32);
+    let done = Rc::new(Cell::new(false));
+    let done_flag = Rc::clone(&done);
+    let mut tween = Tween::new(&x, 100.0, ms(1000)).with_delay(ms(500))
+      .with_repeat(Repeat::Times(1)).with_yoyo(true)
+      .on_complete(move || done_flag.set(true));
+    tween.update(ms(750));
+    assert_eq!(x.get(), 25.0);
+    tween.update(ms(1000)); // on the way back
+    assert_eq!(x.get(), 75.0);
+    assert!(!done.get());
+    assert_eq!(tween.advance(ms(1000)), ms(250)); // ended a quarter of a second ago
+    assert_eq!(x.get(), 0.0);
+    assert!(done.get() && tween.is_finished());
+  }
+
+  #[test]
+  fn test_sequence_and_parallel() {
+    let position = tween_target((0.0f32, 0.0f32));
+    let alpha = tween_target(0u8);
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let call_log = Rc::clone(&log);
+    let mut sequence = Sequence::new()
+      .then(Parallel::new()
+        .with(Tween::new(&position, (100.0, 0.0), ms(1000)))
+        .with(Tween::new(&alpha, 255, ms(500))))
+      .call(move || call_log.borrow_mut().push("moved"))
+      .then(Tween::new(&position, (100.0, 50.0), ms(500))); // from where the first one ended
+    sequence.update(ms(500));
+    assert_eq!((position.get(), alpha.get()), ((50.0, 0.0), 255));
+    sequence.update(ms(750)); // the parallel group ended 0.25 s ago
+    assert_eq!(position.get(), (100.0, 25.0));
+    assert_eq!(*log.borrow(), vec!["moved"]);
+    sequence.update(ms(250));
+    assert!(sequence.is_finished());
+  }
+
+  #[test]
+  fn test_repeated_sequence() {
+    let x = tween_target(0i32);
+    let mut tweener = Tweener::new();
+    tweener.add(Sequence::new()
+      .then(Tween::new(&x, 10, ms(100)).from(0))
+      .wait(ms(100))
+      .with_repeat(Repeat::Times(2)));
+    tweener.update(ms(250));
+    assert_eq!(x.get(), 5);
+    tweener.update(ms(300));
+    assert_eq!((x.get(), tweener.len()), (10, 1));
+    tweener.update(ms(50));
+    assert!(tweener.is_empty());
+  }
+
+  #[test]
+  fn test_small_steps_end_on_time() {
+    // 10 ms at a time: 0.01 summed in f32 misses 0.1, Durations don't
+    let mut wait = Wait::new(ms(100));
+    for _ in 0..9 {
+      wait.update(ms(10));
+    }
+    assert!(!wait.is_finished());
+    assert_eq!(wait.advance(ms(10)), Duration::ZERO);
+    assert!(wait.is_finished());
+
+    // 3 plays of 200 ms, the last frame ending right on the last one
+    let x = tween_target(0i32);
+    let mut sequence = Sequence::new()
+      .then(Tween::new(&x, 10, ms(100)).from(0))
+      .wait(ms(100))
+      .with_repeat(Repeat::Times(2));
+    for _ in 0..59 {
+      sequence.update(ms(10));
+    }
+    assert!(!sequence.is_finished());
+    assert_eq!(sequence.advance(ms(10)), Duration::ZERO);
+    assert!(sequence.is_finished());
+  }
+}